@@ -0,0 +1,43 @@
+//! Program words, read little-endian from the input, either fail to decode
+//! or decode into an instruction that encodes back into them and lists and
+//! counts its cycles on every core.
+
+#![no_main]
+
+use clap::ValueEnum;
+use hex::{
+    bits, cycles::cycles, device::Core, image::Image, instruction::Instruction, record::Record,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|bytes: &[u8]| {
+    let words: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    for (i, &word) in words.iter().enumerate() {
+        let Ok(instruction) = Instruction::decode(word, words.get(i + 1).copied()) else {
+            continue;
+        };
+        let length = instruction.length() as usize;
+        assert_eq!(instruction.encode(), words[i..i + length]);
+        for overloads in [false, true] {
+            let _ = instruction
+                .display(i as u32 * 2, overloads, None)
+                .to_string();
+        }
+        for &core in Core::value_variants() {
+            cycles(&instruction, core, 1);
+        }
+        let record = Record {
+            address: 0,
+            index: hex::record::Index::Data,
+            data: words[i..i + length]
+                .iter()
+                .map(|word| ((word >> 8) as u8, *word as u8))
+                .collect(),
+            size: length * 2,
+        };
+        assert!(bits::breakdown(&Image::from_records(&[record]), 0, true).is_some());
+    }
+});
@@ -0,0 +1,86 @@
+//! Intel HEX text is either rejected or loaded, analyzed and listed. The
+//! image holds the last byte each address is given and erased flash for the
+//! other half of a word, and writes back into records that load the same
+//! image.
+
+#![no_main]
+
+use hex::{
+    callgraph::CallGraph,
+    data::Data,
+    image::{self, Image},
+    listing,
+    record::{self, Index, Record},
+};
+use libfuzzer_sys::fuzz_target;
+use std::collections::BTreeMap;
+
+fuzz_target!(|bytes: &[u8]| {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return;
+    };
+    let records: Vec<Record> = text
+        .split_whitespace()
+        .filter_map(|record| Record::from_str(record).ok())
+        .collect();
+    for record in &records {
+        let _ = record.to_string();
+    }
+    let image = Image::from_records(&records);
+    let mut loaded = BTreeMap::new();
+    for (record, base) in records.iter().zip(image::bases(&records)) {
+        if record.index == Index::Data {
+            for (i, byte) in record.bytes().into_iter().enumerate() {
+                match base.checked_add(i as u32) {
+                    Some(address) if address < 0xffff_0000 => {
+                        loaded.insert(address, byte);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    for segment in image.segments() {
+        for address in segment {
+            let expected = match loaded.get(&address) {
+                Some(&byte) => byte,
+                None => {
+                    assert!(loaded.contains_key(&(address ^ 1)));
+                    0xff
+                }
+            };
+            assert_eq!(image.byte(address), Some(expected));
+        }
+    }
+    for &address in loaded.keys() {
+        assert!(image.byte(address).is_some());
+    }
+    let graph = CallGraph::new(&image);
+    let data = Data::detect(&image, &graph);
+    let segments = image.segments();
+    for segment in &segments {
+        listing::sweep(&image, &data, segment.clone());
+    }
+    let flash: Vec<(u32, Vec<u8>)> = segments
+        .iter()
+        .map(|segment| {
+            let bytes = segment.clone().filter_map(|address| image.byte(address));
+            (segment.start, bytes.collect())
+        })
+        .collect();
+    let flash: Vec<(u32, &[u8])> = flash
+        .iter()
+        .map(|(start, bytes)| (*start, &bytes[..]))
+        .collect();
+    let written: Vec<Record> = record::intel_hex(&flash)
+        .lines()
+        .map(|record| Record::from_str(record).unwrap())
+        .collect();
+    let reloaded = Image::from_records(&written);
+    assert_eq!(reloaded.segments(), segments);
+    for segment in segments {
+        for address in segment.step_by(2) {
+            assert_eq!(reloaded.word(address), image.word(address));
+        }
+    }
+});
@@ -0,0 +1,100 @@
+use crate::symbols::{self, Symbols};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+/// Layout of the sidecar file. Addresses are zero padded hexadecimal keys,
+/// so the entries stay sorted, and data addresses start at 0x800000 as in
+/// avr-gcc.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct Sidecar {
+    labels: BTreeMap<String, String>,
+    comments: BTreeMap<String, String>,
+}
+
+/// Names and comments given to addresses while reading a listing, kept
+/// across runs in a JSON file.
+#[derive(Debug, Default)]
+pub struct Annotations {
+    pub labels: BTreeMap<u32, String>,
+    pub comments: BTreeMap<u32, String>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn parse(entries: BTreeMap<String, String>) -> io::Result<BTreeMap<u32, String>> {
+    entries
+        .into_iter()
+        .map(|(key, value)| {
+            key.strip_prefix("0x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .map(|address| (address, value))
+                .ok_or_else(|| invalid(format!("{}: not a hexadecimal address", key)))
+        })
+        .collect()
+}
+
+fn format(entries: &BTreeMap<u32, String>) -> BTreeMap<String, String> {
+    entries
+        .iter()
+        .map(|(address, value)| (format!("0x{:06x}", address), value.clone()))
+        .collect()
+}
+
+impl Annotations {
+    /// Reads the file at `path`, a missing file has no annotations yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => return Err(error),
+        };
+        let sidecar: Sidecar =
+            serde_json::from_str(&text).map_err(|error| invalid(error.to_string()))?;
+        let labels = parse(sidecar.labels)?;
+        if let Some(name) = labels.values().find(|name| !symbols::valid(name)) {
+            return Err(invalid(format!("{}: not a valid label", name)));
+        }
+        Ok(Annotations {
+            labels,
+            comments: parse(sidecar.comments)?,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let sidecar = Sidecar {
+            labels: format(&self.labels),
+            comments: format(&self.comments),
+        };
+        let text = serde_json::to_string_pretty(&sidecar).map_err(io::Error::other)?;
+        fs::write(path, text + "\n")
+    }
+
+    /// Address of the label `name`.
+    pub fn find(&self, name: &str) -> Option<u32> {
+        self.labels
+            .iter()
+            .find(|(_, label)| *label == name)
+            .map(|(&address, _)| address)
+    }
+
+    /// Adds the labels and comments to `symbols`, replacing the names
+    /// imported for the same addresses.
+    pub fn apply(&self, symbols: &mut Symbols) {
+        for (&address, name) in &self.labels {
+            symbols.define(address, name);
+        }
+        symbols.comments.extend(
+            self.comments
+                .iter()
+                .map(|(&address, comment)| (address, comment.clone())),
+        );
+    }
+}
@@ -0,0 +1,547 @@
+use crate::{
+    device::Core,
+    instruction::{Instruction, Mode, Pointer},
+    opcodes::{self, Kind, Opcode, Rewrite, Token, Values},
+    symbols,
+};
+use clap::ValueEnum;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+/// A mistake in the source, on its 1-based line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Operand of an instruction as written in the source, with its expressions
+/// evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Register(u8),
+    /// A pair `r25:24` or `r25:r24`, given by its low register.
+    Pair(u8),
+    Value(i64),
+    /// `.+N` or `.-N`, a byte offset from the next instruction.
+    Relative(i64),
+    Indirect(Pointer, Mode),
+    Displacement(Pointer, i64),
+}
+
+/// What a line assembles into.
+enum Content<'a> {
+    Instruction(String, Vec<&'a str>),
+    Bytes(Vec<&'a str>),
+    Words(Vec<&'a str>),
+    Text(Vec<u8>),
+}
+
+struct Statement<'a> {
+    line: usize,
+    address: u32,
+    content: Content<'a>,
+}
+
+/// Assembles the syntax of the listing and of `--output asm` into segments
+/// of flash, each a start address and its bytes.
+///
+/// Lines hold an optional `label:` or `0xaddress:`, then an instruction or one of the
+/// directives `.org`, `.byte`, `.word`, `.ascii`, `.asciz` and `.set`, and
+/// a `;` comment. Operands take the aliases the listing prints, pairs both
+/// as `r24` and `r25:24`, and expressions of numbers and symbols joined by
+/// `+` and `-`, optionally in `lo8()`, `hi8()` or `pm()`. Instructions
+/// are those of [`opcodes::OPCODES`] available on `core`, and each encoding
+/// is checked by decoding it again.
+pub fn assemble(source: &str, core: Core) -> Result<Vec<(u32, Vec<u8>)>, AssembleError> {
+    let mut symbols: HashMap<String, i64> = HashMap::new();
+    let mut statements = vec![];
+    // One past the last byte of flash is a valid address to stop at.
+    let mut address = 0u64;
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| AssembleError { line, message };
+        let mut text = strip_comment(text).trim();
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            // Lines of the listing start with their address.
+            match label
+                .strip_prefix("0x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+            {
+                Some(Ok(start)) => address = start as u64,
+                _ if symbols::valid(label) => {
+                    define(&mut symbols, label, address as i64).map_err(error)?
+                }
+                _ => break,
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (head, rest) = match text.split_once(char::is_whitespace) {
+            Some((head, rest)) => (head, rest.trim()),
+            None => (text, ""),
+        };
+        let head = head.to_ascii_lowercase();
+        let arguments = split(rest);
+        let content = match head.as_str() {
+            ".text" | ".global" | ".globl" => continue,
+            ".org" => {
+                let value = evaluate(rest, &symbols).map_err(error)?;
+                address = u32::try_from(value)
+                    .map_err(|_| error(format!("{}: not an address", rest)))?
+                    as u64;
+                continue;
+            }
+            ".set" | ".equ" => match arguments[..] {
+                [name, value] if symbols::valid(name) => {
+                    let value = evaluate(value, &symbols).map_err(error)?;
+                    define(&mut symbols, name, value).map_err(error)?;
+                    continue;
+                }
+                _ => return Err(error(format!("{} needs a name and a value", head))),
+            },
+            ".byte" => Content::Bytes(arguments),
+            ".word" => Content::Words(arguments),
+            ".ascii" | ".asciz" => {
+                let mut bytes = unescape(rest).map_err(error)?;
+                if head == ".asciz" {
+                    bytes.push(0);
+                }
+                Content::Text(bytes)
+            }
+            directive if directive.starts_with('.') => {
+                return Err(error(format!("{}: unknown directive", directive)))
+            }
+            _ => {
+                if !address.is_multiple_of(2) {
+                    return Err(error(format!(
+                        "{}: instructions must start at an even address, not {:#x}",
+                        head, address
+                    )));
+                }
+                Content::Instruction(head, arguments)
+            }
+        };
+        let size = match &content {
+            Content::Instruction(mnemonic, _) => {
+                let mnemonic = resolve(mnemonic, &[]).0;
+                opcodes::named(mnemonic).next().map_or(1, Opcode::words) * 2
+            }
+            Content::Bytes(values) => values.len() as u32,
+            Content::Words(values) => values.len() as u32 * 2,
+            Content::Text(bytes) => bytes.len() as u32,
+        };
+        if address + size as u64 > 1 << 32 {
+            return Err(error(format!("{}: address out of range", text)));
+        }
+        statements.push(Statement {
+            line,
+            address: address as u32,
+            content,
+        });
+        address += size as u64;
+    }
+    let mut flash: BTreeMap<u32, u8> = BTreeMap::new();
+    for statement in statements {
+        let error = |message: String| AssembleError {
+            line: statement.line,
+            message,
+        };
+        let value = |text: &str| evaluate(text, &symbols).map_err(error);
+        let bytes = match statement.content {
+            Content::Instruction(mnemonic, arguments) => {
+                let operands = arguments
+                    .iter()
+                    .map(|text| operand(text, &symbols))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                build(&mnemonic, &operands, statement.address, core)
+                    .map_err(error)?
+                    .and_then(verify)
+                    .ok_or_else(|| {
+                        error(format!(
+                            "{} {}: operand out of range",
+                            mnemonic,
+                            arguments.join(", ")
+                        ))
+                    })?
+                    .iter()
+                    .flat_map(|word| word.to_le_bytes())
+                    .collect()
+            }
+            Content::Bytes(values) => values
+                .iter()
+                .map(|text| match value(text)? {
+                    value @ -128..=255 => Ok(value as u8),
+                    _ => Err(error(format!("{}: does not fit in a byte", text))),
+                })
+                .collect::<Result<Vec<u8>, _>>()?,
+            Content::Words(values) => values
+                .iter()
+                .map(|text| match value(text)? {
+                    value @ -32768..=65535 => Ok(value as u16),
+                    _ => Err(error(format!("{}: does not fit in a word", text))),
+                })
+                .collect::<Result<Vec<u16>, _>>()?
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect(),
+            Content::Text(bytes) => bytes,
+        };
+        for (i, byte) in bytes.into_iter().enumerate() {
+            let address = statement.address + i as u32;
+            if flash.insert(address, byte).is_some() {
+                return Err(error(format!("{:#x} is assembled twice", address)));
+            }
+        }
+    }
+    let mut segments: Vec<(u32, Vec<u8>)> = vec![];
+    for (address, byte) in flash {
+        match segments.last_mut() {
+            Some((start, bytes)) if *start + bytes.len() as u32 == address => bytes.push(byte),
+            _ => segments.push((address, vec![byte])),
+        }
+    }
+    Ok(segments)
+}
+
+fn define(symbols: &mut HashMap<String, i64>, name: &str, value: i64) -> Result<(), String> {
+    match symbols.insert(name.to_string(), value) {
+        Some(_) => Err(format!("{}: defined twice", name)),
+        None => Ok(()),
+    }
+}
+
+/// The words, if decoding them gives an instruction that encodes back into
+/// them.
+fn verify(words: Vec<u16>) -> Option<Vec<u16>> {
+    let decoded = Instruction::decode(words[0], words.get(1).copied()).ok()?;
+    (decoded.encode() == words).then_some(words)
+}
+
+/// The line without its comment, a `;` outside of quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = quoted,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Operands separated by commas outside of parentheses.
+fn split(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        return vec![];
+    }
+    let mut operands = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push(text[start..].trim());
+    operands
+}
+
+/// Bytes of a string literal, with the escapes of [`crate::data::escape`].
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or_else(|| format!("{}: not a string", text))?;
+    let mut bytes = vec![];
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buffer).bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some(digit @ '0'..='7') => {
+                let mut value = digit.to_digit(8).unwrap_or(0);
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(c) => bytes.push(c as u8),
+            None => return Err(format!("{}: unterminated escape", text)),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Parses a number in hexadecimal with a `0x` prefix, binary with `0b`, or
+/// decimal.
+fn number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    match (lower.strip_prefix("0x"), lower.strip_prefix("0b")) {
+        (Some(hex), _) => i64::from_str_radix(hex, 16).ok(),
+        (_, Some(binary)) => i64::from_str_radix(binary, 2).ok(),
+        _ => lower.parse().ok(),
+    }
+}
+
+/// Value of a sum of numbers and symbols, each term optionally wrapped in
+/// `lo8()`, `hi8()` or `pm()`.
+fn evaluate(text: &str, symbols: &HashMap<String, i64>) -> Result<i64, String> {
+    let text = text.trim();
+    let mut depth = 0;
+    // The last binary operator at the top level splits the sum, which keeps
+    // it left-associative.
+    let mut split = None;
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        let binary = previous.is_some_and(|previous| !matches!(previous, '+' | '-' | '('));
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '+' | '-' if depth == 0 && binary => split = Some(i),
+            _ => {}
+        }
+        if !c.is_whitespace() {
+            previous = Some(c);
+        }
+    }
+    if let Some(i) = split {
+        let left = evaluate(&text[..i], symbols)?;
+        let right = evaluate(&text[i + 1..], symbols)?;
+        let value = match &text[i..i + 1] {
+            "+" => left.checked_add(right),
+            _ => left.checked_sub(right),
+        };
+        return value.ok_or_else(|| format!("{}: expression overflows", text));
+    }
+    if let Some(rest) = text.strip_prefix('-') {
+        return evaluate(rest, symbols)?
+            .checked_neg()
+            .ok_or_else(|| format!("{}: expression overflows", text));
+    }
+    for (function, apply) in [
+        ("lo8(", (|value| value & 0xff) as fn(i64) -> i64),
+        ("hi8(", |value| value >> 8 & 0xff),
+        ("pm(", |value| value / 2),
+    ] {
+        if let Some(inner) = text
+            .strip_prefix(function)
+            .and_then(|text| text.strip_suffix(')'))
+        {
+            return Ok(apply(evaluate(inner, symbols)?));
+        }
+    }
+    if let Some(inner) = text
+        .strip_prefix('(')
+        .and_then(|text| text.strip_suffix(')'))
+    {
+        return evaluate(inner, symbols);
+    }
+    if let Some(value) = number(text) {
+        return Ok(value);
+    }
+    match symbols.get(text) {
+        Some(&value) => Ok(value),
+        None if text.is_empty() => Err("missing operand".to_string()),
+        None => Err(format!("{}: undefined symbol", text)),
+    }
+}
+
+/// Register number of `r0` to `r31`.
+fn register(text: &str) -> Option<u8> {
+    let number: u8 = text.strip_prefix(['r', 'R'])?.parse().ok()?;
+    (number < 32).then_some(number)
+}
+
+fn operand(text: &str, symbols: &HashMap<String, i64>) -> Result<Operand, String> {
+    if let Some(d) = register(text) {
+        return Ok(Operand::Register(d));
+    }
+    if let Some((high, low)) = text.split_once(':') {
+        let high = register(high.trim());
+        let low = low.trim();
+        let low = register(low).or_else(|| low.parse().ok().filter(|&low: &u8| low < 32));
+        return match (high, low) {
+            (Some(high), Some(low)) if high == low + 1 => Ok(Operand::Pair(low)),
+            _ => Err(format!("{}: not a register pair", text)),
+        };
+    }
+    let pointer = |name: &str| match name.to_ascii_uppercase().as_str() {
+        "X" => Some(Pointer::X),
+        "Y" => Some(Pointer::Y),
+        "Z" => Some(Pointer::Z),
+        _ => None,
+    };
+    if let Some(pointer) = pointer(text) {
+        return Ok(Operand::Indirect(pointer, Mode::Plain));
+    }
+    if let Some(pointer) = text.strip_prefix('-').and_then(pointer) {
+        return Ok(Operand::Indirect(pointer, Mode::PreDecrement));
+    }
+    if let Some((name, q)) = text.split_once('+') {
+        if let Some(pointer) = pointer(name.trim()) {
+            return match q.trim() {
+                "" => Ok(Operand::Indirect(pointer, Mode::PostIncrement)),
+                q => Ok(Operand::Displacement(pointer, evaluate(q, symbols)?)),
+            };
+        }
+    }
+    if let Some(offset) = text.strip_prefix('.') {
+        if offset.starts_with(['+', '-']) {
+            return Ok(Operand::Relative(evaluate(offset, symbols)?));
+        }
+    }
+    Ok(Operand::Value(evaluate(text, symbols)?))
+}
+
+/// The mnemonic of the table an alias stands for, and its operands
+/// rewritten accordingly. `sbr` and `cbr` are only accepted, the listing
+/// prints `ori` and `andi`.
+fn resolve<'a>(mnemonic: &'a str, operands: &[Operand]) -> (&'a str, Vec<Operand>) {
+    let mut operands = operands.to_vec();
+    match (mnemonic, &mut operands[..]) {
+        ("sbr", _) => return ("ori", operands),
+        ("cbr", [_, Operand::Value(k)]) => {
+            *k = match *k {
+                -128..=255 => !*k & 0xff,
+                _ => !*k,
+            };
+            return ("andi", operands);
+        }
+        ("cbr", _) => return ("andi", operands),
+        _ => {}
+    }
+    let Some(alias) = opcodes::ALIASES
+        .iter()
+        .find(|alias| alias.mnemonic == mnemonic)
+    else {
+        return (mnemonic, operands);
+    };
+    match alias.rewrite {
+        Rewrite::Twice if operands.len() == 1 => operands.push(operands[0]),
+        // Nothing of the table takes no operands where a register is doubled.
+        Rewrite::Twice => operands.clear(),
+        Rewrite::First(value) => operands.insert(0, Operand::Value(value)),
+        Rewrite::Last(value) => operands.push(Operand::Value(value)),
+    }
+    (alias.of, operands)
+}
+
+/// Value of a field of `kind` an operand gives at the byte address
+/// `address`, `None` when the field does not take such an operand.
+fn field(kind: Kind, operand: &Operand, address: u32) -> Result<Option<i64>, String> {
+    let value = match (kind, *operand) {
+        (Kind::Register | Kind::Upper, Operand::Register(d)) => d as i64,
+        // Pairs are written as their low register in avr-as syntax.
+        (Kind::Even | Kind::Word, Operand::Pair(d) | Operand::Register(d)) => d as i64,
+        (Kind::Immediate, Operand::Value(k @ -128..=-1)) => k + 256,
+        (Kind::Immediate | Kind::Bit | Kind::Io | Kind::Data, Operand::Value(k)) => k,
+        (Kind::Program, Operand::Value(target)) => match target % 2 {
+            0 => target / 2,
+            _ => return Err(format!("{:#x}: not a program address", target)),
+        },
+        (Kind::Relative, Operand::Relative(offset) | Operand::Value(offset)) => {
+            let offset = match *operand {
+                Operand::Value(target) => target
+                    .checked_sub(address as i64 + 2)
+                    .ok_or_else(|| format!("{}: expression overflows", target))?,
+                _ => offset,
+            };
+            match offset % 2 {
+                0 => offset / 2,
+                _ => return Err(format!("{}: odd offset", offset)),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Values of the fields of `opcode` for `operands`, `None` when they do not
+/// follow its syntax.
+fn values(opcode: &Opcode, operands: &[Operand], address: u32) -> Result<Option<Values>, String> {
+    let tokens = opcode.tokens();
+    if tokens.len() != operands.len() {
+        return Ok(None);
+    }
+    let mut values = Values::default();
+    for (token, operand) in tokens.into_iter().zip(operands) {
+        match (token, *operand) {
+            (Token::Field(letter), _) => match field(opcode.kind(letter), operand, address)? {
+                Some(value) => values.0.push((letter, value)),
+                None => return Ok(None),
+            },
+            (Token::Indirect(pointer, mode), Operand::Indirect(other, other_mode))
+                if (pointer, mode) == (other, other_mode) => {}
+            (Token::Displacement(pointer, letter), Operand::Displacement(other, q))
+                if pointer == other =>
+            {
+                values.0.push((letter, q))
+            }
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(values))
+}
+
+/// Words of `mnemonic` with `operands` at the byte address `address`, with
+/// the first opcode of the table whose syntax they follow, `None` when an
+/// operand does not fit its field.
+fn build(
+    mnemonic: &str,
+    operands: &[Operand],
+    address: u32,
+    core: Core,
+) -> Result<Option<Vec<u16>>, String> {
+    let (name, operands) = resolve(mnemonic, operands);
+    if opcodes::named(name).next().is_none() {
+        return Err(format!("{}: unknown instruction", mnemonic));
+    }
+    for opcode in opcodes::named(name) {
+        let Some(values) = values(opcode, &operands, address)? else {
+            continue;
+        };
+        if !opcode.available(core) {
+            let core = core
+                .to_possible_value()
+                .map_or(String::new(), |value| value.get_name().to_string());
+            return Err(format!("{}: not available on {}", mnemonic, core));
+        }
+        return Ok(opcode.encode(&values));
+    }
+    Err(format!("{}: wrong operands", mnemonic))
+}
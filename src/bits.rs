@@ -0,0 +1,61 @@
+use crate::{
+    image::Image,
+    instruction::Instruction,
+    opcodes::{self, Token},
+};
+use std::fmt::Write;
+
+/// Binary of the instruction at `address` with each bit attributed to the
+/// opcode, printed as its value, or to the field of an operand, printed as
+/// its letter in the pattern of the opcode.
+pub fn breakdown(image: &Image, address: u32, overloads: bool) -> Option<String> {
+    let word = image.word(address)?;
+    let next = image.word(address + 2);
+    let instruction = Instruction::decode(word, next).ok()?;
+    let (opcode, values) = opcodes::find(&instruction);
+    let mut words = vec![word];
+    words.extend(next.filter(|_| opcode.words() == 2));
+    let mut pattern = opcode.pattern.chars().filter(|&c| c != '_');
+    let mut bits = String::new();
+    let mut fields = String::new();
+    for (i, &value) in words.iter().enumerate() {
+        for bit in (0..16).rev() {
+            let set = value >> bit & 1 == 1;
+            let field = pattern.next().filter(char::is_ascii_alphabetic);
+            if bit != 15 && bit % 4 == 3 {
+                bits.push(' ');
+                fields.push(' ');
+            }
+            bits.push(if set { '1' } else { '0' });
+            fields.push(field.unwrap_or(if set { '1' } else { '0' }));
+        }
+        if i + 1 < words.len() {
+            bits.push_str("  ");
+            fields.push_str("  ");
+        }
+    }
+    let mut output = String::new();
+    let _ = writeln!(
+        output,
+        "{:#x}: {}",
+        address,
+        instruction.display(address, overloads, None)
+    );
+    let _ = writeln!(output, "    bits   {}", bits);
+    let _ = writeln!(output, "    fields {}", fields);
+    let operands: Vec<String> = opcode
+        .tokens()
+        .into_iter()
+        .zip(opcode.operands(&values))
+        .map(|(token, operand)| match token {
+            Token::Field(letter) | Token::Displacement(_, letter) => {
+                format!("{} = {}", letter, operand)
+            }
+            Token::Indirect(..) => operand.to_string(),
+        })
+        .collect();
+    if !operands.is_empty() {
+        let _ = writeln!(output, "    {}", operands.join(", "));
+    }
+    Some(output)
+}
@@ -0,0 +1,224 @@
+use crate::{
+    image::Image,
+    indirect,
+    instruction::{DecodeError, Instruction},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+/// Code reachable from a single entry point without following calls.
+pub struct Function {
+    pub entry: u32,
+    pub instructions: BTreeMap<u32, Instruction>,
+    /// Pairs of call site and callee entry.
+    pub calls: Vec<(u32, u32)>,
+    /// Targets of indirect jumps and calls resolved through `Z`.
+    pub resolved: BTreeMap<u32, Vec<u32>>,
+    /// Indirect calls and jumps whose targets are unknown.
+    pub indirect_calls: Vec<u32>,
+    pub indirect_jumps: Vec<u32>,
+    /// Addresses where decoding stopped, `None` when no code is loaded there.
+    pub errors: Vec<(u32, Option<DecodeError>)>,
+}
+
+pub struct CallGraph {
+    pub functions: BTreeMap<u32, Function>,
+    /// Targets of the interrupt vector table, the first one is reset.
+    pub vectors: Vec<u32>,
+}
+
+/// Interrupt vectors are read from address zero while they hold jumps.
+fn vectors(image: &Image) -> Vec<u32> {
+    let mut vectors = vec![];
+    let mut address = 0;
+    while let Some(Ok(instruction)) = image.decode(address) {
+        match instruction {
+            Instruction::Jmp { .. } | Instruction::Rjmp { .. } => {
+                vectors.extend(instruction.target(address))
+            }
+            _ => break,
+        }
+        address += instruction.length() * 2;
+    }
+    vectors
+}
+
+/// Addresses the execution may continue at after `instruction`, calls are
+/// expected to return and jumps to the reset vector end the flow.
+pub fn successors(image: &Image, address: u32, instruction: &Instruction) -> Vec<u32> {
+    let next = address + instruction.length() * 2;
+    match instruction {
+        Instruction::Ret | Instruction::Reti | Instruction::Ijmp | Instruction::Eijmp => vec![],
+        Instruction::Jmp { .. } | Instruction::Rjmp { .. } => match instruction.target(address) {
+            Some(0) | None => vec![],
+            Some(target) => vec![target],
+        },
+        Instruction::Brbs { .. } | Instruction::Brbc { .. } => {
+            vec![next, instruction.target(address).unwrap_or(next)]
+        }
+        _ => match instruction.skip_target(address, image.length(next).unwrap_or(1)) {
+            Some(target) => vec![next, target],
+            None => vec![next],
+        },
+    }
+}
+
+/// Callee of a direct call, `rcall .+0` only reserves stack space.
+pub fn callee(address: u32, instruction: &Instruction) -> Option<u32> {
+    match instruction {
+        Instruction::Rcall { k: 0 } => None,
+        Instruction::Call { .. } | Instruction::Rcall { .. } => instruction.target(address),
+        _ => None,
+    }
+}
+
+impl Function {
+    fn explore(image: &Image, entry: u32) -> Self {
+        let mut function = Function {
+            entry,
+            instructions: BTreeMap::new(),
+            calls: vec![],
+            resolved: BTreeMap::new(),
+            indirect_calls: vec![],
+            indirect_jumps: vec![],
+            errors: vec![],
+        };
+        let mut pending = vec![entry];
+        // Resolved jumps extend the function, which may resolve more of them.
+        loop {
+            while let Some(address) = pending.pop() {
+                if function.instructions.contains_key(&address) {
+                    continue;
+                }
+                let instruction = match image.decode(address) {
+                    Some(Ok(instruction)) => instruction,
+                    Some(Err(error)) => {
+                        function.errors.push((address, Some(error)));
+                        continue;
+                    }
+                    None => {
+                        function.errors.push((address, None));
+                        continue;
+                    }
+                };
+                if let Some(target) = callee(address, &instruction) {
+                    function.calls.push((address, target));
+                }
+                function.instructions.insert(address, instruction);
+                pending.extend(successors(image, address, &instruction));
+            }
+            let resolved = indirect::resolve(image, &function);
+            if resolved == function.resolved {
+                break;
+            }
+            for (address, targets) in &resolved {
+                if let Some(Instruction::Ijmp | Instruction::Eijmp) =
+                    function.instructions.get(address)
+                {
+                    pending.extend(targets);
+                }
+            }
+            function.resolved = resolved;
+        }
+        for (&address, instruction) in &function.instructions {
+            match (instruction, function.resolved.get(&address)) {
+                (Instruction::Icall | Instruction::Eicall, Some(targets)) => function
+                    .calls
+                    .extend(targets.iter().map(|&target| (address, target))),
+                (Instruction::Icall | Instruction::Eicall, None) => {
+                    function.indirect_calls.push(address)
+                }
+                (Instruction::Ijmp | Instruction::Eijmp, None) => {
+                    function.indirect_jumps.push(address)
+                }
+                _ => {}
+            }
+        }
+        function.calls.sort_unstable();
+        function
+    }
+
+    /// Addresses from the lowest to past the highest instruction.
+    pub fn extent(&self) -> Range<u32> {
+        let start = self
+            .instructions
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(self.entry);
+        let end = self
+            .instructions
+            .iter()
+            .next_back()
+            .map_or(start, |(address, instruction)| {
+                address + instruction.length() * 2
+            });
+        start..end
+    }
+
+    /// Like [`successors`], with resolved indirect jumps followed.
+    pub fn successors(&self, image: &Image, address: u32, instruction: &Instruction) -> Vec<u32> {
+        match (instruction, self.resolved.get(&address)) {
+            (Instruction::Ijmp | Instruction::Eijmp, Some(targets)) => targets.clone(),
+            _ => successors(image, address, instruction),
+        }
+    }
+}
+
+impl CallGraph {
+    /// Discovers functions from the interrupt vectors, or from the lowest
+    /// loaded address when the image has no vector table.
+    pub fn new(image: &Image) -> Self {
+        let mut vectors = vectors(image);
+        if vectors.is_empty() {
+            vectors.extend(image.bounds().map(|bounds| bounds.0));
+        }
+        let mut functions = BTreeMap::new();
+        let mut pending: Vec<u32> = vectors.iter().rev().copied().collect();
+        let mut seen = BTreeSet::new();
+        while let Some(entry) = pending.pop() {
+            if !seen.insert(entry) {
+                continue;
+            }
+            let function = Function::explore(image, entry);
+            pending.extend(function.calls.iter().map(|call| call.1));
+            functions.insert(entry, function);
+        }
+        CallGraph { functions, vectors }
+    }
+
+    /// Resolved targets of indirect jumps and calls of all functions.
+    pub fn resolved(&self) -> BTreeMap<u32, Vec<u32>> {
+        let mut resolved: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for function in self.functions.values() {
+            for (&address, targets) in &function.resolved {
+                let known = resolved.entry(address).or_default();
+                known.extend(targets);
+                known.sort_unstable();
+                known.dedup();
+            }
+        }
+        resolved
+    }
+
+    pub fn reset(&self) -> Option<u32> {
+        self.vectors.first().copied()
+    }
+
+    /// Distinct interrupt handlers, vectors that lead back to reset are
+    /// left out.
+    pub fn interrupts(&self) -> Vec<u32> {
+        let mut interrupts: Vec<u32> = self
+            .vectors
+            .iter()
+            .skip(1)
+            .copied()
+            .filter(|&entry| Some(entry) != self.reset() && entry != 0)
+            .collect();
+        interrupts.sort_unstable();
+        interrupts.dedup();
+        interrupts
+    }
+}
@@ -0,0 +1,90 @@
+use clap::ValueEnum;
+use std::{
+    env,
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    /// Color when printing to a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Mnemonic,
+    Register,
+    Immediate,
+    Address,
+    Label,
+    Comment,
+    Error,
+}
+
+impl Style {
+    /// SGR parameters of the style.
+    fn code(self) -> &'static str {
+        match self {
+            Style::Mnemonic => "33",
+            Style::Register => "36",
+            Style::Immediate => "35",
+            Style::Address => "34",
+            Style::Label => "1;32",
+            Style::Comment => "90",
+            Style::Error => "1;31",
+        }
+    }
+}
+
+/// Whether output is colored, copied into everything that prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    /// `stream` is the standard stream the colored text goes to.
+    pub fn new(when: When, stream: &impl IsTerminal) -> Self {
+        let enabled = match when {
+            When::Always => true,
+            When::Never => false,
+            When::Auto => {
+                stream.is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        };
+        Palette { enabled }
+    }
+
+    pub fn stdout(when: When) -> Self {
+        Palette::new(when, &io::stdout())
+    }
+
+    pub fn stderr(when: When) -> Self {
+        Palette::new(when, &io::stderr())
+    }
+
+    pub fn paint<T: Display>(self, style: Style, text: T) -> Painted<T> {
+        Painted {
+            style: self.enabled.then_some(style),
+            text,
+        }
+    }
+}
+
+/// Text wrapped in the escape sequences of its style, if any.
+pub struct Painted<T> {
+    style: Option<Style>,
+    text: T,
+}
+
+impl<T: Display> Display for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.style {
+            Some(style) => write!(f, "\x1b[{}m{}\x1b[0m", style.code(), self.text),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
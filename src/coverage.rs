@@ -0,0 +1,162 @@
+use crate::{
+    callgraph::CallGraph,
+    color::{Palette, Style},
+    lines::Lines,
+    reassemble,
+    symbols::Symbols,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+/// Source file name of the addresses without a line in lcov output.
+const FLASH: &str = "flash";
+
+/// How many times each instruction of a simulated run was executed.
+pub struct Coverage {
+    /// Executions of the instruction at each word of flash.
+    counts: Vec<u64>,
+}
+
+/// Lines of an lcov record: functions with their first line, and lines
+/// with their count.
+#[derive(Default)]
+struct Record {
+    functions: Vec<(u32, String, u64)>,
+    lines: BTreeMap<u32, u64>,
+}
+
+impl Coverage {
+    pub fn new(flash: u32) -> Self {
+        Coverage {
+            counts: vec![0; flash as usize / 2],
+        }
+    }
+
+    pub fn record(&mut self, address: u32) {
+        if let Some(count) = self.counts.get_mut(address as usize / 2) {
+            *count += 1;
+        }
+    }
+
+    pub fn count(&self, address: u32) -> u64 {
+        self.counts.get(address as usize / 2).copied().unwrap_or(0)
+    }
+
+    /// Addresses of executed instructions.
+    fn executed(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.counts.len())
+            .filter(|&i| self.counts[i] > 0)
+            .map(|i| i as u32 * 2)
+    }
+
+    /// Listing of each function of `graph` with how often each instruction
+    /// ran, `#####` for those that never did, followed by instructions run
+    /// outside of the known functions.
+    pub fn listing(&self, graph: &CallGraph, symbols: &Symbols, palette: Palette) -> String {
+        let mut text = String::new();
+        let mut known = BTreeSet::new();
+        for (entry, function) in &graph.functions {
+            let name = match symbols.code.get(entry) {
+                Some(name) => format!("{:#x} <{}>", entry, name),
+                None => format!("{:#x}", entry),
+            };
+            let hit = function
+                .instructions
+                .keys()
+                .filter(|&&address| self.count(address) > 0)
+                .count();
+            let _ = writeln!(
+                text,
+                "function {}: {} of {} instructions executed, entered {} {}",
+                palette.paint(Style::Label, name),
+                hit,
+                function.instructions.len(),
+                self.count(*entry),
+                match self.count(*entry) {
+                    1 => "time",
+                    _ => "times",
+                }
+            );
+            for (&address, instruction) in &function.instructions {
+                known.insert(address);
+                let count = match self.count(address) {
+                    0 => palette
+                        .paint(Style::Comment, format!("{:>10}", "#####"))
+                        .to_string(),
+                    count => format!("{:>10}", count),
+                };
+                let _ = writeln!(
+                    text,
+                    "{}  {:#06x}: {}",
+                    count,
+                    address,
+                    instruction.display(address, true, None)
+                );
+            }
+        }
+        let outside: Vec<u32> = self
+            .executed()
+            .filter(|address| !known.contains(address))
+            .collect();
+        if !outside.is_empty() {
+            let _ = writeln!(text, "outside of functions:");
+            for address in outside {
+                let _ = writeln!(text, "{:>10}  {:#06x}", self.count(address), address);
+            }
+        }
+        text
+    }
+
+    /// Coverage in the tracefile format of lcov. Instructions are keyed by
+    /// their source line when `lines` has one, lines of several
+    /// instructions counting the most executed. The others are keyed by
+    /// their byte address in a `flash` source file.
+    pub fn lcov(&self, graph: &CallGraph, symbols: &Symbols, lines: &Lines) -> String {
+        let locate = |address: u32| match lines.find(address) {
+            Some((file, line)) => (file.to_string(), line),
+            None => (FLASH.to_string(), address),
+        };
+        let mut records: BTreeMap<String, Record> = BTreeMap::new();
+        let mut addresses: BTreeSet<u32> = self.executed().collect();
+        for (&entry, function) in &graph.functions {
+            let (file, line) = locate(entry);
+            let name = reassemble::label(entry, symbols);
+            let record = records.entry(file).or_default();
+            record.functions.push((line, name, self.count(entry)));
+            addresses.extend(function.instructions.keys());
+        }
+        for address in addresses {
+            let (file, line) = locate(address);
+            let count = records
+                .entry(file)
+                .or_default()
+                .lines
+                .entry(line)
+                .or_default();
+            *count = (*count).max(self.count(address));
+        }
+        let mut text = String::from("TN:\n");
+        for (file, record) in records {
+            let _ = writeln!(text, "SF:{}", file);
+            for (line, name, _) in &record.functions {
+                let _ = writeln!(text, "FN:{},{}", line, name);
+            }
+            for (_, name, count) in &record.functions {
+                let _ = writeln!(text, "FNDA:{},{}", count, name);
+            }
+            let _ = writeln!(text, "FNF:{}", record.functions.len());
+            let hit = record.functions.iter().filter(|f| f.2 > 0).count();
+            let _ = writeln!(text, "FNH:{}", hit);
+            for (line, count) in &record.lines {
+                let _ = writeln!(text, "DA:{},{}", line, count);
+            }
+            let _ = writeln!(text, "LF:{}", record.lines.len());
+            let hit = record.lines.values().filter(|&&count| count > 0).count();
+            let _ = writeln!(text, "LH:{}", hit);
+            text.push_str("end_of_record\n");
+        }
+        text
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{
+    device::{Core, Timing},
+    instruction::Instruction,
+    opcodes::{self, Clocks},
+};
+use std::fmt;
+
+/// Clock cycles of an instruction, `taken` is the count when a branch is
+/// taken or a skip is performed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycles {
+    pub cycles: u32,
+    pub taken: Option<u32>,
+}
+
+impl Cycles {
+    fn fixed(cycles: u32) -> Self {
+        Cycles {
+            cycles,
+            taken: None,
+        }
+    }
+}
+
+impl fmt::Display for Cycles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.taken {
+            Some(taken) => write!(f, "{}/{}", self.cycles, taken),
+            None => write!(f, "{}", self.cycles),
+        }
+    }
+}
+
+/// Cycles of `instruction` on `core` as listed in the AVR instruction set
+/// manual, `skipped` is the length in words of the following instruction
+/// and only matters to skips. Data memory accesses assume internal SRAM.
+pub fn cycles(instruction: &Instruction, core: Core, skipped: u32) -> Cycles {
+    clocks(opcodes::find(instruction).0.clocks, core, skipped)
+}
+
+/// Cycles of an opcode with `clocks` on `core`.
+pub fn clocks(clocks: Clocks, core: Core, skipped: u32) -> Cycles {
+    let timing = core.timing();
+    let wide = core.pc_bytes() == 3;
+    let by = |columns: [u32; 4]| match timing {
+        Timing::Avre => columns[0],
+        Timing::Avrxm => columns[1],
+        Timing::Avrxt => columns[2],
+        Timing::Avrrc => columns[3],
+    };
+    match clocks {
+        Clocks::Fixed(columns) => Cycles::fixed(by(columns)),
+        Clocks::Wide(columns) => Cycles::fixed(by(columns) + wide as u32),
+        Clocks::Branch => Cycles {
+            cycles: 1,
+            taken: Some(2),
+        },
+        Clocks::Skip(columns) => Cycles {
+            cycles: by(columns),
+            taken: Some(by(columns) + skipped),
+        },
+        Clocks::SkipIo(columns) => Cycles {
+            cycles: by(columns),
+            taken: Some(match timing {
+                Timing::Avrrc => by(columns) + 1,
+                _ => by(columns) + skipped,
+            }),
+        },
+    }
+}
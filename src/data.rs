@@ -0,0 +1,169 @@
+use crate::{
+    callgraph::CallGraph,
+    image::Image,
+    indirect::{self, Reference},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Printable characters an unreferenced string needs before its terminator.
+const MIN_STRING: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Null-terminated ASCII text, the terminator included.
+    String,
+    /// Bytes read with `lpm`, up to the next code or data unless the size
+    /// of the table is known.
+    Table,
+}
+
+/// Data found in flash, `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u32,
+    pub end: u32,
+    pub kind: Kind,
+}
+
+pub struct Data {
+    pub regions: BTreeMap<u32, Region>,
+    /// Flash address loaded by each `ldi` of a pointer read with `lpm`.
+    pub loads: BTreeMap<u32, u32>,
+}
+
+fn printable(byte: u8) -> bool {
+    matches!(byte, 0x20..=0x7e | b'\t' | b'\n' | b'\r')
+}
+
+/// Escapes `bytes` the way `.asciz` expects them.
+pub fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            b'\t' => text.push_str("\\t"),
+            b'\n' => text.push_str("\\n"),
+            b'\r' => text.push_str("\\r"),
+            0x20..=0x7e => text.push(byte as char),
+            _ => text.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    text
+}
+
+impl Data {
+    /// Finds strings and tables outside of the code reachable in `graph`,
+    /// at addresses read with `lpm` and, for strings, anywhere else.
+    pub fn detect(image: &Image, graph: &CallGraph) -> Self {
+        let mut code = BTreeSet::new();
+        let mut references: BTreeMap<u32, Reference> = BTreeMap::new();
+        for function in graph.functions.values() {
+            for (&address, instruction) in &function.instructions {
+                code.extend(address..address + instruction.length() * 2);
+            }
+            for (address, reference) in indirect::references(image, function) {
+                let known = references.entry(address).or_default();
+                known.loads.extend(reference.loads);
+                known.length = known.length.max(reference.length);
+            }
+        }
+        // Length of the text of a string at `address`, without the terminator.
+        let string = |address: u32| -> Option<u32> {
+            let mut length = 0;
+            loop {
+                let address = address + length;
+                if code.contains(&address) {
+                    return None;
+                }
+                match image.byte(address)? {
+                    0 => return Some(length),
+                    byte if printable(byte) => length += 1,
+                    _ => return None,
+                }
+            }
+        };
+        let mut data = Data {
+            regions: BTreeMap::new(),
+            loads: BTreeMap::new(),
+        };
+        let mut tables = vec![];
+        for (&address, reference) in &references {
+            if code.contains(&address) || image.byte(address).is_none() {
+                continue;
+            }
+            for &load in &reference.loads {
+                data.loads.insert(load, address);
+            }
+            match string(address) {
+                Some(length) => data.insert(address, address + length + 1, Kind::String),
+                None => tables.push(address),
+            }
+        }
+        for segment in image.segments() {
+            let mut address = segment.start;
+            while address < segment.end {
+                if let Some(region) = data.regions.get(&address) {
+                    address = region.end;
+                    continue;
+                }
+                match string(address) {
+                    Some(length) if length >= MIN_STRING && !tables.contains(&address) => {
+                        data.insert(address, address + length + 1, Kind::String);
+                        address += length + 1;
+                    }
+                    _ => address += 1,
+                }
+            }
+        }
+        let starts: BTreeSet<u32> = data.regions.keys().chain(&tables).copied().collect();
+        for start in tables {
+            let limit = references[&start]
+                .length
+                .map_or(u32::MAX, |length| start + length);
+            let mut end = start + 1;
+            while end < limit
+                && image.byte(end).is_some()
+                && !code.contains(&end)
+                && !starts.contains(&end)
+            {
+                end += 1;
+            }
+            data.insert(start, end, Kind::Table);
+        }
+        data
+    }
+
+    fn insert(&mut self, start: u32, end: u32, kind: Kind) {
+        self.regions.insert(start, Region { start, end, kind });
+    }
+
+    /// Data starting at `address`.
+    pub fn region(&self, address: u32) -> Option<&Region> {
+        self.regions.get(&address)
+    }
+
+    /// Start of the first region after `address`.
+    pub fn next(&self, address: u32) -> Option<u32> {
+        self.regions
+            .range(address + 1..)
+            .next()
+            .map(|(&start, _)| start)
+    }
+
+    /// Comment for an `ldi` loading the pointer to data, the address and
+    /// the text of a string.
+    pub fn describe(&self, image: &Image, load: u32) -> Option<String> {
+        let address = *self.loads.get(&load)?;
+        let region = self.regions.get(&address)?;
+        Some(match region.kind {
+            Kind::String => {
+                let text: Vec<u8> = (region.start..region.end - 1)
+                    .filter_map(|address| image.byte(address))
+                    .collect();
+                format!("{:#x} \"{}\"", address, escape(&text))
+            }
+            Kind::Table => format!("{:#x}", address),
+        })
+    }
+}
@@ -0,0 +1,604 @@
+use clap::ValueEnum;
+
+/// AVR core families, named after the avr-gcc architectures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Core {
+    Avr1,
+    Avr2,
+    Avr25,
+    Avr3,
+    Avr31,
+    Avr35,
+    Avr4,
+    Avr5,
+    Avr51,
+    Avr6,
+    Xmega2,
+    Xmega3,
+    Xmega4,
+    Xmega5,
+    Xmega6,
+    Xmega7,
+    Tiny,
+}
+
+impl Core {
+    /// Number of bytes the return address takes on the stack, three on
+    /// devices with a 22-bit program counter.
+    pub fn pc_bytes(&self) -> u32 {
+        match self {
+            Core::Avr6 | Core::Xmega6 | Core::Xmega7 => 3,
+            _ => 2,
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        match self {
+            Core::Xmega3 => Timing::Avrxt,
+            Core::Xmega2 | Core::Xmega4 | Core::Xmega5 | Core::Xmega6 | Core::Xmega7 => {
+                Timing::Avrxm
+            }
+            Core::Tiny => Timing::Avrrc,
+            _ => Timing::Avre,
+        }
+    }
+}
+
+/// Instruction timing columns of the AVR instruction set manual.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Classic cores, AVR and AVRe.
+    Avre,
+    Avrxm,
+    Avrxt,
+    /// Reduced core of the smallest tinyAVR devices.
+    Avrrc,
+}
+
+/// Memory of a device, addresses are byte addresses in their own space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    pub core: Core,
+    /// Bytes of flash.
+    pub flash: u32,
+    /// Data address of I/O register 0, registers are mapped below it when
+    /// this is not zero.
+    pub io: u16,
+    /// Data address of the first byte of internal SRAM.
+    pub sram: u16,
+    /// Bytes of internal SRAM.
+    pub sram_size: u16,
+    /// Bytes of EEPROM.
+    pub eeprom: u16,
+    /// Letter and data address of the `PINx` register of each GPIO port,
+    /// `DDRx` and `PORTx` follow it.
+    pub ports: &'static [(char, u16)],
+    pub timer0: Option<Timer>,
+    pub timer1: Option<Timer>,
+    pub usart: Option<Usart>,
+    pub nvm: Option<Nvm>,
+}
+
+/// Data addresses and interrupt vectors of a timer/counter laid out like
+/// those of the ATmega328P. 16-bit registers are given by their low byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timer {
+    pub tccra: u16,
+    pub tccrb: u16,
+    pub tcnt: u16,
+    pub icr: Option<u16>,
+    pub ocra: u16,
+    pub ocrb: u16,
+    pub timsk: u16,
+    pub tifr: u16,
+    /// Whether the counter and its registers are 16 bits wide.
+    pub wide: bool,
+    /// Vectors of the compare match A, compare match B and overflow
+    /// interrupts.
+    pub vectors: [u8; 3],
+}
+
+/// Data addresses and interrupt vectors of a USART laid out like the one of
+/// the ATmega328P.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usart {
+    pub ucsra: u16,
+    pub ucsrb: u16,
+    pub ucsrc: u16,
+    pub ubrrl: u16,
+    pub ubrrh: u16,
+    pub udr: u16,
+    /// Vectors of the receive complete, data register empty and transmit
+    /// complete interrupts.
+    pub vectors: [u8; 3],
+}
+
+/// Data addresses and interrupt vectors of the EEPROM and self-programming
+/// controllers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nvm {
+    /// `EECR`, followed by `EEDR`, `EEARL` and `EEARH`.
+    pub eecr: u16,
+    pub spmcsr: u16,
+    /// Bytes of a flash page, erased and written at once by `spm`.
+    pub page: u16,
+    /// Vector of the EEPROM ready interrupt.
+    pub ee_ready: u8,
+    /// Vector of the store program memory ready interrupt, if there is one.
+    pub spm_ready: Option<u8>,
+}
+
+impl Profile {
+    /// Last address of internal SRAM, where the stack starts.
+    pub fn ramend(&self) -> u16 {
+        self.sram + self.sram_size - 1
+    }
+
+    /// Bytes of each entry of the interrupt vector table, a `jmp` on devices
+    /// with more than 8 KiB of flash and an `rjmp` on the others.
+    pub fn vector_size(&self) -> u32 {
+        match self.flash > 0x2000 {
+            true => 4,
+            false => 2,
+        }
+    }
+}
+
+impl Core {
+    /// Memory of a typical device of the family, for images that do not name
+    /// their device. It has no peripherals.
+    pub fn profile(self) -> Profile {
+        let (flash, io, sram, sram_size, eeprom) = match self {
+            Core::Avr1 => (0x400, 0x20, 0x60, 0x20, 0x40),
+            Core::Avr2 | Core::Avr25 => (0x2000, 0x20, 0x60, 0x200, 0x200),
+            Core::Avr3 | Core::Avr31 | Core::Avr35 => (0x8000, 0x20, 0x100, 0x800, 0x400),
+            Core::Avr4 => (0x2000, 0x20, 0x60, 0x400, 0x200),
+            Core::Avr5 => (0x8000, 0x20, 0x100, 0x800, 0x400),
+            Core::Avr51 => (0x20000, 0x20, 0x100, 0x4000, 0x1000),
+            Core::Avr6 => (0x40000, 0x20, 0x200, 0x2000, 0x1000),
+            Core::Xmega2 | Core::Xmega3 | Core::Xmega4 | Core::Xmega5 => {
+                (0x10000, 0, 0x2000, 0x2000, 0x800)
+            }
+            Core::Xmega6 | Core::Xmega7 => (0x40000, 0, 0x2000, 0x4000, 0x1000),
+            Core::Tiny => (0x400, 0, 0x40, 0x20, 0),
+        };
+        Profile {
+            core: self,
+            flash,
+            io,
+            sram,
+            sram_size,
+            eeprom,
+            ports: &[],
+            timer0: None,
+            timer1: None,
+            usart: None,
+            nvm: None,
+        }
+    }
+}
+
+/// Devices whose memory and peripherals are known exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Device {
+    Atmega8,
+    Atmega328p,
+    Atmega32u4,
+    Atmega1284p,
+    Atmega2560,
+    Attiny85,
+}
+
+/// Timer/counter 0 and 1 of the megaAVR devices, with their vectors.
+fn timers(timer0: [u8; 3], timer1: [u8; 3]) -> (Option<Timer>, Option<Timer>) {
+    let timer0 = Timer {
+        tccra: 0x44,
+        tccrb: 0x45,
+        tcnt: 0x46,
+        icr: None,
+        ocra: 0x47,
+        ocrb: 0x48,
+        timsk: 0x6e,
+        tifr: 0x35,
+        wide: false,
+        vectors: timer0,
+    };
+    let timer1 = Timer {
+        tccra: 0x80,
+        tccrb: 0x81,
+        tcnt: 0x84,
+        icr: Some(0x86),
+        ocra: 0x88,
+        ocrb: 0x8a,
+        timsk: 0x6f,
+        tifr: 0x36,
+        wide: true,
+        vectors: timer1,
+    };
+    (Some(timer0), Some(timer1))
+}
+
+/// EEPROM and self-programming controllers with `EECR` at `eecr`.
+fn nvm(eecr: u16, page: u16, ee_ready: u8, spm_ready: Option<u8>) -> Option<Nvm> {
+    Some(Nvm {
+        eecr,
+        spmcsr: 0x57,
+        page,
+        ee_ready,
+        spm_ready,
+    })
+}
+
+/// USART at `ucsra` with its registers in the order of the megaAVR devices.
+fn usart(ucsra: u16, vectors: [u8; 3]) -> Option<Usart> {
+    Some(Usart {
+        ucsra,
+        ucsrb: ucsra + 1,
+        ucsrc: ucsra + 2,
+        ubrrl: ucsra + 4,
+        ubrrh: ucsra + 5,
+        udr: ucsra + 6,
+        vectors,
+    })
+}
+
+impl Device {
+    /// Memory and peripherals of the device. Timers and the USART are only
+    /// modelled on devices with the register layout of the ATmega328P.
+    pub fn profile(self) -> Profile {
+        let (core, flash, sram, sram_size, eeprom) = match self {
+            Device::Atmega8 => (Core::Avr4, 0x2000, 0x60, 0x400, 0x200),
+            Device::Atmega328p => (Core::Avr5, 0x8000, 0x100, 0x800, 0x400),
+            Device::Atmega32u4 => (Core::Avr5, 0x8000, 0x100, 0xa00, 0x400),
+            Device::Atmega1284p => (Core::Avr51, 0x20000, 0x100, 0x4000, 0x1000),
+            Device::Atmega2560 => (Core::Avr6, 0x40000, 0x200, 0x2000, 0x1000),
+            Device::Attiny85 => (Core::Avr25, 0x2000, 0x60, 0x200, 0x200),
+        };
+        let (ports, (timer0, timer1), usart): (&[(char, u16)], _, _) = match self {
+            Device::Atmega8 => (&[('b', 0x36), ('c', 0x33), ('d', 0x30)], (None, None), None),
+            Device::Attiny85 => (&[('b', 0x36)], (None, None), None),
+            Device::Atmega328p => (
+                &[('b', 0x23), ('c', 0x26), ('d', 0x29)],
+                timers([14, 15, 16], [11, 12, 13]),
+                usart(0xc0, [18, 19, 20]),
+            ),
+            Device::Atmega32u4 => (
+                &[
+                    ('b', 0x23),
+                    ('c', 0x26),
+                    ('d', 0x29),
+                    ('e', 0x2c),
+                    ('f', 0x2f),
+                ],
+                timers([21, 22, 23], [17, 18, 20]),
+                usart(0xc8, [25, 26, 27]),
+            ),
+            Device::Atmega1284p => (
+                &[('a', 0x20), ('b', 0x23), ('c', 0x26), ('d', 0x29)],
+                timers([16, 17, 18], [13, 14, 15]),
+                usart(0xc0, [20, 21, 22]),
+            ),
+            Device::Atmega2560 => (
+                &[
+                    ('a', 0x20),
+                    ('b', 0x23),
+                    ('c', 0x26),
+                    ('d', 0x29),
+                    ('e', 0x2c),
+                    ('f', 0x2f),
+                    ('g', 0x32),
+                    ('h', 0x100),
+                    ('j', 0x103),
+                    ('k', 0x106),
+                    ('l', 0x109),
+                ],
+                timers([21, 22, 23], [17, 18, 20]),
+                usart(0xc0, [25, 26, 27]),
+            ),
+        };
+        let nvm = match self {
+            Device::Atmega8 => nvm(0x3c, 64, 15, Some(18)),
+            Device::Atmega328p => nvm(0x3f, 128, 22, Some(25)),
+            Device::Atmega32u4 => nvm(0x3f, 128, 30, Some(37)),
+            Device::Atmega1284p => nvm(0x3f, 256, 25, Some(27)),
+            Device::Atmega2560 => nvm(0x3f, 256, 30, Some(40)),
+            Device::Attiny85 => nvm(0x3c, 64, 6, None),
+        };
+        Profile {
+            core,
+            flash,
+            io: 0x20,
+            sram,
+            sram_size,
+            eeprom,
+            ports,
+            timer0,
+            timer1,
+            usart,
+            nvm,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Vector names of the datasheet, in order and numbered from 0 for
+    /// `RESET` as in the avr-libc headers, those of the peripherals the
+    /// simulator does not model included.
+    pub(crate) fn table(device: Device) -> &'static [&'static str] {
+        match device {
+            Device::Atmega8 => &[
+                "RESET",
+                "INT0",
+                "INT1",
+                "TIMER2_COMP",
+                "TIMER2_OVF",
+                "TIMER1_CAPT",
+                "TIMER1_COMPA",
+                "TIMER1_COMPB",
+                "TIMER1_OVF",
+                "TIMER0_OVF",
+                "SPI_STC",
+                "USART_RXC",
+                "USART_UDRE",
+                "USART_TXC",
+                "ADC",
+                "EE_RDY",
+                "ANA_COMP",
+                "TWI",
+                "SPM_RDY",
+            ],
+            Device::Atmega328p => &[
+                "RESET",
+                "INT0",
+                "INT1",
+                "PCINT0",
+                "PCINT1",
+                "PCINT2",
+                "WDT",
+                "TIMER2_COMPA",
+                "TIMER2_COMPB",
+                "TIMER2_OVF",
+                "TIMER1_CAPT",
+                "TIMER1_COMPA",
+                "TIMER1_COMPB",
+                "TIMER1_OVF",
+                "TIMER0_COMPA",
+                "TIMER0_COMPB",
+                "TIMER0_OVF",
+                "SPI_STC",
+                "USART_RX",
+                "USART_UDRE",
+                "USART_TX",
+                "ADC",
+                "EE_READY",
+                "ANALOG_COMP",
+                "TWI",
+                "SPM_READY",
+            ],
+            Device::Atmega32u4 => &[
+                "RESET",
+                "INT0",
+                "INT1",
+                "INT2",
+                "INT3",
+                "reserved",
+                "reserved",
+                "INT6",
+                "reserved",
+                "PCINT0",
+                "USB_GEN",
+                "USB_COM",
+                "WDT",
+                "reserved",
+                "reserved",
+                "reserved",
+                "TIMER1_CAPT",
+                "TIMER1_COMPA",
+                "TIMER1_COMPB",
+                "TIMER1_COMPC",
+                "TIMER1_OVF",
+                "TIMER0_COMPA",
+                "TIMER0_COMPB",
+                "TIMER0_OVF",
+                "SPI_STC",
+                "USART1_RX",
+                "USART1_UDRE",
+                "USART1_TX",
+                "ANALOG_COMP",
+                "ADC",
+                "EE_READY",
+                "TIMER3_CAPT",
+                "TIMER3_COMPA",
+                "TIMER3_COMPB",
+                "TIMER3_COMPC",
+                "TIMER3_OVF",
+                "TWI",
+                "SPM_READY",
+                "TIMER4_COMPA",
+                "TIMER4_COMPB",
+                "TIMER4_COMPD",
+                "TIMER4_OVF",
+                "TIMER4_FPF",
+            ],
+            Device::Atmega1284p => &[
+                "RESET",
+                "INT0",
+                "INT1",
+                "INT2",
+                "PCINT0",
+                "PCINT1",
+                "PCINT2",
+                "PCINT3",
+                "WDT",
+                "TIMER2_COMPA",
+                "TIMER2_COMPB",
+                "TIMER2_OVF",
+                "TIMER1_CAPT",
+                "TIMER1_COMPA",
+                "TIMER1_COMPB",
+                "TIMER1_OVF",
+                "TIMER0_COMPA",
+                "TIMER0_COMPB",
+                "TIMER0_OVF",
+                "SPI_STC",
+                "USART0_RX",
+                "USART0_UDRE",
+                "USART0_TX",
+                "ANALOG_COMP",
+                "ADC",
+                "EE_READY",
+                "TWI",
+                "SPM_READY",
+                "USART1_RX",
+                "USART1_UDRE",
+                "USART1_TX",
+                "TIMER3_CAPT",
+                "TIMER3_COMPA",
+                "TIMER3_COMPB",
+                "TIMER3_OVF",
+            ],
+            Device::Atmega2560 => &[
+                "RESET",
+                "INT0",
+                "INT1",
+                "INT2",
+                "INT3",
+                "INT4",
+                "INT5",
+                "INT6",
+                "INT7",
+                "PCINT0",
+                "PCINT1",
+                "PCINT2",
+                "WDT",
+                "TIMER2_COMPA",
+                "TIMER2_COMPB",
+                "TIMER2_OVF",
+                "TIMER1_CAPT",
+                "TIMER1_COMPA",
+                "TIMER1_COMPB",
+                "TIMER1_COMPC",
+                "TIMER1_OVF",
+                "TIMER0_COMPA",
+                "TIMER0_COMPB",
+                "TIMER0_OVF",
+                "SPI_STC",
+                "USART0_RX",
+                "USART0_UDRE",
+                "USART0_TX",
+                "ANALOG_COMP",
+                "ADC",
+                "EE_READY",
+                "TIMER3_CAPT",
+                "TIMER3_COMPA",
+                "TIMER3_COMPB",
+                "TIMER3_COMPC",
+                "TIMER3_OVF",
+                "USART1_RX",
+                "USART1_UDRE",
+                "USART1_TX",
+                "TWI",
+                "SPM_READY",
+                "TIMER4_CAPT",
+                "TIMER4_COMPA",
+                "TIMER4_COMPB",
+                "TIMER4_COMPC",
+                "TIMER4_OVF",
+                "TIMER5_CAPT",
+                "TIMER5_COMPA",
+                "TIMER5_COMPB",
+                "TIMER5_COMPC",
+                "TIMER5_OVF",
+                "USART2_RX",
+                "USART2_UDRE",
+                "USART2_TX",
+                "USART3_RX",
+                "USART3_UDRE",
+                "USART3_TX",
+            ],
+            Device::Attiny85 => &[
+                "RESET",
+                "INT0",
+                "PCINT0",
+                "TIMER1_COMPA",
+                "TIMER1_OVF",
+                "TIMER0_OVF",
+                "EE_RDY",
+                "ANA_COMP",
+                "ADC",
+                "TIMER1_COMPB",
+                "TIMER0_COMPA",
+                "TIMER0_COMPB",
+                "WDT",
+                "USI_START",
+                "USI_OVF",
+            ],
+        }
+    }
+
+    /// Number of the vector of `device` named any of `names`, as the
+    /// datasheets number some peripherals and not others.
+    pub(crate) fn vector(device: Device, names: &[&str]) -> u8 {
+        table(device)
+            .iter()
+            .position(|name| names.contains(name))
+            .unwrap() as u8
+    }
+
+    /// Names of the vectors `numbers` in the table of `device`.
+    fn names(device: Device, numbers: &[u8]) -> Vec<&'static str> {
+        numbers
+            .iter()
+            .map(|&number| table(device)[number as usize])
+            .collect()
+    }
+
+    #[test]
+    fn vectors_match_the_datasheets() {
+        for &device in Device::value_variants() {
+            let profile = device.profile();
+            if let (Some(timer0), Some(timer1)) = (profile.timer0, profile.timer1) {
+                assert_eq!(
+                    names(device, &timer0.vectors),
+                    ["TIMER0_COMPA", "TIMER0_COMPB", "TIMER0_OVF"],
+                    "{:?}",
+                    device
+                );
+                assert_eq!(
+                    names(device, &timer1.vectors),
+                    ["TIMER1_COMPA", "TIMER1_COMPB", "TIMER1_OVF"],
+                    "{:?}",
+                    device
+                );
+            }
+            if let Some(usart) = profile.usart {
+                let names: Vec<String> = names(device, &usart.vectors)
+                    .iter()
+                    .map(|name| name.replace(['0', '1'], ""))
+                    .collect();
+                assert_eq!(
+                    names,
+                    ["USART_RX", "USART_UDRE", "USART_TX"],
+                    "{:?}",
+                    device
+                );
+            }
+            if let Some(nvm) = profile.nvm {
+                let ready = table(device)[nvm.ee_ready as usize];
+                assert!(matches!(ready, "EE_READY" | "EE_RDY"), "{:?}", device);
+                let ready = nvm.spm_ready.map(|number| table(device)[number as usize]);
+                match device {
+                    Device::Attiny85 => assert_eq!(ready, None),
+                    _ => assert!(
+                        matches!(ready, Some("SPM_READY" | "SPM_RDY")),
+                        "{:?}",
+                        device
+                    ),
+                }
+            }
+        }
+    }
+}
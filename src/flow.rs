@@ -0,0 +1,232 @@
+use crate::{
+    callgraph::Function, cycles::cycles, device::Core, image::Image, instruction::Instruction,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Straight-line run of instructions with a single entry at `start`.
+pub struct Block {
+    pub start: u32,
+    pub instructions: Vec<(u32, Instruction)>,
+    /// Start addresses of the blocks the last instruction leads to.
+    pub successors: Vec<u32>,
+}
+
+/// Natural loop given by a back edge from `latch` to `header`.
+pub struct Loop {
+    pub header: u32,
+    pub latch: u32,
+    pub blocks: BTreeSet<u32>,
+}
+
+/// Splits a function into basic blocks keyed by their start address.
+pub fn blocks(image: &Image, function: &Function) -> BTreeMap<u32, Block> {
+    let mut leaders = BTreeSet::from([function.entry]);
+    for (&address, instruction) in &function.instructions {
+        let next = function.successors(image, address, instruction);
+        if next != [address + instruction.length() * 2] {
+            leaders.extend(next);
+        }
+    }
+    let mut blocks = BTreeMap::new();
+    for &start in &leaders {
+        let mut block = Block {
+            start,
+            instructions: vec![],
+            successors: vec![],
+        };
+        let mut address = start;
+        while let Some(&instruction) = function.instructions.get(&address) {
+            block.instructions.push((address, instruction));
+            let next = function.successors(image, address, &instruction);
+            match next[..] {
+                [following] if !leaders.contains(&following) => address = following,
+                _ => {
+                    block.successors = next;
+                    block.successors.dedup();
+                    break;
+                }
+            }
+        }
+        if !block.instructions.is_empty() {
+            blocks.insert(start, block);
+        }
+    }
+    let starts: BTreeSet<u32> = blocks.keys().copied().collect();
+    for block in blocks.values_mut() {
+        block
+            .successors
+            .retain(|successor| starts.contains(successor));
+    }
+    blocks
+}
+
+fn dominators(blocks: &BTreeMap<u32, Block>, entry: u32) -> BTreeMap<u32, BTreeSet<u32>> {
+    let all: BTreeSet<u32> = blocks.keys().copied().collect();
+    let mut predecessors: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for block in blocks.values() {
+        for successor in &block.successors {
+            predecessors
+                .entry(*successor)
+                .or_default()
+                .push(block.start);
+        }
+    }
+    let mut dominators: BTreeMap<u32, BTreeSet<u32>> = blocks
+        .keys()
+        .map(|&start| match start == entry {
+            true => (start, BTreeSet::from([start])),
+            false => (start, all.clone()),
+        })
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &start in blocks.keys().filter(|&&start| start != entry) {
+            let mut set = predecessors
+                .get(&start)
+                .into_iter()
+                .flatten()
+                .filter_map(|predecessor| dominators.get(predecessor))
+                .fold(None, |set: Option<BTreeSet<u32>>, other| match set {
+                    Some(set) => Some(set.intersection(other).copied().collect()),
+                    None => Some(other.clone()),
+                })
+                .unwrap_or_default();
+            set.insert(start);
+            if set != dominators[&start] {
+                dominators.insert(start, set);
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+
+/// Natural loops of the function, irreducible cycles are not reported.
+pub fn loops(blocks: &BTreeMap<u32, Block>, entry: u32) -> Vec<Loop> {
+    let dominators = dominators(blocks, entry);
+    let mut loops = vec![];
+    for block in blocks.values() {
+        for &header in &block.successors {
+            if !dominators[&block.start].contains(&header) {
+                continue;
+            }
+            let mut body = BTreeSet::from([header]);
+            let mut pending = vec![block.start];
+            while let Some(start) = pending.pop() {
+                if body.insert(start) {
+                    pending.extend(
+                        blocks
+                            .values()
+                            .filter(|other| other.successors.contains(&start))
+                            .map(|other| other.start),
+                    );
+                }
+            }
+            loops.push(Loop {
+                header,
+                latch: block.start,
+                blocks: body,
+            });
+        }
+    }
+    loops
+}
+
+/// Cycles spent in `block` when it continues at `successor`, a branch or
+/// skip counts as taken unless it falls through.
+pub fn edge_cycles(image: &Image, block: &Block, successor: Option<u32>, core: Core) -> u32 {
+    let last = block.instructions.len() - 1;
+    block
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(i, (address, instruction))| {
+            let next = address + instruction.length() * 2;
+            let cycles = cycles(instruction, core, image.length(next).unwrap_or(1));
+            match successor {
+                Some(successor) if i == last && successor != next => {
+                    cycles.taken.unwrap_or(cycles.cycles)
+                }
+                _ => cycles.cycles,
+            }
+        })
+        .sum()
+}
+
+/// Shortest and longest cycles of the block over all its exits.
+pub fn block_cycles(image: &Image, block: &Block, core: Core) -> (u32, u32) {
+    match block.successors.is_empty() {
+        true => {
+            let cycles = edge_cycles(image, block, None, core);
+            (cycles, cycles)
+        }
+        false => block
+            .successors
+            .iter()
+            .map(|&successor| edge_cycles(image, block, Some(successor), core))
+            .fold((u32::MAX, 0), |(min, max), cycles| {
+                (min.min(cycles), max.max(cycles))
+            }),
+    }
+}
+
+/// Shortest and longest cycles of one iteration from the header back to it,
+/// nested loops are counted as a single pass.
+pub fn loop_cycles(
+    image: &Image,
+    blocks: &BTreeMap<u32, Block>,
+    body: &Loop,
+    loops: &[Loop],
+    core: Core,
+) -> (u32, u32) {
+    let back_edges: BTreeSet<(u32, u32)> = loops.iter().map(|l| (l.latch, l.header)).collect();
+    fn walk(
+        start: u32,
+        image: &Image,
+        blocks: &BTreeMap<u32, Block>,
+        body: &Loop,
+        back_edges: &BTreeSet<(u32, u32)>,
+        core: Core,
+        memo: &mut BTreeMap<u32, Option<(u32, u32)>>,
+    ) -> Option<(u32, u32)> {
+        if let Some(known) = memo.get(&start) {
+            return *known;
+        }
+        memo.insert(start, None);
+        let block = &blocks[&start];
+        let mut result: Option<(u32, u32)> = None;
+        for &successor in &block.successors {
+            let cycles = edge_cycles(image, block, Some(successor), core);
+            let rest = match successor == body.header {
+                true if start == body.latch => Some((0, 0)),
+                true => None,
+                false
+                    if !body.blocks.contains(&successor)
+                        || back_edges.contains(&(start, successor)) =>
+                {
+                    None
+                }
+                false => walk(successor, image, blocks, body, back_edges, core, memo),
+            };
+            if let Some((min, max)) = rest {
+                result = Some(match result {
+                    Some((low, high)) => (low.min(min + cycles), high.max(max + cycles)),
+                    None => (min + cycles, max + cycles),
+                });
+            }
+        }
+        memo.insert(start, result);
+        result
+    }
+    walk(
+        body.header,
+        image,
+        blocks,
+        body,
+        &back_edges,
+        core,
+        &mut BTreeMap::new(),
+    )
+    .unwrap_or((0, 0))
+}
@@ -0,0 +1,351 @@
+use crate::simulator::{Event, Simulator, Stop};
+use std::{
+    collections::BTreeSet,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+/// Offsets of data memory and EEPROM in the address space of avr-gdb, flash
+/// starts at zero.
+const DATA: u32 = 0x800000;
+const EEPROM: u32 = 0x810000;
+
+/// Register numbers of avr-gdb after the 32 general purpose registers.
+const SREG: usize = 32;
+const SP: usize = 33;
+const PC: usize = 34;
+
+/// Instructions executed between two checks for an interrupt by the
+/// debugger.
+const POLL: u32 = 4096;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Address and length of the `addr,length` argument of a memory packet.
+fn range(text: &str) -> Option<(u32, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((
+        u32::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+/// Signal reported to the debugger for the reason the core stopped.
+fn signal(stop: Stop) -> u8 {
+    match stop {
+        Stop::Cycles | Stop::Break | Stop::Sleep => SIGTRAP,
+        Stop::Outside => SIGSEGV,
+        Stop::Undecoded(_) | Stop::Unsupported(_) => SIGILL,
+    }
+}
+
+/// One debugger connected to the simulator.
+struct Session {
+    stream: TcpStream,
+    simulator: Simulator,
+    breakpoints: BTreeSet<u32>,
+}
+
+/// Waits for avr-gdb to connect to `address`, `:1234` for a port on the
+/// loopback interface, and lets it debug `simulator` until it detaches.
+pub fn serve(address: &str, simulator: Simulator) -> io::Result<()> {
+    let address = match address.starts_with(':') {
+        true => format!("127.0.0.1{}", address),
+        false => address.to_string(),
+    };
+    let listener = TcpListener::bind(address)?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    eprintln!("debugger connected from {}", peer);
+    stream.set_nodelay(true)?;
+    Session {
+        stream,
+        simulator,
+        breakpoints: BTreeSet::new(),
+    }
+    .run()
+}
+
+impl Session {
+    fn byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Next packet with a valid checksum, acknowledged, or `None` when the
+    /// debugger closed the connection.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Acknowledgements and interrupts while stopped are skipped.
+            match self.byte() {
+                Ok(b'$') => {}
+                Ok(_) => continue,
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(error) => return Err(error),
+            }
+            let mut data = vec![];
+            loop {
+                match self.byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let sum = [self.byte()?, self.byte()?];
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                == Some(checksum(&data));
+            if !valid {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        write!(self.stream, "${}#{:02x}", data, checksum(data.as_bytes()))
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            match packet.as_str() {
+                "D" => return self.send("OK"),
+                "k" => return Ok(()),
+                _ => {}
+            }
+            let reply = self.handle(&packet)?;
+            self.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Reply to a packet, empty for packets that are not supported.
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => hex(&self.registers()),
+            "G" => match unhex(arguments) {
+                Some(bytes) => {
+                    for (number, value) in self.split(&bytes).into_iter().enumerate() {
+                        self.set_register(number, value);
+                    }
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(arguments, 16) {
+                Ok(number) if number <= PC => hex(&self.register(number)),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = arguments.split_once('=').and_then(|(number, value)| {
+                    Some((usize::from_str_radix(number, 16).ok()?, unhex(value)?))
+                });
+                match parsed {
+                    Some((number, value)) if number <= PC => {
+                        let mut bytes = [0; 4];
+                        for (byte, value) in bytes.iter_mut().zip(value) {
+                            *byte = value;
+                        }
+                        self.set_register(number, u32::from_le_bytes(bytes));
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match range(arguments) {
+                Some((address, length)) => {
+                    let bytes: Vec<u8> = (address..)
+                        .take(length)
+                        .map_while(|address| self.read(address))
+                        .collect();
+                    match bytes.is_empty() && length > 0 {
+                        true => "E01".to_string(),
+                        false => hex(&bytes),
+                    }
+                }
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = arguments
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((self::range(range)?, unhex(data)?)));
+                match parsed {
+                    Some(((address, _), bytes))
+                        if (address..)
+                            .zip(&bytes)
+                            .all(|(address, &byte)| self.write(address, byte)) =>
+                    {
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "c" | "s" => {
+                if let Ok(address) = u32::from_str_radix(arguments, 16) {
+                    self.simulator.pc = address;
+                }
+                format!("S{:02x}", self.resume(command == "s")?)
+            }
+            "Z" | "z" => {
+                let fields: Vec<&str> = arguments.split(',').collect();
+                match fields[..] {
+                    // Software and hardware breakpoints are the same here.
+                    ["0" | "1", address, _] => match u32::from_str_radix(address, 16) {
+                        Ok(address) => {
+                            match command {
+                                "Z" => self.breakpoints.insert(address),
+                                _ => self.breakpoints.remove(&address),
+                            };
+                            "OK".to_string()
+                        }
+                        Err(_) => "E01".to_string(),
+                    },
+                    _ => String::new(),
+                }
+            }
+            "H" => "OK".to_string(),
+            _ if packet.starts_with("qSupported") => "PacketSize=1000".to_string(),
+            _ if packet == "qAttached" => "1".to_string(),
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    /// Register `number` in the byte order and width avr-gdb expects.
+    fn register(&self, number: usize) -> Vec<u8> {
+        let simulator = &self.simulator;
+        match number {
+            SREG => vec![simulator.sreg],
+            SP => simulator.sp.to_le_bytes().to_vec(),
+            PC => simulator.pc.to_le_bytes().to_vec(),
+            number => vec![simulator.registers[number]],
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        (0..=PC).flat_map(|number| self.register(number)).collect()
+    }
+
+    /// Values of the registers in the `G` packet.
+    fn split(&self, bytes: &[u8]) -> Vec<u32> {
+        let mut values = vec![];
+        let mut rest = bytes;
+        for number in 0..=PC {
+            let width = self.register(number).len().min(rest.len());
+            let (value, tail) = rest.split_at(width);
+            let mut padded = [0; 4];
+            padded[..width].copy_from_slice(value);
+            values.push(u32::from_le_bytes(padded));
+            rest = tail;
+        }
+        values
+    }
+
+    fn set_register(&mut self, number: usize, value: u32) {
+        let simulator = &mut self.simulator;
+        match number {
+            SREG => simulator.sreg = value as u8,
+            SP => simulator.sp = value as u16,
+            PC => simulator.pc = value,
+            number => simulator.registers[number] = value as u8,
+        }
+    }
+
+    /// Byte at an address of avr-gdb, `None` outside of the memories.
+    fn read(&self, address: u32) -> Option<u8> {
+        let simulator = &self.simulator;
+        match address {
+            address if address < DATA => simulator.flash.get(address as usize).copied(),
+            address if address < EEPROM => {
+                let address = (address - DATA) as usize;
+                (address < simulator.data.len()).then(|| simulator.peek(address as u16))
+            }
+            address => simulator.eeprom.get((address - EEPROM) as usize).copied(),
+        }
+    }
+
+    fn write(&mut self, address: u32, value: u8) -> bool {
+        let simulator = &mut self.simulator;
+        let cell = match address {
+            address if address < DATA => simulator.flash.get_mut(address as usize),
+            address if address < EEPROM => {
+                let address = address - DATA;
+                if (address as usize) < simulator.data.len() {
+                    simulator.write(address as u16, value);
+                    return true;
+                }
+                None
+            }
+            address => simulator.eeprom.get_mut((address - EEPROM) as usize),
+        };
+        match cell {
+            Some(cell) => {
+                *cell = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Executes one instruction or, for a continue, until a breakpoint, a
+    /// stop of the core or an interrupt by the debugger. Returns the signal
+    /// to report.
+    fn resume(&mut self, step: bool) -> io::Result<u8> {
+        let mut executed = 0u32;
+        loop {
+            match self.simulator.step() {
+                Err(stop) => return Ok(signal(stop)),
+                // A sleeping core is not stopped by a single step.
+                Ok(Event::Idle) => {}
+                Ok(_) if step || self.breakpoints.contains(&self.simulator.pc) => {
+                    return Ok(SIGTRAP);
+                }
+                Ok(_) => {}
+            }
+            executed = executed.wrapping_add(1);
+            if executed.is_multiple_of(POLL) && self.interrupted()? {
+                return Ok(SIGINT);
+            }
+        }
+    }
+
+    /// Whether the debugger sent an interrupt or went away.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Ok(true),
+            Ok(_) => Ok(byte[0] == 0x03),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Calls firmware routines on the simulator the way avr-gcc code calls
+//! them, for `#[test]`s asserting on their results from Rust:
+//!
+//! ```no_run
+//! use hex::{device::Device, harness::Harness};
+//!
+//! let text = std::fs::read_to_string("firmware.hex").unwrap();
+//! let mut harness = Harness::from_hex(Device::Atmega328p.profile(), &text).unwrap();
+//! let sum = harness.call(0x100, &[1000u16.into(), 2345u16.into()]).unwrap();
+//! assert_eq!(sum.u16(), 3345);
+//! ```
+
+use crate::{
+    coverage::Coverage,
+    device::Profile,
+    image::Image,
+    record::{Record, RecordParseError},
+    simulator::{Event, Simulator, Stop},
+};
+
+/// An argument of a C function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Argument {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+}
+
+impl Argument {
+    /// Bytes of the value, least significant first.
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            Argument::U8(value) => vec![value],
+            Argument::U16(value) => value.to_le_bytes().to_vec(),
+            Argument::U32(value) => value.to_le_bytes().to_vec(),
+            Argument::U64(value) => value.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+impl From<u8> for Argument {
+    fn from(value: u8) -> Self {
+        Argument::U8(value)
+    }
+}
+
+impl From<i8> for Argument {
+    fn from(value: i8) -> Self {
+        Argument::U8(value as u8)
+    }
+}
+
+impl From<bool> for Argument {
+    fn from(value: bool) -> Self {
+        Argument::U8(value as u8)
+    }
+}
+
+impl From<u16> for Argument {
+    fn from(value: u16) -> Self {
+        Argument::U16(value)
+    }
+}
+
+impl From<i16> for Argument {
+    fn from(value: i16) -> Self {
+        Argument::U16(value as u16)
+    }
+}
+
+impl From<u32> for Argument {
+    fn from(value: u32) -> Self {
+        Argument::U32(value)
+    }
+}
+
+impl From<i32> for Argument {
+    fn from(value: i32) -> Self {
+        Argument::U32(value as u32)
+    }
+}
+
+impl From<f32> for Argument {
+    fn from(value: f32) -> Self {
+        Argument::U32(value.to_bits())
+    }
+}
+
+impl From<u64> for Argument {
+    fn from(value: u64) -> Self {
+        Argument::U64(value)
+    }
+}
+
+impl From<i64> for Argument {
+    fn from(value: i64) -> Self {
+        Argument::U64(value as u64)
+    }
+}
+
+/// The registers after a function returned, with its return value read as
+/// avr-gcc returns values of each width.
+#[derive(Debug, Clone, Copy)]
+pub struct Return {
+    pub registers: [u8; 32],
+    /// Cycles from the call to the return.
+    pub cycles: u64,
+}
+
+impl Return {
+    /// The `N` bytes ending with r25, least significant first.
+    fn bytes<const N: usize>(&self) -> [u8; N] {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&self.registers[26 - N..26]);
+        bytes
+    }
+
+    pub fn u8(&self) -> u8 {
+        self.registers[24]
+    }
+
+    pub fn i8(&self) -> i8 {
+        self.registers[24] as i8
+    }
+
+    pub fn bool(&self) -> bool {
+        self.registers[24] != 0
+    }
+
+    pub fn u16(&self) -> u16 {
+        u16::from_le_bytes(self.bytes())
+    }
+
+    pub fn i16(&self) -> i16 {
+        i16::from_le_bytes(self.bytes())
+    }
+
+    pub fn u32(&self) -> u32 {
+        u32::from_le_bytes(self.bytes())
+    }
+
+    pub fn i32(&self) -> i32 {
+        i32::from_le_bytes(self.bytes())
+    }
+
+    pub fn f32(&self) -> f32 {
+        f32::from_le_bytes(self.bytes())
+    }
+
+    pub fn u64(&self) -> u64 {
+        u64::from_le_bytes(self.bytes())
+    }
+
+    pub fn i64(&self) -> i64 {
+        i64::from_le_bytes(self.bytes())
+    }
+}
+
+/// A simulated device running calls into its firmware. The state carries
+/// over from one call to the next, as memory does on the device.
+pub struct Harness {
+    pub simulator: Simulator,
+    /// Cycles a call or [`Harness::run_to`] may take before it fails with
+    /// [`Stop::Cycles`].
+    pub max_cycles: u64,
+    /// Instructions executed by all calls so far.
+    pub coverage: Coverage,
+}
+
+impl Harness {
+    pub fn new(profile: Profile, image: &Image) -> Self {
+        Harness {
+            simulator: Simulator::new(profile, image),
+            max_cycles: 1_000_000,
+            coverage: Coverage::new(profile.flash),
+        }
+    }
+
+    /// A device programmed with the records of an Intel HEX file.
+    pub fn from_hex(profile: Profile, text: &str) -> Result<Self, RecordParseError> {
+        let records = text
+            .split_whitespace()
+            .map(Record::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Harness::new(profile, &Image::from_records(&records)))
+    }
+
+    /// Executes from the program counter until it reaches the byte address
+    /// `address`, such as the startup code up to `main`.
+    pub fn run_to(&mut self, address: u32) -> Result<(), Stop> {
+        let start = self.simulator.cycles;
+        while self.simulator.pc != address {
+            if self.simulator.cycles - start >= self.max_cycles {
+                return Err(Stop::Cycles);
+            }
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Calls the function at the byte address `function` and runs it until
+    /// it returns. Arguments are assigned to registers from r25 down to r8,
+    /// each starting at an even register, and the first that does not fit
+    /// and all after it are passed on the stack. r1 is cleared as the
+    /// compiler expects. The program counter is restored afterwards.
+    pub fn call(&mut self, function: u32, arguments: &[Argument]) -> Result<Return, Stop> {
+        let simulator = &mut self.simulator;
+        let (pc, sp) = (simulator.pc, simulator.sp);
+        let mut next = 26;
+        let mut stack = vec![];
+        for argument in arguments {
+            let bytes = argument.bytes();
+            let size = (bytes.len() + 1) & !1;
+            if stack.is_empty() && next >= 8 + size {
+                next -= size;
+                simulator.registers[next..next + bytes.len()].copy_from_slice(&bytes);
+            } else {
+                stack.extend(bytes);
+            }
+        }
+        simulator.registers[1] = 0;
+        // The first byte on the stack is the one right above the return
+        // address.
+        let arguments = simulator.sp.wrapping_sub(stack.len() as u16);
+        for (address, byte) in (arguments.wrapping_add(1)..).zip(stack) {
+            simulator.write(address, byte);
+        }
+        simulator.sp = arguments;
+        simulator.push_pc(0);
+        simulator.pc = function;
+        let start = simulator.cycles;
+        // Only the return of the function itself pops the stack back to
+        // where the return address was pushed.
+        while self.simulator.sp != arguments {
+            if self.simulator.cycles - start >= self.max_cycles {
+                return Err(Stop::Cycles);
+            }
+            self.step()?;
+        }
+        let simulator = &mut self.simulator;
+        simulator.sp = sp;
+        simulator.pc = pc;
+        Ok(Return {
+            registers: simulator.registers,
+            cycles: simulator.cycles - start,
+        })
+    }
+
+    fn step(&mut self) -> Result<(), Stop> {
+        let pc = self.simulator.pc;
+        if self.simulator.step()? == Event::Executed {
+            self.coverage.record(pc);
+        }
+        Ok(())
+    }
+
+    /// Bytes of data memory from `address`, as the program would see them.
+    pub fn memory(&self, address: u16, length: usize) -> Vec<u8> {
+        (address..)
+            .take(length)
+            .map(|address| self.simulator.peek(address))
+            .collect()
+    }
+
+    pub fn set_memory(&mut self, address: u16, bytes: &[u8]) {
+        for (address, byte) in (address..).zip(bytes) {
+            self.simulator.write(address, *byte);
+        }
+    }
+}
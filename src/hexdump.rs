@@ -0,0 +1,41 @@
+use std::fmt::Write;
+
+/// Bytes shown on one row.
+const ROW: u32 = 16;
+
+/// Rows of `bytes` loaded at `start` in the layout of `hexdump -C`: the
+/// address, sixteen bytes in two groups and their printable characters.
+/// Rows are aligned to sixteen bytes, positions outside of `bytes` are blank.
+pub fn rows(start: u32, bytes: &[u8]) -> String {
+    let end = start + bytes.len() as u32;
+    let mut output = String::new();
+    let mut row = start - start % ROW;
+    while row < end {
+        let mut hex = String::new();
+        let mut text = String::new();
+        for address in row..row + ROW {
+            if address == row + ROW / 2 {
+                hex.push(' ');
+            }
+            match (start..end)
+                .contains(&address)
+                .then(|| bytes[(address - start) as usize])
+            {
+                Some(byte) => {
+                    let _ = write!(hex, " {:02x}", byte);
+                    text.push(match byte {
+                        0x20..=0x7e => byte as char,
+                        _ => '.',
+                    });
+                }
+                None => {
+                    hex.push_str("   ");
+                    text.push(' ');
+                }
+            }
+        }
+        let _ = writeln!(output, "{:08x} {}  |{}|", row, hex, text);
+        row += ROW;
+    }
+    output
+}
@@ -0,0 +1,167 @@
+use crate::{
+    instruction::{DecodeError, Instruction},
+    record::{Index, Record},
+};
+use std::{collections::BTreeMap, ops::Range};
+
+/// Program memory assembled from all data records, with extended segment and
+/// linear address records applied.
+pub struct Image {
+    words: BTreeMap<u32, u16>,
+}
+
+/// Words are not loaded from this byte address on, which leaves room to
+/// step past the last instruction without overflowing.
+const LIMIT: u32 = 0xffff_0000;
+
+/// Address each record is loaded at, with extended segment and linear
+/// address records applied.
+pub fn bases(records: &[Record]) -> Vec<u32> {
+    let mut bases = vec![];
+    let mut base: u32 = 0;
+    for record in records {
+        if let Index::AddressSegment | Index::ExtendedAddress = record.index {
+            // Pairs hold the little-endian order of the data bytes,
+            // while the address itself is written big-endian.
+            let value = match record.data.first() {
+                Some(content) => u16::from_be_bytes([content.1, content.0]) as u32,
+                None => 0,
+            };
+            base = match record.index {
+                Index::AddressSegment => value << 4,
+                _ => value << 16,
+            };
+        }
+        bases.push(base + record.address as u32);
+    }
+    bases
+}
+
+/// Adds `data` from the byte address `base` to `bytes`, dropping what lies
+/// at or past `LIMIT`.
+fn load(bytes: &mut BTreeMap<u32, u8>, base: u32, data: impl IntoIterator<Item = u8>) {
+    for (i, byte) in data.into_iter().enumerate() {
+        match base.checked_add(i as u32) {
+            Some(address) if address < LIMIT => {
+                bytes.insert(address, byte);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Part of `range` inside `window`, `None` when they do not overlap.
+pub fn clip(range: Range<u32>, window: &Range<u32>) -> Option<Range<u32>> {
+    let clipped = range.start.max(window.start)..range.end.min(window.end);
+    (clipped.start < clipped.end).then_some(clipped)
+}
+
+impl Image {
+    /// Words of the data records. A record may start at an odd address,
+    /// the other half of a word no record fills is 0xff like erased flash.
+    pub fn from_records(records: &[Record]) -> Self {
+        let mut bytes = BTreeMap::new();
+        for (record, base) in records.iter().zip(bases(records)) {
+            if record.index == Index::Data {
+                load(&mut bytes, base, record.bytes());
+            }
+        }
+        Image::from_bytes(&bytes)
+    }
+
+    /// Words of `segments`, each a start address and the bytes from it, as
+    /// the assembler emits them.
+    pub fn from_segments(segments: &[(u32, Vec<u8>)]) -> Self {
+        let mut bytes = BTreeMap::new();
+        for (start, segment) in segments {
+            load(&mut bytes, *start, segment.iter().copied());
+        }
+        Image::from_bytes(&bytes)
+    }
+
+    /// Pairs loaded bytes into words.
+    fn from_bytes(bytes: &BTreeMap<u32, u8>) -> Self {
+        let mut words = BTreeMap::new();
+        for &address in bytes.keys() {
+            let even = address & !1;
+            let byte = |address| bytes.get(&address).copied().unwrap_or(0xff);
+            words.insert(even, u16::from_le_bytes([byte(even), byte(even + 1)]));
+        }
+        Image { words }
+    }
+
+    /// Program word at the byte `address`.
+    pub fn word(&self, address: u32) -> Option<u16> {
+        self.words.get(&address).copied()
+    }
+
+    /// Byte of program memory at `address`, words are stored little-endian.
+    pub fn byte(&self, address: u32) -> Option<u8> {
+        let word = self.word(address & !1)?.to_le_bytes();
+        Some(word[(address & 1) as usize])
+    }
+
+    /// Lowest and highest byte addresses of loaded words.
+    pub fn bounds(&self) -> Option<(u32, u32)> {
+        Some((
+            *self.words.first_key_value()?.0,
+            *self.words.last_key_value()?.0,
+        ))
+    }
+
+    /// Contiguous runs of loaded words as byte address ranges.
+    pub fn segments(&self) -> Vec<Range<u32>> {
+        let mut segments: Vec<Range<u32>> = vec![];
+        for &address in self.words.keys() {
+            match segments.last_mut() {
+                Some(segment) if segment.end == address => segment.end += 2,
+                _ => segments.push(address..address + 2),
+            }
+        }
+        segments
+    }
+
+    /// Contiguous runs of loaded words inside `window`.
+    pub fn segments_in(&self, window: &Range<u32>) -> Vec<Range<u32>> {
+        self.segments()
+            .into_iter()
+            .filter_map(|segment| clip(segment, window))
+            .collect()
+    }
+
+    /// Length in words of the instruction at the byte `address`, `None` if it
+    /// cannot be decoded.
+    pub fn length(&self, address: u32) -> Option<u32> {
+        match self.decode(address)? {
+            Ok(instruction) => Some(instruction.length()),
+            Err(_) => None,
+        }
+    }
+
+    /// Decodes the instruction at the byte `address`, `None` if nothing is
+    /// loaded there.
+    pub fn decode(&self, address: u32) -> Option<Result<Instruction, DecodeError>> {
+        Some(Instruction::decode(
+            self.word(address)?,
+            self.word(address + 2),
+        ))
+    }
+}
+
+#[cfg(test)]
+impl Image {
+    /// Program words, each segment loaded from its byte address, for tests
+    /// that write their programs as the words an assembler would emit.
+    pub fn from_words(segments: &[(u32, &[u16])]) -> Self {
+        let segments: Vec<(u32, Vec<u8>)> = segments
+            .iter()
+            .map(|&(start, words)| {
+                (
+                    start,
+                    words.iter().flat_map(|word| word.to_le_bytes()).collect(),
+                )
+            })
+            .collect();
+        Image::from_segments(&segments)
+    }
+}
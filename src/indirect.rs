@@ -0,0 +1,724 @@
+use crate::{callgraph::Function, image::Image, instruction::Instruction};
+use std::collections::BTreeMap;
+
+/// I/O addresses of the extended indirect and RAMPZ registers.
+const EIND: u8 = 0x3c;
+const RAMPZ: u8 = 0x3b;
+
+/// Entries read from a table whose length is not bounded by a compare.
+const MAX_ENTRIES: u32 = 256;
+
+/// Symbolic content of `Z` when it depends on an unknown index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Table {
+    /// `Z = scale * index + offset`.
+    Index { offset: u16, scale: u16 },
+    /// `Z` holds a word read from flash at `address + stride * index`.
+    Entries { address: u32, stride: u32 },
+}
+
+/// Low byte of a table entry read with `lpm rd, Z+`, the entry is complete
+/// once the high byte lands in `r31` and the low one is moved to `r30`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Read {
+    register: u8,
+    address: u32,
+    stride: u32,
+    high: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    registers: [Option<u8>; 32],
+    /// Address of the `ldi` each known register value comes from.
+    origins: [Option<u32>; 32],
+    carry: Option<bool>,
+    eind: Option<u8>,
+    rampz: Option<u8>,
+    z: Option<Table>,
+    read: Option<Read>,
+    subtrahend: Option<u8>,
+    shift: bool,
+    /// Immediate of the last compare, bounding a switch index.
+    bound: Option<u8>,
+}
+
+impl State {
+    fn new() -> Self {
+        State {
+            registers: [None; 32],
+            origins: [None; 32],
+            carry: None,
+            eind: None,
+            rampz: None,
+            z: None,
+            read: None,
+            subtrahend: None,
+            shift: false,
+            bound: None,
+        }
+    }
+
+    fn merge(&self, other: &State) -> State {
+        fn same<T: PartialEq + Copy>(a: Option<T>, b: Option<T>) -> Option<T> {
+            a.filter(|_| a == b)
+        }
+        let mut registers = [None; 32];
+        let mut origins = [None; 32];
+        for i in 0..32 {
+            registers[i] = same(self.registers[i], other.registers[i]);
+            origins[i] = same(self.origins[i], other.origins[i]);
+        }
+        State {
+            registers,
+            origins,
+            carry: same(self.carry, other.carry),
+            eind: same(self.eind, other.eind),
+            rampz: same(self.rampz, other.rampz),
+            z: same(self.z, other.z),
+            read: same(self.read, other.read),
+            subtrahend: same(self.subtrahend, other.subtrahend),
+            shift: self.shift && other.shift,
+            bound: same(self.bound, other.bound),
+        }
+    }
+
+    fn z(&self) -> Option<u16> {
+        Some(u16::from_le_bytes([
+            self.registers[30]?,
+            self.registers[31]?,
+        ]))
+    }
+
+    fn set_z(&mut self, z: Option<u16>) {
+        let bytes = z.map(u16::to_le_bytes);
+        self.registers[30] = bytes.map(|bytes| bytes[0]);
+        self.registers[31] = bytes.map(|bytes| bytes[1]);
+    }
+
+    fn pair(&self, d: u8) -> Option<u16> {
+        Some(u16::from_le_bytes([
+            self.registers[d as usize]?,
+            self.registers[d as usize + 1]?,
+        ]))
+    }
+}
+
+/// Instructions that leave the carry flag alone.
+fn keeps_carry(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Nop
+            | Instruction::Movw { .. }
+            | Instruction::Mov { .. }
+            | Instruction::Ldi { .. }
+            | Instruction::Ld { .. }
+            | Instruction::Ldd { .. }
+            | Instruction::St { .. }
+            | Instruction::Std { .. }
+            | Instruction::Lds { .. }
+            | Instruction::Sts { .. }
+            | Instruction::LpmR0
+            | Instruction::Lpm { .. }
+            | Instruction::ElpmR0
+            | Instruction::Elpm { .. }
+            | Instruction::Push { .. }
+            | Instruction::Pop { .. }
+            | Instruction::In { .. }
+            | Instruction::Out { .. }
+            | Instruction::Swap { .. }
+            | Instruction::Inc { .. }
+            | Instruction::Dec { .. }
+            | Instruction::And { .. }
+            | Instruction::Andi { .. }
+            | Instruction::Or { .. }
+            | Instruction::Ori { .. }
+            | Instruction::Eor { .. }
+            | Instruction::Bld { .. }
+            | Instruction::Bst { .. }
+            | Instruction::Cbi { .. }
+            | Instruction::Sbi { .. }
+            | Instruction::Sbic { .. }
+            | Instruction::Sbis { .. }
+            | Instruction::Sbrc { .. }
+            | Instruction::Sbrs { .. }
+            | Instruction::Rjmp { .. }
+            | Instruction::Jmp { .. }
+            | Instruction::Brbs { .. }
+            | Instruction::Brbc { .. }
+    )
+}
+
+/// Registers a callee may change according to the avr-gcc ABI.
+const CALL_USED: [u8; 13] = [0, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 30, 31];
+
+fn transfer(image: &Image, state: &State, address: u32, instruction: &Instruction) -> State {
+    let mut next = state.clone();
+    let registers = &state.registers;
+    let written = instruction.writes();
+    for &register in &written {
+        next.registers[register as usize] = None;
+        next.origins[register as usize] = None;
+    }
+    if !keeps_carry(instruction) {
+        next.carry = None;
+    }
+    let z_written = written.iter().any(|&register| register >= 30);
+    if z_written {
+        next.z = None;
+    }
+    next.subtrahend = None;
+    next.shift = false;
+    let byte = |address: u32, extended: bool| match extended {
+        true => state
+            .rampz
+            .and_then(|rampz| image.byte((rampz as u32) << 16 | address)),
+        false => image.byte(address),
+    };
+    match *instruction {
+        Instruction::Ldi { d, k } => {
+            next.registers[d as usize] = Some(k);
+            next.origins[d as usize] = Some(address);
+        }
+        Instruction::Mov { d, r } => {
+            next.registers[d as usize] = registers[r as usize];
+            next.origins[d as usize] = state.origins[r as usize];
+            if let Some(read) = state.read {
+                if d == 30 && r == read.register && read.high {
+                    next.z = Some(Table::Entries {
+                        address: read.address,
+                        stride: read.stride,
+                    });
+                }
+            }
+        }
+        Instruction::Movw { d, r } => {
+            next.registers[d as usize] = registers[r as usize];
+            next.registers[d as usize + 1] = registers[r as usize + 1];
+            next.origins[d as usize] = state.origins[r as usize];
+            next.origins[d as usize + 1] = state.origins[r as usize + 1];
+        }
+        Instruction::Eor { d, r } if d == r => next.registers[d as usize] = Some(0),
+        Instruction::Eor { d, r } | Instruction::And { d, r } | Instruction::Or { d, r } => {
+            next.registers[d as usize] =
+                registers[d as usize]
+                    .zip(registers[r as usize])
+                    .map(|(a, b)| match instruction {
+                        Instruction::Eor { .. } => a ^ b,
+                        Instruction::And { .. } => a & b,
+                        _ => a | b,
+                    })
+        }
+        Instruction::Andi { d, k } => {
+            next.registers[d as usize] = registers[d as usize].map(|a| a & k)
+        }
+        Instruction::Ori { d, k } => {
+            next.registers[d as usize] = registers[d as usize].map(|a| a | k)
+        }
+        Instruction::Inc { d } => {
+            next.registers[d as usize] = registers[d as usize].map(|a| a.wrapping_add(1))
+        }
+        Instruction::Dec { d } => {
+            next.registers[d as usize] = registers[d as usize].map(|a| a.wrapping_sub(1))
+        }
+        Instruction::Com { d } => next.registers[d as usize] = registers[d as usize].map(|a| !a),
+        Instruction::Swap { d } => {
+            next.registers[d as usize] = registers[d as usize].map(|a| a.rotate_left(4))
+        }
+        Instruction::Add { d, r } | Instruction::Adc { d, r } => {
+            let carry = match instruction {
+                Instruction::Add { .. } => Some(false),
+                _ => state.carry,
+            };
+            if let (Some(a), Some(b), Some(carry)) =
+                (registers[d as usize], registers[r as usize], carry)
+            {
+                let sum = a as u16 + b as u16 + carry as u16;
+                next.registers[d as usize] = Some(sum as u8);
+                next.carry = Some(sum > 0xff);
+            }
+            match (instruction, state.z) {
+                (Instruction::Add { d: 30, r: 30 }, Some(Table::Index { .. })) => {
+                    next.z = state.z;
+                    next.shift = true;
+                }
+                (Instruction::Adc { d: 31, r: 31 }, Some(Table::Index { offset, scale }))
+                    if state.shift =>
+                {
+                    // Past 16 bits of scale Z no longer indexes a table.
+                    next.z = scale.checked_mul(2).map(|scale| Table::Index {
+                        offset: offset.wrapping_mul(2),
+                        scale,
+                    })
+                }
+                _ => {}
+            }
+        }
+        Instruction::Sub { d, r }
+        | Instruction::Sbc { d, r }
+        | Instruction::Subi { d, k: r }
+        | Instruction::Sbci { d, k: r } => {
+            let subtrahend = match instruction {
+                Instruction::Sub { .. } | Instruction::Sbc { .. } => registers[r as usize],
+                _ => Some(r),
+            };
+            let borrow = match instruction {
+                Instruction::Sub { .. } | Instruction::Subi { .. } => Some(false),
+                _ => state.carry,
+            };
+            if let (Some(a), Some(b), Some(borrow)) = (registers[d as usize], subtrahend, borrow) {
+                let difference = a as i16 - b as i16 - borrow as i16;
+                next.registers[d as usize] = Some(difference as u8);
+                next.carry = Some(difference < 0);
+            }
+            // Switch tables are addressed by subtracting the negated table
+            // address from an index in Z.
+            match (instruction, registers[d as usize], state.z) {
+                (Instruction::Subi { d: 30, k }, None, _) => {
+                    next.subtrahend = Some(*k);
+                    next.z = state.z;
+                }
+                (Instruction::Sbci { d: 31, k }, _, z) => {
+                    if let Some(low) = state.subtrahend {
+                        let subtrahend = u16::from_le_bytes([low, *k]);
+                        next.z = Some(match z {
+                            Some(Table::Index { offset, scale }) => Table::Index {
+                                offset: offset.wrapping_sub(subtrahend),
+                                scale,
+                            },
+                            _ => Table::Index {
+                                offset: 0u16.wrapping_sub(subtrahend),
+                                scale: 1,
+                            },
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Instruction::Adiw { d, k } | Instruction::Sbiw { d, k } => {
+            let sum = |pair: u16| match instruction {
+                Instruction::Adiw { .. } => pair.wrapping_add(k as u16),
+                _ => pair.wrapping_sub(k as u16),
+            };
+            if let Some(pair) = state.pair(d) {
+                let bytes = sum(pair).to_le_bytes();
+                next.registers[d as usize] = Some(bytes[0]);
+                next.registers[d as usize + 1] = Some(bytes[1]);
+            }
+            if let (30, Some(Table::Index { offset, scale })) = (d, state.z) {
+                next.z = Some(Table::Index {
+                    offset: sum(offset),
+                    scale,
+                });
+            }
+        }
+        Instruction::LpmR0 | Instruction::ElpmR0 => {
+            let extended = *instruction == Instruction::ElpmR0;
+            next.registers[0] = state.z().and_then(|z| byte(z as u32, extended));
+        }
+        Instruction::Lpm { d, increment } | Instruction::Elpm { d, increment } => {
+            let extended = matches!(instruction, Instruction::Elpm { .. });
+            match (state.z(), state.z) {
+                (Some(z), _) => {
+                    next.registers[d as usize] = byte(z as u32, extended);
+                    if increment {
+                        next.set_z(Some(z.wrapping_add(1)));
+                    }
+                }
+                (None, Some(Table::Index { offset, scale })) => match (increment, state.read) {
+                    (true, _) => {
+                        next.read = Some(Read {
+                            register: d,
+                            address: offset as u32,
+                            stride: scale as u32,
+                            high: false,
+                        });
+                        next.z = Some(Table::Index {
+                            offset: offset.wrapping_add(1),
+                            scale,
+                        });
+                    }
+                    (false, Some(read)) if d == 31 && !read.high => {
+                        next.read = Some(Read { high: true, ..read });
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Instruction::Out { a: EIND, r } => next.eind = registers[r as usize],
+        Instruction::Out { a: RAMPZ, r } => next.rampz = registers[r as usize],
+        Instruction::Cpi { k, .. } => next.bound = Some(k),
+        Instruction::Call { .. }
+        | Instruction::Rcall { .. }
+        | Instruction::Icall
+        | Instruction::Eicall
+            if *instruction != Instruction::Rcall { k: 0 } =>
+        {
+            for register in CALL_USED {
+                next.registers[register as usize] = None;
+                next.origins[register as usize] = None;
+            }
+            next.z = None;
+            next.read = None;
+            next.bound = None;
+        }
+        _ => {}
+    }
+    if let Some(read) = next.read {
+        let moved = matches!(*instruction, Instruction::Mov { d: 30, r } if r == read.register);
+        let clobbered = written.contains(&read.register)
+            && !matches!(
+                instruction,
+                Instruction::Lpm { .. } | Instruction::Elpm { .. }
+            );
+        if moved || clobbered {
+            next.read = None;
+        }
+    }
+    next
+}
+
+/// Word addresses of the code the indirect instruction at `address` may
+/// reach with `state` on entry.
+fn targets(image: &Image, state: &State, instruction: &Instruction) -> Vec<u32> {
+    let extended = matches!(instruction, Instruction::Eijmp | Instruction::Eicall);
+    let high = match extended {
+        true => match state.eind {
+            Some(eind) => (eind as u32) << 16,
+            None => 0,
+        },
+        false => 0,
+    };
+    let count = state.bound.map_or(MAX_ENTRIES, |bound| bound as u32);
+    let code = |word: u32| -> Option<u32> {
+        let address = (high | word) * 2;
+        image.decode(address)?.ok().map(|_| address)
+    };
+    if let Some(z) = state.z() {
+        return code(z as u32).into_iter().collect();
+    }
+    let mut targets = vec![];
+    match state.z {
+        // A direct table of jumps, one word each.
+        Some(Table::Index { offset, scale: 1 }) => {
+            for i in 0..count {
+                let address = (high | (offset as u32 + i)) * 2;
+                match image.decode(address) {
+                    Some(Ok(Instruction::Rjmp { .. })) => targets.push(address),
+                    _ => break,
+                }
+            }
+        }
+        Some(Table::Entries { address, stride }) => {
+            for i in 0..count {
+                let entry = address + stride * i;
+                let word = match (image.byte(entry), image.byte(entry + 1)) {
+                    (Some(low), Some(high)) => u16::from_le_bytes([low, high]),
+                    _ => break,
+                };
+                match code(word as u32) {
+                    Some(target) if word != 0 => targets.push(target),
+                    _ => break,
+                }
+            }
+        }
+        _ => {}
+    }
+    targets
+}
+
+/// States on entry of each instruction of the function, `visit` sees every
+/// state before it is merged with the others reaching the same instruction.
+fn propagate(
+    image: &Image,
+    function: &Function,
+    mut visit: impl FnMut(u32, &Instruction, &State),
+) -> BTreeMap<u32, State> {
+    let mut states: BTreeMap<u32, State> = BTreeMap::new();
+    let mut pending = vec![(function.entry, State::new())];
+    while let Some((address, state)) = pending.pop() {
+        let Some(instruction) = function.instructions.get(&address) else {
+            continue;
+        };
+        let state = match states.get(&address) {
+            Some(known) => {
+                let merged = known.merge(&state);
+                if merged == *known {
+                    continue;
+                }
+                merged
+            }
+            None => state,
+        };
+        visit(address, instruction, &state);
+        let next = transfer(image, &state, address, instruction);
+        states.insert(address, state);
+        for successor in function.successors(image, address, instruction) {
+            pending.push((successor, next.clone()));
+        }
+    }
+    states
+}
+
+/// Resolves the targets of indirect jumps and calls of a function by
+/// propagating constants loaded into `Z`, directly, through `movw` or read
+/// from flash tables with `lpm`, as avr-gcc does for function pointers and
+/// switch tables.
+pub fn resolve(image: &Image, function: &Function) -> BTreeMap<u32, Vec<u32>> {
+    let states = propagate(image, function, |_, _, _| {});
+    let mut resolved = BTreeMap::new();
+    for (address, instruction) in &function.instructions {
+        if let Instruction::Ijmp | Instruction::Eijmp | Instruction::Icall | Instruction::Eicall =
+            instruction
+        {
+            let Some(state) = states.get(address) else {
+                continue;
+            };
+            let targets = targets(image, state, instruction);
+            if !targets.is_empty() {
+                resolved.insert(*address, targets);
+            }
+        }
+    }
+    resolved
+}
+
+/// Flash data read with `lpm` or `elpm`.
+#[derive(Debug, Default)]
+pub struct Reference {
+    /// `ldi` instructions that loaded the pointer into `Z`, none for tables
+    /// indexed at run time.
+    pub loads: Vec<u32>,
+    /// Size in bytes of a table bounded by a compare.
+    pub length: Option<u32>,
+}
+
+/// Flash data read by the function keyed by its address.
+pub fn references(image: &Image, function: &Function) -> BTreeMap<u32, Reference> {
+    let mut references: BTreeMap<u32, Reference> = BTreeMap::new();
+    propagate(image, function, |_, instruction, state| {
+        let extended = match instruction {
+            Instruction::LpmR0 | Instruction::Lpm { .. } => false,
+            Instruction::ElpmR0 | Instruction::Elpm { .. } => true,
+            _ => return,
+        };
+        let high = match (extended, state.rampz) {
+            (false, _) => 0,
+            (true, Some(rampz)) => (rampz as u32) << 16,
+            (true, None) => return,
+        };
+        match (state.z(), state.origins[30].zip(state.origins[31]), state.z) {
+            (Some(z), Some((low, high_load)), _) => {
+                let reference = references.entry(high | z as u32).or_default();
+                reference.loads.extend([low, high_load]);
+                reference.loads.sort_unstable();
+                reference.loads.dedup();
+            }
+            (None, _, Some(Table::Index { offset, scale }))
+                if scale > 1 && state.read.is_none() =>
+            {
+                let reference = references.entry(high | offset as u32).or_default();
+                reference.length = state.bound.map(|bound| bound as u32 * scale as u32);
+            }
+            _ => {}
+        }
+    });
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callgraph::CallGraph;
+
+    /// Reset code calling the function under test at 0x40.
+    const MAIN: (u32, &[u16]) = (
+        0x0,
+        &[
+            0x940c, 0x0002, // jmp main
+            // main:
+            0x940e, 0x0020, // call 0x40
+            0xcffd, // rjmp main
+        ],
+    );
+
+    /// Z = index + table for the table of `rjmp`s at 0x80.
+    const DISPATCH: &[u16] = &[
+        0x2fe8, // mov r30, r24
+        0xe0f0, // ldi r31, 0
+        0x5ce0, // subi r30, lo8(-(pm(table)))
+        0x4fff, // sbci r31, hi8(-(pm(table)))
+        0x9409, // ijmp
+        // done:
+        0x9508, // ret
+    ];
+
+    const TABLE: (u32, &[u16]) = (
+        0x80,
+        &[
+            // table:
+            0xc002, // rjmp case
+            0xc001, // rjmp case
+            0xc000, // rjmp case
+            // case:
+            0x9508, // ret
+        ],
+    );
+
+    /// Resolved indirect jumps and calls of the function at 0x40.
+    fn resolved(body: &[(u32, &[u16])]) -> BTreeMap<u32, Vec<u32>> {
+        let image = Image::from_words(&[&[MAIN], body].concat());
+        CallGraph::new(&image).functions[&0x40].resolved.clone()
+    }
+
+    /// The table dispatch bounded by the word of a `cpi r24` and `brsh done`.
+    fn switch(cpi: u16) -> BTreeMap<u32, Vec<u32>> {
+        resolved(&[(0x40, &[cpi, 0xf428]), (0x44, DISPATCH), TABLE])
+    }
+
+    #[test]
+    fn resolves_function_pointers() {
+        let body: &[(u32, &[u16])] = &[
+            (
+                0x40,
+                &[
+                    0xe4e0, // ldi r30, lo8(pm(first))
+                    0xe0f0, // ldi r31, hi8(pm(first))
+                    0x9509, // icall
+                    0xe481, // ldi r24, lo8(pm(second))
+                    0xe090, // ldi r25, hi8(pm(second))
+                    0x01fc, // movw r30, r24
+                    0x9409, // ijmp
+                ],
+            ),
+            (
+                0x80,
+                &[
+                    // first:
+                    0x9508, // ret
+                    // second:
+                    0x9508, // ret
+                ],
+            ),
+        ];
+        assert_eq!(
+            resolved(body),
+            BTreeMap::from([(0x44, vec![0x80]), (0x4c, vec![0x82])])
+        );
+    }
+
+    #[test]
+    fn resolves_tables_of_rjmp() {
+        // cpi r24, 3
+        assert_eq!(
+            switch(0x3083),
+            BTreeMap::from([(0x4c, vec![0x80, 0x82, 0x84])])
+        );
+    }
+
+    #[test]
+    fn bounds_tables_by_the_compare() {
+        // cpi r24, 2
+        assert_eq!(switch(0x3082), BTreeMap::from([(0x4c, vec![0x80, 0x82])]));
+        // cpi r24, 5, past the end of the table.
+        assert_eq!(
+            switch(0x3085),
+            BTreeMap::from([(0x4c, vec![0x80, 0x82, 0x84])])
+        );
+        // Without a compare the table ends at the first other instruction.
+        assert_eq!(
+            resolved(&[(0x40, DISPATCH), TABLE]),
+            BTreeMap::from([(0x48, vec![0x80, 0x82, 0x84])])
+        );
+    }
+
+    #[test]
+    fn resolves_tablejump2() {
+        // Z = index + table, doubled and read from flash by __tablejump2__.
+        let body: &[(u32, &[u16])] = &[
+            (
+                0x40,
+                &[
+                    0x3083, // cpi r24, 3
+                    0xf430, // brsh done
+                    0x2fe8, // mov r30, r24
+                    0xe0f0, // ldi r31, 0
+                    0x5ce0, // subi r30, lo8(-(pm(table)))
+                    0x4fff, // sbci r31, hi8(-(pm(table)))
+                    0x940c, 0x0060, // jmp __tablejump2__
+                    // done:
+                    0x9508, // ret
+                ],
+            ),
+            (
+                0x80,
+                &[
+                    // table:
+                    0x0050, 0x0051, 0x0052, // .word pm(zero), pm(one), pm(two)
+                ],
+            ),
+            (
+                0xa0,
+                &[
+                    // zero:
+                    0x9508, // ret
+                    // one:
+                    0x9508, // ret
+                    // two:
+                    0x9508, // ret
+                ],
+            ),
+            (
+                0xc0,
+                &[
+                    // __tablejump2__:
+                    0x0fee, // lsl r30
+                    0x1fff, // rol r31
+                    0x9005, // lpm r0, Z+
+                    0x91f4, // lpm r31, Z
+                    0x2de0, // mov r30, r0
+                    0x9409, // ijmp
+                ],
+            ),
+        ];
+        assert_eq!(
+            resolved(body),
+            BTreeMap::from([(0xca, vec![0xa0, 0xa2, 0xa4])])
+        );
+        // The table is read as flash data, three entries of two bytes.
+        let image = Image::from_words(&[&[MAIN], body].concat());
+        let graph = CallGraph::new(&image);
+        let table = &references(&image, &graph.functions[&0x40])[&0x80];
+        assert_eq!(table.length, Some(6));
+    }
+
+    #[test]
+    fn leaves_unknown_pointers_unresolved() {
+        let body: &[(u32, &[u16])] = &[(
+            0x40,
+            &[
+                0x01fc, // movw r30, r24
+                0x9509, // icall
+                0x9508, // ret
+            ],
+        )];
+        assert!(resolved(body).is_empty());
+    }
+
+    #[test]
+    fn gives_up_on_scales_past_16_bits() {
+        let index = [
+            0x2fe8, // mov r30, r24
+            0xe0f0, // ldi r31, 0
+            0x50e0, // subi r30, 0
+            0x40f0, // sbci r31, 0
+        ];
+        // lsl r30, rol r31
+        let shifts = [0x0fee, 0x1fff].repeat(16);
+        // ijmp
+        let code = [&index[..], &shifts, &[0x9409]].concat();
+        assert!(resolved(&[(0x40, &code)]).is_empty());
+    }
+}
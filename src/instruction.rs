@@ -0,0 +1,456 @@
+use crate::{
+    color::{Palette, Style},
+    opcodes,
+    symbols::Symbols,
+};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Pointer {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Plain,
+    PostIncrement,
+    PreDecrement,
+}
+
+/// A single decoded AVR instruction.
+///
+/// Register fields hold the actual register number (`d + 16` and similar
+/// offsets are already applied), `jmp`/`call` hold word addresses and the
+/// relative jumps hold signed word offsets, just like the datasheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,
+    Movw { d: u8, r: u8 },
+    Muls { d: u8, r: u8 },
+    Mulsu { d: u8, r: u8 },
+    Fmul { d: u8, r: u8 },
+    Fmuls { d: u8, r: u8 },
+    Fmulsu { d: u8, r: u8 },
+    Cpc { d: u8, r: u8 },
+    Sbc { d: u8, r: u8 },
+    Add { d: u8, r: u8 },
+    Cpse { d: u8, r: u8 },
+    Cp { d: u8, r: u8 },
+    Sub { d: u8, r: u8 },
+    Adc { d: u8, r: u8 },
+    And { d: u8, r: u8 },
+    Eor { d: u8, r: u8 },
+    Or { d: u8, r: u8 },
+    Mov { d: u8, r: u8 },
+    Mul { d: u8, r: u8 },
+    Cpi { d: u8, k: u8 },
+    Sbci { d: u8, k: u8 },
+    Subi { d: u8, k: u8 },
+    Ori { d: u8, k: u8 },
+    Andi { d: u8, k: u8 },
+    Ldi { d: u8, k: u8 },
+    Ld { d: u8, pointer: Pointer, mode: Mode },
+    Ldd { d: u8, pointer: Pointer, q: u8 },
+    St { pointer: Pointer, mode: Mode, r: u8 },
+    Std { pointer: Pointer, q: u8, r: u8 },
+    Lds { d: u8, k: u16 },
+    Sts { k: u16, r: u8 },
+    LpmR0,
+    Lpm { d: u8, increment: bool },
+    ElpmR0,
+    Elpm { d: u8, increment: bool },
+    Spm,
+    SpmZ,
+    Xch { r: u8 },
+    Las { r: u8 },
+    Lac { r: u8 },
+    Lat { r: u8 },
+    Pop { d: u8 },
+    Push { r: u8 },
+    Com { d: u8 },
+    Neg { d: u8 },
+    Swap { d: u8 },
+    Inc { d: u8 },
+    Asr { d: u8 },
+    Lsr { d: u8 },
+    Ror { d: u8 },
+    Dec { d: u8 },
+    Bset { s: u8 },
+    Bclr { s: u8 },
+    Des { k: u8 },
+    Ijmp,
+    Eijmp,
+    Icall,
+    Eicall,
+    Ret,
+    Reti,
+    Sleep,
+    Break,
+    Wdr,
+    Jmp { k: u32 },
+    Call { k: u32 },
+    Adiw { d: u8, k: u8 },
+    Sbiw { d: u8, k: u8 },
+    Cbi { a: u8, b: u8 },
+    Sbic { a: u8, b: u8 },
+    Sbi { a: u8, b: u8 },
+    Sbis { a: u8, b: u8 },
+    In { d: u8, a: u8 },
+    Out { a: u8, r: u8 },
+    Rjmp { k: i16 },
+    Rcall { k: i16 },
+    Brbs { s: u8, k: i8 },
+    Brbc { s: u8, k: i8 },
+    Bld { d: u8, b: u8 },
+    Bst { d: u8, b: u8 },
+    Sbrc { r: u8, b: u8 },
+    Sbrs { r: u8, b: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The word does not encode any known instruction.
+    UnexpectedCommand(u16),
+    /// A 32-bit instruction whose second word is missing.
+    MissingExtension(u16),
+}
+
+/// A typed operand of an instruction, as it is printed in the listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Operand {
+    Register(u8),
+    /// A register pair given by its low register, printed as `r25:24`.
+    Pair(u8),
+    Immediate(u8),
+    Bit(u8),
+    Io(u8),
+    Data(u16),
+    /// Absolute byte address in program memory.
+    Program(u32),
+    /// Byte offset relative to the next instruction.
+    Relative(i32),
+    Indirect(Pointer, Mode),
+    Displacement(Pointer, u8),
+}
+
+impl Instruction {
+    /// Decodes the instruction starting with `word`, `next` is the following
+    /// program word which is consumed by 32-bit instructions only.
+    pub fn decode(word: u16, next: Option<u16>) -> Result<Self, DecodeError> {
+        opcodes::decode(word, next)
+    }
+
+    /// Program words of the instruction, the inverse of [`Self::decode`].
+    /// Operands are cut to the width of their fields, so one out of range
+    /// yields the words of another instruction, which decoding them again
+    /// reveals.
+    pub fn encode(&self) -> Vec<u16> {
+        let (opcode, values) = opcodes::find(self);
+        opcode.place(&values)
+    }
+
+    /// Size of the instruction in 16-bit words.
+    pub fn length(&self) -> u32 {
+        opcodes::find(self).0.words()
+    }
+
+    /// Whether the instruction skips the next one when its condition holds.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Cpse { .. }
+                | Instruction::Sbic { .. }
+                | Instruction::Sbis { .. }
+                | Instruction::Sbrc { .. }
+                | Instruction::Sbrs { .. }
+        )
+    }
+
+    /// Byte address execution continues at when the skip is performed,
+    /// `skipped` is the length in words of the following instruction.
+    pub fn skip_target(&self, address: u32, skipped: u32) -> Option<u32> {
+        match self.is_skip() {
+            true => Some(address + (self.length() + skipped) * 2),
+            false => None,
+        }
+    }
+
+    /// Byte address of the jump, branch or call target for the instruction
+    /// located at `address`.
+    pub fn target(&self, address: u32) -> Option<u32> {
+        let relative = |k: i32| address.wrapping_add_signed(k * 2 + 2);
+        match *self {
+            Instruction::Jmp { k } | Instruction::Call { k } => Some(k * 2),
+            Instruction::Rjmp { k } | Instruction::Rcall { k } => Some(relative(k as i32)),
+            Instruction::Brbs { k, .. } | Instruction::Brbc { k, .. } => Some(relative(k as i32)),
+            _ => None,
+        }
+    }
+
+    /// General purpose registers the instruction writes to.
+    pub fn writes(&self) -> Vec<u8> {
+        let pointer = |pointer: Pointer| match pointer {
+            Pointer::X => [26, 27],
+            Pointer::Y => [28, 29],
+            Pointer::Z => [30, 31],
+        };
+        match *self {
+            Instruction::Movw { d, .. }
+            | Instruction::Adiw { d, .. }
+            | Instruction::Sbiw { d, .. } => {
+                vec![d, d + 1]
+            }
+            Instruction::Muls { .. }
+            | Instruction::Mulsu { .. }
+            | Instruction::Fmul { .. }
+            | Instruction::Fmuls { .. }
+            | Instruction::Fmulsu { .. }
+            | Instruction::Mul { .. } => vec![0, 1],
+            Instruction::Sbc { d, .. }
+            | Instruction::Add { d, .. }
+            | Instruction::Sub { d, .. }
+            | Instruction::Adc { d, .. }
+            | Instruction::And { d, .. }
+            | Instruction::Eor { d, .. }
+            | Instruction::Or { d, .. }
+            | Instruction::Mov { d, .. }
+            | Instruction::Sbci { d, .. }
+            | Instruction::Subi { d, .. }
+            | Instruction::Ori { d, .. }
+            | Instruction::Andi { d, .. }
+            | Instruction::Ldi { d, .. }
+            | Instruction::Ldd { d, .. }
+            | Instruction::Lds { d, .. }
+            | Instruction::Pop { d }
+            | Instruction::Com { d }
+            | Instruction::Neg { d }
+            | Instruction::Swap { d }
+            | Instruction::Inc { d }
+            | Instruction::Asr { d }
+            | Instruction::Lsr { d }
+            | Instruction::Ror { d }
+            | Instruction::Dec { d }
+            | Instruction::In { d, .. }
+            | Instruction::Bld { d, .. } => vec![d],
+            Instruction::Ld {
+                d,
+                pointer: p,
+                mode,
+            } => match mode {
+                Mode::Plain => vec![d],
+                _ => [&[d][..], &pointer(p)[..]].concat(),
+            },
+            Instruction::St {
+                pointer: p, mode, ..
+            } => match mode {
+                Mode::Plain => vec![],
+                _ => pointer(p).to_vec(),
+            },
+            Instruction::Lpm { d, increment } | Instruction::Elpm { d, increment } => {
+                match increment {
+                    true => vec![d, 30, 31],
+                    false => vec![d],
+                }
+            }
+            Instruction::LpmR0 | Instruction::ElpmR0 => vec![0],
+            Instruction::SpmZ => vec![30, 31],
+            Instruction::Xch { r }
+            | Instruction::Las { r }
+            | Instruction::Lac { r }
+            | Instruction::Lat { r } => vec![r],
+            _ => vec![],
+        }
+    }
+
+    /// Mnemonic of the instruction, `overloads` replaces common commands
+    /// with their private forms such as `clr` or `breq`.
+    pub fn mnemonic(&self, overloads: bool) -> &'static str {
+        self.spelling(overloads).0
+    }
+
+    /// Operands in the order they are printed, matching [`Self::mnemonic`].
+    pub fn operands(&self, overloads: bool) -> Vec<Operand> {
+        self.spelling(overloads).1
+    }
+
+    /// Mnemonic and operands, those of the first alias that applies when
+    /// `overloads` is set.
+    fn spelling(&self, overloads: bool) -> (&'static str, Vec<Operand>) {
+        let (opcode, values) = opcodes::find(self);
+        let operands = opcode.operands(&values);
+        opcodes::ALIASES
+            .iter()
+            .filter(|alias| overloads && alias.of == opcode.mnemonic)
+            .find_map(|alias| Some((alias.mnemonic, alias.applies(&operands)?)))
+            .unwrap_or((opcode.mnemonic, operands))
+    }
+
+    /// Formats the instruction located at `address` as a listing line,
+    /// `skipped` is the length of the following instruction when known.
+    pub fn display(&self, address: u32, overloads: bool, skipped: Option<u32>) -> Listing<'_> {
+        Listing {
+            instruction: self,
+            address,
+            overloads,
+            skipped,
+            targets: &[],
+            comment: None,
+            palette: Palette::default(),
+            symbols: None,
+        }
+    }
+}
+
+impl fmt::Display for Pointer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pointer::X => write!(f, "X"),
+            Pointer::Y => write!(f, "Y"),
+            Pointer::Z => write!(f, "Z"),
+        }
+    }
+}
+
+impl Operand {
+    pub fn style(&self) -> Style {
+        match self {
+            Operand::Register(_)
+            | Operand::Pair(_)
+            | Operand::Indirect(..)
+            | Operand::Displacement(..) => Style::Register,
+            Operand::Immediate(_) | Operand::Bit(_) | Operand::Io(_) | Operand::Data(_) => {
+                Style::Immediate
+            }
+            Operand::Program(_) | Operand::Relative(_) => Style::Address,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Register(d) => write!(f, "r{}", d),
+            Operand::Pair(d) => write!(f, "r{}:{}", d + 1, d),
+            Operand::Immediate(k) => write!(f, "{:#x}", k),
+            Operand::Bit(b) => write!(f, "{}", b),
+            Operand::Io(a) => write!(f, "{:#x}", a),
+            Operand::Data(k) => write!(f, "{:#x}", k),
+            Operand::Program(k) => write!(f, "{:#x}", k),
+            Operand::Relative(k) => write!(f, ".{:+}", k),
+            Operand::Indirect(pointer, Mode::Plain) => write!(f, "{}", pointer),
+            Operand::Indirect(pointer, Mode::PostIncrement) => write!(f, "{}+", pointer),
+            Operand::Indirect(pointer, Mode::PreDecrement) => write!(f, "-{}", pointer),
+            Operand::Displacement(pointer, q) => write!(f, "{}+{}", pointer, q),
+        }
+    }
+}
+
+/// Listing line of an instruction, see [`Instruction::display`].
+pub struct Listing<'a> {
+    instruction: &'a Instruction,
+    address: u32,
+    overloads: bool,
+    skipped: Option<u32>,
+    targets: &'a [u32],
+    comment: Option<&'a str>,
+    palette: Palette,
+    symbols: Option<&'a Symbols>,
+}
+
+impl<'a> Listing<'a> {
+    /// Resolved targets of an indirect jump or call.
+    pub fn targets(self, targets: &'a [u32]) -> Self {
+        Listing { targets, ..self }
+    }
+
+    /// Free text appended after the computed targets.
+    pub fn comment(self, comment: Option<&'a str>) -> Self {
+        Listing { comment, ..self }
+    }
+
+    pub fn palette(self, palette: Palette) -> Self {
+        Listing { palette, ..self }
+    }
+
+    /// Names printed in place of the addresses they are defined for.
+    pub fn symbols(self, symbols: &'a Symbols) -> Self {
+        Listing {
+            symbols: Some(symbols),
+            ..self
+        }
+    }
+
+    /// Symbol of an operand addressing code or data, if one is defined there.
+    fn symbol(&self, operand: &Operand) -> Option<&'a str> {
+        let symbols = self.symbols?;
+        let name = match *operand {
+            Operand::Program(_) | Operand::Relative(_) => {
+                symbols.code.get(&self.instruction.target(self.address)?)
+            }
+            Operand::Data(k) => symbols.data.get(&(k as u32)),
+            _ => None,
+        };
+        name.map(String::as_str)
+    }
+
+    /// Address in a comment, followed by the symbol defined there.
+    fn address(&self, address: u32) -> String {
+        match self.symbols.and_then(|symbols| symbols.code.get(&address)) {
+            Some(name) => format!("{:#x} <{}>", address, name),
+            None => format!("{:#x}", address),
+        }
+    }
+}
+
+impl fmt::Display for Listing<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let palette = self.palette;
+        write!(
+            f,
+            "{}",
+            palette.paint(Style::Mnemonic, self.instruction.mnemonic(self.overloads))
+        )?;
+        for (i, operand) in self.instruction.operands(self.overloads).iter().enumerate() {
+            let operand = match self.symbol(operand) {
+                Some(name) => palette.paint(Style::Label, name.to_string()),
+                None => palette.paint(operand.style(), operand.to_string()),
+            };
+            match i {
+                0 => write!(f, " {}", operand)?,
+                _ => write!(f, ", {}", operand)?,
+            }
+        }
+        let mut comment = String::new();
+        let skip = |skipped| self.instruction.skip_target(self.address, skipped);
+        if let Some(target) = self.instruction.target(self.address) {
+            comment += &format!(" ; {:#x}", target);
+        } else if let Some(target) = self.skipped.and_then(skip) {
+            comment += &format!(" ; {:#x}", target);
+        } else if let (Some(short), Some(long)) = (skip(1), skip(2)) {
+            comment += &format!(" ; {:#x} (or {:#x})", short, long);
+        }
+        for operand in self.instruction.operands(self.overloads) {
+            if let (Operand::Data(k), Some(_)) = (operand, self.symbol(&operand)) {
+                comment += &format!(" ; {:#x}", k);
+            }
+        }
+        for (i, target) in self.targets.iter().enumerate() {
+            match i {
+                0 => comment += &format!(" ; {}", self.address(*target)),
+                _ => comment += &format!(", {}", self.address(*target)),
+            }
+        }
+        if let Some(text) = self.comment {
+            comment += &format!(" ; {}", text);
+        }
+        if !comment.is_empty() {
+            write!(f, "{}", palette.paint(Style::Comment, comment))?;
+        }
+        Ok(())
+    }
+}
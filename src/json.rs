@@ -0,0 +1,121 @@
+use crate::{
+    data::Data,
+    image::{self, Image},
+    instruction::Operand,
+    listing::{self, Content},
+    record::{Index, Record},
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, ops::Range};
+
+#[derive(Serialize)]
+struct Program {
+    records: Vec<Header>,
+}
+
+/// Fields of [`Record`] followed by the lines decoded from its data.
+#[derive(Serialize)]
+struct Header {
+    /// Number of data bytes.
+    size: usize,
+    address: u16,
+    index: String,
+    /// Data bytes in the order of the record.
+    data: Vec<u8>,
+    lines: Vec<Line>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Line {
+    Instruction {
+        address: u32,
+        words: Vec<u16>,
+        mnemonic: &'static str,
+        operands: Vec<Operand>,
+        /// Length in words.
+        length: u32,
+        /// Branch, jump, call or exact skip target.
+        target: Option<u32>,
+        /// Resolved targets of an indirect jump or call.
+        targets: Vec<u32>,
+    },
+    Data {
+        address: u32,
+        directive: &'static str,
+        bytes: Vec<u8>,
+        text: Option<String>,
+    },
+}
+
+/// The decoded program as a JSON document, lines are limited to `window`.
+pub fn render(
+    records: &[Record],
+    image: &Image,
+    data: &Data,
+    resolved: &BTreeMap<u32, Vec<u32>>,
+    overloads: bool,
+    window: &Range<u32>,
+) -> Result<String, serde_json::Error> {
+    let mut program = Program { records: vec![] };
+    for (record, base) in records.iter().zip(image::bases(records)) {
+        let mut header = Header {
+            size: record.size,
+            address: record.address,
+            index: format!("{:?}", record.index),
+            data: record.bytes(),
+            lines: vec![],
+        };
+        let range = base..base.saturating_add(record.size as u32);
+        let range = match record.index {
+            Index::Data => match image::clip(range, window) {
+                Some(range) => Some(range),
+                None => continue,
+            },
+            _ => None,
+        };
+        if let Some(range) = range {
+            for line in listing::sweep(image, data, range) {
+                let address = line.address;
+                header.lines.push(match line.content {
+                    Content::Instruction {
+                        instruction,
+                        skipped,
+                    } => Line::Instruction {
+                        address,
+                        words: (0..instruction.length())
+                            .filter_map(|i| image.word(address + i * 2))
+                            .collect(),
+                        mnemonic: instruction.mnemonic(overloads),
+                        operands: instruction.operands(overloads),
+                        length: instruction.length(),
+                        target: instruction.target(address).or_else(|| {
+                            skipped.and_then(|skipped| instruction.skip_target(address, skipped))
+                        }),
+                        targets: resolved.get(&address).cloned().unwrap_or_default(),
+                    },
+                    Content::String(text) => Line::Data {
+                        address,
+                        directive: ".asciz",
+                        text: Some(String::from_utf8_lossy(&text).into_owned()),
+                        bytes: text,
+                    },
+                    Content::Bytes(bytes) => Line::Data {
+                        address,
+                        directive: ".byte",
+                        bytes,
+                        text: None,
+                    },
+                    Content::Word(word) => Line::Data {
+                        address,
+                        directive: ".word",
+                        bytes: word.to_le_bytes().to_vec(),
+                        text: None,
+                    },
+                });
+            }
+        }
+        program.records.push(header);
+    }
+    serde_json::to_string_pretty(&program)
+}
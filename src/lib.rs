@@ -0,0 +1,35 @@
+//! Disassembler and instruction-set simulator for AVR microcontrollers.
+//!
+//! The `hex` binary is built on these modules. [`harness`] calls firmware
+//! routines on the simulator from Rust tests.
+
+pub mod annotations;
+pub mod assembler;
+pub mod bits;
+pub mod callgraph;
+pub mod color;
+pub mod coverage;
+pub mod cycles;
+pub mod data;
+pub mod device;
+pub mod flow;
+pub mod gdb;
+pub mod harness;
+pub mod hexdump;
+pub mod image;
+pub mod indirect;
+pub mod instruction;
+pub mod json;
+pub mod lines;
+pub mod listing;
+pub mod nvm;
+pub mod objdump;
+pub mod opcodes;
+pub mod peripherals;
+pub mod profiler;
+pub mod reassemble;
+pub mod record;
+pub mod simulator;
+pub mod stack;
+pub mod symbols;
+pub mod trace;
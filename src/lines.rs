@@ -0,0 +1,64 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+/// Source lines of flash addresses, read from the DWARF line table that
+/// `avr-objdump --dwarf=decodedline` prints for an ELF file.
+#[derive(Debug, Default)]
+pub struct Lines {
+    /// File and line of the instructions from each address on, `None` after
+    /// the end of a sequence.
+    pub rows: BTreeMap<u32, Option<(String, u32)>>,
+}
+
+impl Lines {
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        self.parse(&fs::read_to_string(path)?);
+        Ok(())
+    }
+
+    /// Adds the rows `file line address` of the decoded line table. A `-`
+    /// line ends a sequence. File names are completed with the path of the
+    /// `CU: path:` or `path:[++]` heading they fall under when it names the
+    /// same file.
+    pub fn parse(&mut self, text: &str) {
+        let mut heading = String::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(path) = line
+                .strip_suffix(":[++]")
+                .or_else(|| line.strip_prefix("CU: ")?.strip_suffix(':'))
+            {
+                heading = path.to_string();
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (file, number, address) = match fields[..] {
+                [file, number, address, ..] => match address.strip_prefix("0x") {
+                    Some(address) => match u32::from_str_radix(address, 16) {
+                        Ok(address) => (file, number, address),
+                        Err(_) => continue,
+                    },
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let row = match number.parse() {
+                Ok(number) => {
+                    let file = match Path::new(&heading).file_name() {
+                        Some(name) if name == file => heading.clone(),
+                        _ => file.to_string(),
+                    };
+                    Some((file, number))
+                }
+                Err(_) if number == "-" => None,
+                Err(_) => continue,
+            };
+            self.rows.insert(address, row);
+        }
+    }
+
+    /// File and line of the instruction at `address`.
+    pub fn find(&self, address: u32) -> Option<(&str, u32)> {
+        let (_, row) = self.rows.range(..=address).next_back()?;
+        row.as_ref().map(|(file, line)| (file.as_str(), *line))
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{
+    data::{Data, Kind},
+    image::Image,
+    instruction::Instruction,
+};
+use std::ops::Range;
+
+/// Bytes of a table shown on one `.byte` line.
+const BYTES_PER_LINE: u32 = 8;
+
+pub enum Content {
+    /// `skipped` is the length of the following instruction when known.
+    Instruction {
+        instruction: Instruction,
+        skipped: Option<u32>,
+    },
+    /// Text of a null-terminated string, without the terminator.
+    String(Vec<u8>),
+    Bytes(Vec<u8>),
+    /// A word that does not decode to an instruction.
+    Word(u16),
+}
+
+pub struct Line {
+    pub address: u32,
+    pub content: Content,
+}
+
+impl Line {
+    /// Number of bytes the line covers.
+    pub fn size(&self) -> u32 {
+        match &self.content {
+            Content::Instruction { instruction, .. } => instruction.length() * 2,
+            Content::String(text) => text.len() as u32 + 1,
+            Content::Bytes(bytes) => bytes.len() as u32,
+            Content::Word(_) => 2,
+        }
+    }
+}
+
+/// Walks the loaded `range` linearly, decoding instructions except where
+/// `data` was detected and rendering words that do not decode as `.word`.
+pub fn sweep(image: &Image, data: &Data, range: Range<u32>) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut address = range.start;
+    while address < range.end {
+        let bytes = |range: Range<u32>| -> Vec<u8> {
+            range.filter_map(|address| image.byte(address)).collect()
+        };
+        if let Some(region) = data.region(address) {
+            let end = region.end.min(range.end);
+            match region.kind {
+                // A string is only shown as one when its terminator is in range.
+                Kind::String if region.end <= range.end => lines.push(Line {
+                    address,
+                    content: Content::String(bytes(address..end - 1)),
+                }),
+                Kind::String | Kind::Table => {
+                    for start in (address..end).step_by(BYTES_PER_LINE as usize) {
+                        lines.push(Line {
+                            address: start,
+                            content: Content::Bytes(bytes(
+                                start..(start + BYTES_PER_LINE).min(end),
+                            )),
+                        });
+                    }
+                }
+            }
+            address = end;
+            continue;
+        }
+        // Code is word aligned, data may end anywhere.
+        let limit = data.next(address).unwrap_or(range.end).min(range.end);
+        if address % 2 == 1 || limit - address < 2 {
+            lines.push(Line {
+                address,
+                content: Content::Bytes(bytes(address..address + 1)),
+            });
+            address += 1;
+            continue;
+        }
+        let content = match image.decode(address) {
+            Some(Ok(instruction)) if address + instruction.length() * 2 <= limit => {
+                let next = address + instruction.length() * 2;
+                let skipped = match image.decode(next) {
+                    Some(Ok(skipped)) if data.region(next).is_none() => Some(skipped.length()),
+                    _ => None,
+                };
+                Content::Instruction {
+                    instruction,
+                    skipped,
+                }
+            }
+            _ => Content::Word(image.word(address).unwrap_or_default()),
+        };
+        let line = Line { address, content };
+        address += line.size();
+        lines.push(line);
+    }
+    lines
+}
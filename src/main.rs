@@ -1,13 +1,131 @@
-use bitmatch::bitmatch;
-use clap::Parser;
+use hex::{
+    annotations, assembler, bits, callgraph, color, coverage, cycles, data, device, flow, gdb,
+    hexdump, image, json, lines, listing, objdump, opcodes, peripherals, profiler, reassemble,
+    record, simulator, stack, symbols, trace,
+};
+
+use annotations::Annotations;
+use callgraph::CallGraph;
+use clap::{Parser, Subcommand, ValueEnum};
+use color::{Palette, Style, When};
+use coverage::Coverage;
+use data::Data;
+use device::{Core, Device, Profile};
+use image::Image;
+use lines::Lines;
+use listing::Content;
+use peripherals::{Input, Peripheral};
+use profiler::Profiler;
+use record::{Index, Record, RecordParseError};
+use simulator::{Event, Simulator, Stop};
 use std::{
-    fmt::{self, Debug},
-    ops::BitXor,
+    fmt::Debug,
+    fs::File,
+    io::{BufWriter, Write},
+    ops::Range,
+    path::PathBuf,
+    process,
 };
+use symbols::Symbols;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    /// Listing with one instruction per line
+    Text,
+    /// Records and decoded instructions as a JSON document
+    Json,
+    /// Listing laid out like `avr-objdump -d`
+    Objdump,
+    /// Sixteen bytes per row with their printable characters
+    Hexdump,
+    /// Assembly source avr-as builds back into the same image
+    Asm,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Name an address in the annotation file, or remove its name
+    Label {
+        #[arg(value_parser = address)]
+        address: u32,
+        name: Option<String>,
+    },
+    /// Rename a label of the annotation file
+    Rename { old: String, new: String },
+    /// Comment an address in the annotation file, or remove its comment
+    Comment {
+        #[arg(value_parser = address)]
+        address: u32,
+        text: Option<String>,
+    },
+    /// Execute the image from reset and print the final state of the core
+    Run {
+        /// Stop after this many clock cycles
+        #[arg(long, default_value_t = 1_000_000)]
+        max_cycles: u64,
+        /// Print each executed instruction with what it changed
+        #[arg(long, default_value_t = false)]
+        trace: bool,
+        /// Write the trace in the compact binary format to this file
+        #[arg(long, value_name = "FILE")]
+        binary_trace: Option<PathBuf>,
+        /// Trace only instructions from this address on
+        #[arg(long, value_parser = address)]
+        start: Option<u32>,
+        /// Trace only instructions below this address
+        #[arg(long, value_parser = address)]
+        end: Option<u32>,
+        /// Write the flash to this Intel HEX file when the run stops
+        #[arg(long, value_name = "FILE")]
+        dump_flash: Option<PathBuf>,
+        /// Write the EEPROM to this Intel HEX file when the run stops
+        #[arg(long, value_name = "FILE")]
+        dump_eeprom: Option<PathBuf>,
+        /// Write how often each instruction ran to this file in lcov format
+        #[arg(long, value_name = "FILE")]
+        coverage: Option<PathBuf>,
+        /// Print the functions with how often each instruction ran
+        #[arg(long, default_value_t = false)]
+        coverage_listing: bool,
+        /// Print a flat and call graph profile of the cycles in each function
+        #[arg(long, default_value_t = false)]
+        profile: bool,
+        /// Key coverage by source line, from `avr-objdump --dwarf=decodedline`
+        #[arg(long, value_name = "FILE")]
+        lines: Option<PathBuf>,
+        /// Bytes the USART receives, `-` for standard input
+        #[arg(long, value_name = "FILE", default_value = "-")]
+        usart_in: PathBuf,
+        /// Where the USART transmits, `-` for standard output
+        #[arg(long, value_name = "FILE", default_value = "-")]
+        usart_out: PathBuf,
+        /// Hex code
+        hex: Vec<String>,
+    },
+    /// Assemble a source in the syntax of the listing into Intel HEX
+    Asm {
+        /// Assembly source, `-` for standard input
+        source: PathBuf,
+        /// Write the Intel HEX to this file instead of standard output
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Print the instruction set as a Markdown table, with the cycles on the core
+    Instructions,
+    /// Let avr-gdb debug the image on the simulator over TCP
+    Gdbserver {
+        /// Address to listen on, `:1234` for a port on the loopback interface
+        address: String,
+        /// Hex code
+        hex: Vec<String>,
+    },
+}
 
 #[derive(Parser, Debug)]
 #[command(version, about)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Hex code
     hex: Vec<String>,
     /// Advanced
@@ -16,150 +134,177 @@ struct Cli {
     /// Operator overloading
     #[arg(short, long, default_value_t = true)]
     overloads: bool,
+    /// Core family of the target device
+    #[arg(short, long, value_enum, default_value_t = Core::Avr5, global = true)]
+    core: Core,
+    /// Target device, sets the core and the memory sizes
+    #[arg(long, value_enum, global = true)]
+    device: Option<Device>,
+    /// Report worst-case stack usage instead of the listing
+    #[arg(short, long, default_value_t = false)]
+    stack: bool,
+    /// Print clock cycles of each instruction, basic block and loop
+    #[arg(long, default_value_t = false)]
+    cycles: bool,
+    /// Show the raw bytes of each line of the listing
+    #[arg(short, long, default_value_t = false)]
+    bytes: bool,
+    /// Print the bit fields of the instruction at this address
+    #[arg(long, value_parser = address)]
+    bits: Option<u32>,
+    /// First address to decode
+    #[arg(long, value_parser = address, conflicts_with = "function")]
+    start: Option<u32>,
+    /// Address to stop decoding at, exclusive
+    #[arg(long, value_parser = address, conflicts_with_all = ["length", "function"])]
+    end: Option<u32>,
+    /// Number of bytes to decode from the start
+    #[arg(long, value_parser = address, conflicts_with = "function")]
+    length: Option<u32>,
+    /// Decode only the function with this name or entry address
+    #[arg(long)]
+    function: Option<String>,
+    /// Read symbols from `avr-nm` output or a linker map file
+    #[arg(long, value_name = "FILE", global = true)]
+    symbols: Vec<PathBuf>,
+    /// Labels and comments of your own, kept in this JSON file
+    #[arg(long, value_name = "FILE", global = true)]
+    annotations: Option<PathBuf>,
+    /// Output format of the listing
+    #[arg(long, value_enum, default_value_t = Output::Text)]
+    output: Output,
+    /// Colorize the output
+    #[arg(long, value_enum, default_value_t = When::Auto, global = true)]
+    color: When,
+    /// Assume interrupt handlers may interrupt each other
+    #[arg(long, default_value_t = false)]
+    nested_interrupts: bool,
 }
 
-#[derive(Debug, PartialEq)]
-enum Index {
-    Data = 0,
-    End = 1,
-    AddressSegment = 2,
-    StartAddress80x86 = 3,
-    ExtendedAddress = 4,
-    LinearAdrres = 5,
-}
-
-struct Record {
-    address: u16,
-    index: Index,
-    data: Vec<(u8, u8)>,
+/// Parses an address given in hexadecimal with a `0x` prefix or in decimal.
+fn address(text: &str) -> Result<u32, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|error| error.to_string())
 }
 
-enum RecordParseError {
-    BeginningOfRecord,
-    CalculatingTheSize,
-    CalculatingTheAddress,
-    CalculatingIndex,
-    CalculatingData,
-    CalculatingChecksum,
+/// Prints `message` as an error and exits with `code`.
+fn fail(errors: Palette, message: impl std::fmt::Display, code: exitcode::ExitCode) -> ! {
+    eprintln!("{}", errors.paint(Style::Error, message));
+    process::exit(code);
 }
 
-impl Record {
-    fn from_str(hex: &str) -> Result<Self, RecordParseError> {
-        if &hex[0..1] != ":" {
-            return Err(RecordParseError::BeginningOfRecord);
-        }
-        let mut data: Self = Record {
-            address: match u16::from_str_radix(&hex[3..7], 16) {
-                Ok(content) => content,
-                _ => return Err(RecordParseError::CalculatingTheAddress),
-            },
-            index: match u8::from_str_radix(&hex[7..9], 16) {
-                Ok(content) => match content {
-                    0 => Index::Data,
-                    1 => Index::End,
-                    2 => Index::AddressSegment,
-                    3 => Index::StartAddress80x86,
-                    4 => Index::ExtendedAddress,
-                    5 => Index::LinearAdrres,
-                    _ => return Err(RecordParseError::CalculatingIndex),
-                },
-                _ => return Err(RecordParseError::CalculatingIndex),
-            },
-            data: vec![],
-        };
-        data.data
-            .reserve(match usize::from_str_radix(&hex[1..3], 16) {
-                Ok(content) => content,
-                _ => return Err(RecordParseError::CalculatingTheSize),
-            });
-        for i in (9..9 + (data.data.capacity() * 2)).step_by(4) {
-            data.data.push((
-                match u8::from_str_radix(&hex[i + 2..i + 4], 16) {
-                    Ok(content) => content,
-                    _ => return Err(RecordParseError::CalculatingData),
-                },
-                match u8::from_str_radix(&hex[i..i + 2], 16) {
-                    Ok(content) => content,
-                    _ => return Err(RecordParseError::CalculatingData),
-                },
-            ));
-        }
-        match u8::from_str_radix(
-            &hex[9 + data.data.len() * 4..9 + data.data.len() * 4 + 2],
-            16,
-        ) {
-            Ok(_) => Ok(data),
-            _ => Err(RecordParseError::CalculatingChecksum),
+/// Applies an annotation command to the file and saves it.
+fn annotate(command: Command, path: &std::path::Path, errors: Palette) {
+    let mut annotations = Annotations::load(path).unwrap_or_else(|error| {
+        fail(
+            errors,
+            format!("{}: {}", path.display(), error),
+            exitcode::DATAERR,
+        )
+    });
+    let valid = |name: &str| {
+        if !symbols::valid(name) {
+            fail(
+                errors,
+                format!("{}: not a valid label", name),
+                exitcode::USAGE,
+            );
         }
-    }
-}
-
-impl fmt::Display for Record {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut result = writeln!(
-            f,
-            "size: {}, address: {:#x}, index: {:?},",
-            self.data.len(),
-            self.address,
-            self.index
-        );
-        if !self.data.is_empty() && result == Ok(()) {
-            result = writeln!(f, "data: ");
-            for i in &self.data {
-                if result == Ok(()) {
-                    result = writeln!(f, "    ({:#010b}, {:#010b}), ", i.0, i.1);
-                } else {
-                    return result;
+    };
+    match command {
+        Command::Label {
+            address,
+            name: Some(name),
+        } => {
+            valid(&name);
+            annotations.labels.insert(address, name);
+        }
+        Command::Label {
+            address,
+            name: None,
+        } => {
+            annotations.labels.remove(&address);
+        }
+        Command::Rename { old, new } => {
+            valid(&new);
+            match annotations.find(&old) {
+                Some(address) => {
+                    annotations.labels.insert(address, new);
                 }
+                None => fail(errors, format!("{}: no such label", old), exitcode::USAGE),
             }
         }
-        result
+        Command::Comment {
+            address,
+            text: Some(text),
+        } => {
+            annotations.comments.insert(address, text);
+        }
+        Command::Comment {
+            address,
+            text: None,
+        } => {
+            annotations.comments.remove(&address);
+        }
+        Command::Run { .. }
+        | Command::Asm { .. }
+        | Command::Instructions
+        | Command::Gdbserver { .. } => {
+            unreachable!("only annotation commands edit annotations")
+        }
     }
-}
-
-fn from_additional_code(sign: bool, number: u16, mask: u16) -> i16 {
-    match sign {
-        true => -((number.bitxor(mask) + 1) as i16),
-        false => number as i16,
+    if let Err(error) = annotations.save(path) {
+        fail(
+            errors,
+            format!("{}: {}", path.display(), error),
+            exitcode::CANTCREAT,
+        );
     }
 }
 
-#[bitmatch]
-fn main() {
-    let cli: Cli = Cli::parse();
+/// Parses the records given on the command line, merging each one into the
+/// previous record when it continues it.
+fn records(hex: &[String], errors: Palette) -> Vec<Record> {
     let mut records: Vec<Record> = vec![];
-    for record in cli.hex {
-        let mut data = match Record::from_str(&record) {
+    for record in hex {
+        let mut data = match Record::from_str(record) {
             Ok(content) => content,
             Err(error) => {
-                match error {
+                let message = match error {
                     RecordParseError::BeginningOfRecord => {
-                        panic!("Each record in the Intel HEX file must start with a colon")
+                        "Each record in the Intel HEX file must start with a colon"
                     }
                     RecordParseError::CalculatingTheSize => {
-                        panic!("Error when calculating the data size, you need one byte (two hexadecimal digits), which in decimal is between 0 and 255")
+                        "Error when calculating the data size, you need one byte (two hexadecimal digits), which in decimal is between 0 and 255"
                     }
                     RecordParseError::CalculatingTheAddress => {
-                        panic!("Error when calculating the starting address, data block which is 2 bytes and indicates the absolute position of the record data in the binary file")
+                        "Error when calculating the starting address, data block which is 2 bytes and indicates the absolute position of the record data in the binary file"
                     }
                     RecordParseError::CalculatingIndex => {
-                        panic!("The field type is expected to take the following values: 0, 1, 2, 3, 4, 5")
+                        "The field type is expected to take the following values: 0, 1, 2, 3, 4, 5"
                     }
                     RecordParseError::CalculatingData => {
-                        panic!("Error when reading data bytes for writing to EPROM, the number of bytes to be written is specified at the beginning, in the range from 0 to 255 bytes")
+                        "Error when reading data bytes for writing to EPROM, the number of bytes to be written is specified at the beginning, in the range from 0 to 255 bytes"
                     }
                     RecordParseError::CalculatingChecksum => {
-                        panic!("Error when reading the last byte of a record, a checksum calculated so that the sum of all bytes in the record is zero")
+                        "Error when reading the last byte of a record, a checksum calculated so that the sum of all bytes in the record is zero"
                     }
-                }
+                };
+                fail(errors, message, exitcode::DATAERR);
             }
         };
         match records.last_mut() {
             Some(record) => {
-                if ((record.address + record.data.len() as u16 * 2) == data.address)
+                // Pairs only continue a record without a padded last pair.
+                if ((record.address as u32 + record.size as u32) == data.address as u32)
                     && (record.index == data.index)
+                    && record.size % 2 == 0
                 {
                     record.data.append(&mut data.data);
+                    record.size += data.size;
                 } else {
                     records.push(data);
                 }
@@ -167,309 +312,553 @@ fn main() {
             None => records.push(data),
         };
     }
-    for data in records {
-        if cli.advanced {
-            println!("{}", data);
-        }
-        let mut iter = data.data.into_iter().enumerate();
-        while let Some((mut i, content)) = iter.next() {
-            i = i * 2 + data.address as usize;
-            print!("{:#x}: ", i);
-            #[bitmatch]
-            match u16::from_be_bytes([content.0, content.1]) {
-                "0000_0000_0000_0000" => println!("nop"),
-                "0000_0001_dddd_rrrr" => {
-                    println!("movw r{}:{}, r{}:{}", d * 2 + 1, d * 2, r * 2 + 1, r * 2)
-                }
-                "0000_0010_dddd_rrrr" => println!("muls r{}, r{}", d + 16, r + 16),
-                "0000_0011_0ddd_0rrr" => println!("mulsu r{}, r{}", d + 16, r + 16),
-                "0000_0011_0ddd_1rrr" => println!("fmul r{}, r{}", d + 16, r + 16),
-                "0000_0011_1ddd_0rrr" => println!("fmuls r{}, r{}", d + 16, r + 16),
-                "0000_0011_1ddd_1rrr" => println!("fmulsu r{}, r{}", d + 16, r + 16),
-                "0000_01rd_dddd_rrrr" => println!("cpc r{}, r{}", d, r),
-                "0000_10rd_dddd_rrrr" => println!("sbc r{}, r{}", d, r),
-                "0000_11rd_dddd_rrrr" => match (d == r) && cli.overloads {
-                    true => println!("lsl r{}", d),
-                    false => println!("add r{}, r{}", d, r),
-                },
-                "0001_00rd_dddd_rrrr" => println!(
-                    "cpse r{}, r{} ; {:#x} (or {:#x})",
-                    d,
-                    r,
-                    i + 2 * 2,
-                    i + 3 * 2
+    records
+}
+
+/// Symbols of the `--symbols` files with the labels and comments of the
+/// annotation file applied.
+fn load_symbols(cli: &Cli, errors: Palette) -> Symbols {
+    let mut symbols = Symbols::default();
+    for path in &cli.symbols {
+        if let Err(error) = symbols.load(path) {
+            fail(
+                errors,
+                format!("{}: {}", path.display(), error),
+                exitcode::NOINPUT,
+            );
+        }
+    }
+    if let Some(path) = &cli.annotations {
+        match Annotations::load(path) {
+            Ok(annotations) => annotations.apply(&mut symbols),
+            Err(error) => fail(
+                errors,
+                format!("{}: {}", path.display(), error),
+                exitcode::DATAERR,
+            ),
+        }
+    }
+    symbols
+}
+
+/// What `run` executes: the device, the peripherals attached to it and
+/// the number of cycles it may take.
+struct Machine {
+    profile: Profile,
+    max_cycles: u64,
+    peripherals: Vec<Box<dyn Peripheral>>,
+}
+
+/// What `run` prints or writes while the program executes.
+struct Tracing {
+    text: bool,
+    binary: Option<PathBuf>,
+    range: Range<u32>,
+}
+
+/// What `run` writes and prints once the program stopped.
+struct Reports {
+    /// Intel HEX files the memories are written to.
+    flash: Option<PathBuf>,
+    eeprom: Option<PathBuf>,
+    /// lcov file of the coverage.
+    coverage: Option<PathBuf>,
+    coverage_listing: bool,
+    profile: bool,
+    lines: Lines,
+    symbols: Symbols,
+}
+
+/// Writes `text` to `path`, exiting when it cannot be created.
+fn save(path: &std::path::Path, text: impl AsRef<[u8]>, errors: Palette) {
+    if let Err(error) = std::fs::write(path, text) {
+        fail(
+            errors,
+            format!("{}: {}", path.display(), error),
+            exitcode::CANTCREAT,
+        );
+    }
+}
+
+/// The 16-byte rows of `memory` that are not erased, joined into segments.
+fn programmed(memory: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut segments: Vec<(u32, &[u8])> = vec![];
+    for (i, row) in memory.chunks(16).enumerate() {
+        if row.iter().all(|&byte| byte == 0xff) {
+            continue;
+        }
+        let start = i * 16;
+        match segments.last_mut() {
+            Some((address, bytes)) if *address as usize + bytes.len() == start => {
+                *bytes = &memory[*address as usize..start + row.len()];
+            }
+            _ => segments.push((start as u32, row)),
+        }
+    }
+    segments
+}
+
+/// The peripherals of the device, with the USART connected to the files
+/// named on the command line, `-` standing for standard input and output.
+fn peripherals(
+    profile: &Profile,
+    input: &std::path::Path,
+    output: &std::path::Path,
+    errors: Palette,
+) -> Vec<Box<dyn Peripheral>> {
+    let input = match input.to_str() {
+        Some("-") => Input::Stdin(None),
+        _ => match std::fs::read(input) {
+            Ok(bytes) => Input::Bytes(bytes.into()),
+            Err(error) => fail(
+                errors,
+                format!("{}: {}", input.display(), error),
+                exitcode::NOINPUT,
+            ),
+        },
+    };
+    let output: Box<dyn Write> = match output.to_str() {
+        Some("-") => Box::new(std::io::stdout()),
+        _ => match File::create(output) {
+            Ok(file) => Box::new(file),
+            Err(error) => fail(
+                errors,
+                format!("{}: {}", output.display(), error),
+                exitcode::CANTCREAT,
+            ),
+        },
+    };
+    peripherals::standard(profile, input, output)
+}
+
+/// Runs the image from reset and prints where and why it stopped.
+fn simulate(
+    hex: &[String],
+    machine: Machine,
+    tracing: Tracing,
+    reports: Reports,
+    palette: Palette,
+    errors: Palette,
+) {
+    let Machine {
+        profile,
+        max_cycles,
+        peripherals,
+    } = machine;
+    let records = records(hex, errors);
+    let image = Image::from_records(&records);
+    let mut simulator = Simulator::new(profile, &image);
+    for peripheral in peripherals {
+        simulator.attach(peripheral);
+    }
+    println!(
+        "flash {} bytes, sram {:#x}-{:#x}, eeprom {} bytes",
+        simulator.flash.len(),
+        profile.sram,
+        profile.ramend(),
+        simulator.eeprom.len()
+    );
+    let mut binary = tracing.binary.as_ref().map(|path| {
+        let mut file = File::create(path)
+            .map(BufWriter::new)
+            .unwrap_or_else(|error| {
+                fail(
+                    errors,
+                    format!("{}: {}", path.display(), error),
+                    exitcode::CANTCREAT,
+                )
+            });
+        let _ = file.write_all(trace::MAGIC);
+        file
+    });
+    let mut coverage = Coverage::new(profile.flash);
+    let mut profiler = Profiler::new(&simulator);
+    let stop = simulator.run(max_cycles, |before, event, after| {
+        if event == Event::Executed {
+            coverage.record(before.pc);
+        }
+        profiler.record(before, event, after);
+        if !tracing.range.contains(&before.pc) {
+            return;
+        }
+        if tracing.text {
+            println!("{}", trace::line(before, event, after, true));
+        }
+        if let Some(file) = &mut binary {
+            let _ = file.write_all(&trace::record(before, event, after));
+        }
+    });
+    if let Some(Err(error)) = binary.as_mut().map(|file| file.flush()) {
+        fail(errors, error, exitcode::IOERR);
+    }
+    let memories = [
+        (&reports.flash, &simulator.flash),
+        (&reports.eeprom, &simulator.eeprom),
+    ];
+    for (path, memory) in memories {
+        if let Some(path) = path {
+            save(path, record::intel_hex(&programmed(memory)), errors);
+        }
+    }
+    println!("stopped at {:#x}: {}", simulator.pc, stop);
+    print!("{}", simulator);
+    if reports.coverage.is_some() || reports.coverage_listing {
+        let graph = CallGraph::new(&image);
+        if let Some(path) = &reports.coverage {
+            save(
+                path,
+                coverage.lcov(&graph, &reports.symbols, &reports.lines),
+                errors,
+            );
+        }
+        if reports.coverage_listing {
+            print!("{}", coverage.listing(&graph, &reports.symbols, palette));
+        }
+    }
+    if reports.profile {
+        print!("{}", profiler.report(&reports.symbols, palette));
+    }
+    if matches!(
+        stop,
+        Stop::Outside | Stop::Undecoded(_) | Stop::Unsupported(_)
+    ) {
+        process::exit(exitcode::SOFTWARE);
+    }
+}
+
+fn main() {
+    let cli: Cli = Cli::parse();
+    let palette = Palette::stdout(cli.color);
+    let errors = Palette::stderr(cli.color);
+    let profile = cli
+        .device
+        .map_or(cli.core.profile(), |device| device.profile());
+    let core = profile.core;
+    if let Some(Command::Run {
+        max_cycles,
+        trace,
+        binary_trace,
+        start,
+        end,
+        dump_flash,
+        dump_eeprom,
+        coverage,
+        coverage_listing,
+        profile: profiling,
+        lines: lines_path,
+        usart_in,
+        usart_out,
+        hex,
+    }) = &cli.command
+    {
+        let mut lines = Lines::default();
+        if let Some(path) = lines_path {
+            if let Err(error) = lines.load(path) {
+                fail(
+                    errors,
+                    format!("{}: {}", path.display(), error),
+                    exitcode::NOINPUT,
+                );
+            }
+        }
+        let reports = Reports {
+            flash: dump_flash.clone(),
+            eeprom: dump_eeprom.clone(),
+            coverage: coverage.clone(),
+            coverage_listing: *coverage_listing,
+            profile: *profiling,
+            lines,
+            symbols: load_symbols(&cli, errors),
+        };
+        let machine = Machine {
+            profile,
+            max_cycles: *max_cycles,
+            peripherals: peripherals(&profile, usart_in, usart_out, errors),
+        };
+        let tracing = Tracing {
+            text: *trace,
+            binary: binary_trace.clone(),
+            range: start.unwrap_or(0)..end.unwrap_or(u32::MAX),
+        };
+        simulate(hex, machine, tracing, reports, palette, errors);
+        return;
+    }
+    if let Some(Command::Asm { source, output }) = &cli.command {
+        let text = match source.to_str() {
+            Some("-") => std::io::read_to_string(std::io::stdin()),
+            _ => std::fs::read_to_string(source),
+        };
+        let text = text.unwrap_or_else(|error| {
+            fail(
+                errors,
+                format!("{}: {}", source.display(), error),
+                exitcode::NOINPUT,
+            )
+        });
+        let segments = assembler::assemble(&text, core).unwrap_or_else(|error| {
+            fail(
+                errors,
+                format!("{}: {}", source.display(), error),
+                exitcode::DATAERR,
+            )
+        });
+        let segments: Vec<(u32, &[u8])> = segments
+            .iter()
+            .map(|(address, bytes)| (*address, &bytes[..]))
+            .collect();
+        let hex = record::intel_hex(&segments);
+        match output {
+            Some(path) => save(path, hex, errors),
+            None => print!("{}", hex),
+        }
+        return;
+    }
+    if let Some(Command::Instructions) = &cli.command {
+        print!("{}", opcodes::reference(core));
+        return;
+    }
+    if let Some(Command::Gdbserver { address, hex }) = &cli.command {
+        let image = Image::from_records(&records(hex, errors));
+        let mut simulator = Simulator::new(profile, &image);
+        let output = Box::new(std::io::stdout());
+        for peripheral in peripherals::standard(&profile, Input::Stdin(None), output) {
+            simulator.attach(peripheral);
+        }
+        if let Err(error) = gdb::serve(address, simulator) {
+            fail(errors, format!("{}: {}", address, error), exitcode::IOERR);
+        }
+        return;
+    }
+    if let Some(command) = cli.command {
+        match &cli.annotations {
+            Some(path) => annotate(command, path, errors),
+            None => fail(
+                errors,
+                "--annotations FILE is needed to edit annotations",
+                exitcode::USAGE,
+            ),
+        }
+        return;
+    }
+    let records = records(&cli.hex, errors);
+    let symbols = load_symbols(&cli, errors);
+    let image = Image::from_records(&records);
+    let graph = CallGraph::new(&image);
+    if cli.stack {
+        print!(
+            "{}",
+            stack::analyze(&image, &graph, core.pc_bytes(), cli.nested_interrupts)
+        );
+        return;
+    }
+    if let Some(address) = cli.bits {
+        match bits::breakdown(&image, address, cli.overloads) {
+            Some(breakdown) => print!("{}", breakdown),
+            None => fail(
+                errors,
+                format!("{:#x}: no instruction is loaded there", address),
+                exitcode::USAGE,
+            ),
+        }
+        return;
+    }
+    let window = match &cli.function {
+        Some(name) => {
+            let function = symbols
+                .find(name)
+                .or_else(|| address(name).ok())
+                .and_then(|entry| graph.functions.get(&entry));
+            match function {
+                Some(function) => function.extent(),
+                None => fail(
+                    errors,
+                    format!("{}: no such function", name),
+                    exitcode::USAGE,
                 ),
-                "0001_01rd_dddd_rrrr" => println!("cp r{}, r{}", d, r),
-                "0001_10rd_dddd_rrrr" => println!("sub r{}, r{}", d, r),
-                "0001_11rd_dddd_rrrr" => match (d == r) && cli.overloads {
-                    true => println!("rol r{}", d),
-                    false => println!("adc r{}, r{}", d, r),
-                },
-                "0010_00rd_dddd_rrrr" => match (d == r) && cli.overloads {
-                    true => println!("tst r{}", d),
-                    false => println!("and r{}, r{}", d, r),
-                },
-                "0010_01rd_dddd_rrrr" => match (d == r) && cli.overloads {
-                    true => println!("clr r{}", d),
-                    false => println!("eor r{}, r{}", d, r),
-                },
-                "0010_10rd_dddd_rrrr" => println!("or r{}, r{}", d, r),
-                "0010_11rd_dddd_rrrr" => println!("mov r{}, r{}", d, r),
-                "0011_kkkk_dddd_kkkk" => println!("cpi r{}, {}", d + 16, k),
-                "0100_kkkk_dddd_kkkk" => println!("sbci r{}, {}", d + 16, k),
-                "0101_kkkk_dddd_kkkk" => println!("subi r{}, {:#x}", d + 16, k),
-                "0110_kkkk_dddd_kkkk" => println!("ori r{}, {:#x}", d + 16, k),
-                "0111_kkkk_dddd_kkkk" => println!("andi r{}, {:#x}", d + 16, k),
-                "1000_000d_dddd_0000" => println!("ld r{}, Z", d),
-                "1000_000d_dddd_1000" => println!("ld r{}, Y", d),
-                "1000_001r_rrrr_0000" => println!("st Z, r{}", r),
-                "1000_001r_rrrr_1000" => println!("st Y, r{}", r),
-                "1000_001r_rrrr_1001" => println!("st Y+, r{}", r),
-                "1000_001r_rrrr_1010" => println!("st -Y, r{}", r),
-                "1001_000d_dddd_0000" => match iter.next() {
-                    Some((_, extension)) => println!(
-                        "lds r{}, {:#x}",
-                        d,
-                        u16::from_be_bytes([extension.0, extension.1])
-                    ),
-                    _ => panic!("instruction lds was expected"),
-                },
-                "1001_000d_dddd_0001" => println!("ld r{}, Z+", d),
-                "1001_000d_dddd_0010" => println!("ld r{}, -Z", d),
-                "1001_000d_dddd_0100" => println!("lpm r{}, Z", d),
-                "1001_000d_dddd_0101" => println!("lpm r{}, Z+", d),
-                "1001_000d_dddd_0110" => println!("elpm r{}, Z", d),
-                "1001_000d_dddd_0111" => println!("elpm r{}, z+", d),
-                "1001_000d_dddd_1001" => println!("ld r{}, Y+", d),
-                "1001_000d_dddd_1010" => println!("ld r{}, -Y", d),
-                "1001_000d_dddd_1100" => println!("ld r{}, X", d),
-                "1001_000d_dddd_1101" => println!("ld r{}, X+", d),
-                "1001_000d_dddd_1110" => println!("ld r{}, -X", d),
-                "1001_000d_dddd_1111" => println!("pop r{}", d),
-                "1001_001d_dddd_0000" => match iter.next() {
-                    Some((_, extension)) => println!(
-                        "sts {}, r{}",
-                        u16::from_be_bytes([extension.0, extension.1]),
-                        d
-                    ),
-                    _ => panic!("instruction sts was expected"),
-                },
-                "1001_001r_rrrr_0001" => println!("st Z+, r{}", r),
-                "1001_001r_rrrr_0010" => println!("st -Z, r{}", r),
-                "1001_001r_rrrr_1100" => println!("st X, r{}", r),
-                "1001_001r_rrrr_1101" => println!("st X+, r{}", r),
-                "1001_001r_rrrr_1110" => println!("st X-, r{}", r),
-                "1001_001d_dddd_1111" => println!("push r{}", d),
-                "1001_0100_0000_1000" if cli.overloads => println!("sec"),
-                "1001_0100_0001_1000" if cli.overloads => println!("sez"),
-                "1001_0100_0010_1000" if cli.overloads => println!("sen"),
-                "1001_0100_0011_1000" if cli.overloads => println!("sev"),
-                "1001_0100_0100_1000" if cli.overloads => println!("ses"),
-                "1001_0100_0101_1000" if cli.overloads => println!("seh"),
-                "1001_0100_0110_1000" if cli.overloads => println!("set"),
-                "1001_0100_0111_1000" if cli.overloads => println!("sei"),
-                "1001_0100_0000_1001" => println!("ijmp"),
-                "1001_0100_0001_1001" => println!("eijmp"),
-                "1001_0100_0sss_1000" => println!("bset {}", s),
-                "1001_0100_1000_1000" if cli.overloads => println!("clc"),
-                "1001_0100_1001_1000" if cli.overloads => println!("clz"),
-                "1001_0100_1010_1000" if cli.overloads => println!("cln"),
-                "1001_0100_1011_1000" if cli.overloads => println!("clv"),
-                "1001_0100_1100_1000" if cli.overloads => println!("cls"),
-                "1001_0100_1101_1000" if cli.overloads => println!("clh"),
-                "1001_0100_1110_1000" if cli.overloads => println!("clt"),
-                "1001_0100_1111_1000" if cli.overloads => println!("cli"),
-                "1001_0100_1sss_1000" => println!("bclr {}", s),
-                "1001_0101_0000_1000" => println!("ret"),
-                "1001_0101_0000_1001" => println!("icall"),
-                "1001_0101_0001_1000" => println!("reti"),
-                "1001_0101_0001_1001" => println!("eicall"),
-                "1001_0101_1000_1000" => println!("sleep"),
-                "1001_0101_1001_1000" => println!("break"),
-                "1001_0101_1010_1000" => println!("wdr"),
-                "1001_0101_1100_1000" => println!("lpm"),
-                "1001_0101_1101_1000" => println!("elpm"),
-                "1001_0101_1110_1000" => println!("spm"),
-                "1001_010d_dddd_0000" => println!("com r{}", d),
-                "1001_010d_dddd_0001" => println!("neg r{}", d),
-                "1001_010d_dddd_0010" => println!("swap r{}", d),
-                "1001_010d_dddd_0011" => println!("inc r{}", d),
-                "1001_010d_dddd_0101" => println!("asr r{}", d),
-                "1001_010d_dddd_0110" => println!("lsr r{}", d),
-                "1001_010d_dddd_0111" => println!("ror r{}", d),
-                "1001_010d_dddd_1010" => println!("dec r{}", d),
-                "1001_010k_kkkk_110k" => match iter.next() {
-                    Some((_, extension)) => println!(
-                        "jmp {:#x} ; {:#x}",
-                        u32::from_be_bytes([0, k as u8, extension.0, extension.1]) * 2,
-                        u32::from_be_bytes([0, k as u8, extension.0, extension.1]) * 2
-                    ),
-                    _ => panic!("instruction jmp was expected"),
-                },
-                "1001_010k_kkkk_111k" => match iter.next() {
-                    Some((_, extension)) => println!(
-                        "call {:#x} ; {:#x}",
-                        u32::from_be_bytes([0, k as u8, extension.0, extension.1]) * 2,
-                        u32::from_be_bytes([0, k as u8, extension.0, extension.1]) * 2
-                    ),
-                    _ => panic!("instruction call was expected"),
-                },
-                "1001_0110_kkdd_kkkk" => {
-                    println!("adiw r{}:{}, {}", d * 2 + 25, d * 2 + 24, k)
+            }
+        }
+        None => {
+            let start = cli.start.unwrap_or(0);
+            let end = match (cli.end, cli.length) {
+                (Some(end), _) => end,
+                (None, Some(length)) => start.saturating_add(length),
+                (None, None) => u32::MAX,
+            };
+            start..end
+        }
+    };
+    let resolved = graph.resolved();
+    let data = Data::detect(&image, &graph);
+    if cli.output == Output::Json {
+        match json::render(&records, &image, &data, &resolved, cli.overloads, &window) {
+            Ok(text) => println!("{}", text),
+            Err(error) => fail(errors, error, exitcode::SOFTWARE),
+        }
+        return;
+    }
+    if cli.output == Output::Asm {
+        print!(
+            "{}",
+            reassemble::render(
+                &image,
+                &data,
+                &resolved,
+                &symbols,
+                reassemble::Options {
+                    core,
+                    overloads: cli.overloads,
+                    palette,
+                    window: &window,
                 }
-                "1001_0111_kkdd_kkkk" => {
-                    println!("sbiw r{}:{}, {}", d * 2 + 25, d * 2 + 24, k)
+            )
+        );
+        return;
+    }
+    if cli.output == Output::Hexdump {
+        for segment in image.segments_in(&window) {
+            let bytes: Vec<u8> = segment
+                .clone()
+                .filter_map(|address| image.byte(address))
+                .collect();
+            print!("{}", hexdump::rows(segment.start, &bytes));
+        }
+        return;
+    }
+    if cli.output == Output::Objdump {
+        for segment in image.segments_in(&window) {
+            print!("{}", objdump::render(&image, segment, &symbols));
+        }
+        return;
+    }
+    for (record, base) in records.iter().zip(image::bases(&records)) {
+        let range = base..base.saturating_add(record.size as u32);
+        let range = match record.index {
+            Index::Data => match image::clip(range, &window) {
+                Some(range) => Some(range),
+                None => continue,
+            },
+            _ => None,
+        };
+        if cli.advanced {
+            println!("{}", record);
+        }
+        let Some(range) = range else {
+            continue;
+        };
+        for line in listing::sweep(&image, &data, range) {
+            let address = line.address;
+            let note = symbols.comments.get(&address);
+            if let Some(name) = symbols.code.get(&address) {
+                println!("{}:", palette.paint(Style::Label, name));
+            }
+            print!(
+                "{}: ",
+                palette.paint(Style::Address, format!("{:#x}", address))
+            );
+            if cli.bytes {
+                let raw: Vec<String> = match line.content {
+                    Content::Instruction { .. } | Content::Word(_) => (address
+                        ..address + line.size())
+                        .filter_map(|address| image.byte(address))
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect(),
+                    _ => vec![],
+                };
+                print!("{:<12}", raw.join(" "));
+            }
+            match line.content {
+                Content::Instruction {
+                    instruction,
+                    skipped,
+                } => {
+                    if cli.cycles {
+                        let cycles = cycles::cycles(&instruction, core, skipped.unwrap_or(1));
+                        print!(
+                            "{} ",
+                            palette.paint(Style::Comment, format!("{:<5}", cycles.to_string()))
+                        );
+                    }
+                    let targets = resolved.get(&address).map_or(&[][..], |targets| targets);
+                    let comment: Vec<String> = data
+                        .describe(&image, address)
+                        .into_iter()
+                        .chain(note.cloned())
+                        .collect();
+                    let comment = comment.join(" ; ");
+                    println!(
+                        "{}",
+                        instruction
+                            .display(address, cli.overloads, skipped)
+                            .targets(targets)
+                            .comment((!comment.is_empty()).then_some(comment.as_str()))
+                            .palette(palette)
+                            .symbols(&symbols)
+                    );
+                    continue;
                 }
-                "1001_1000_aaaa_abbb" => println!("cbi {:#x}, {}", a, b),
-                "1001_1001_aaaa_abbb" => println!(
-                    "sbic {:#x}, {} ; {:#x} (or {:#x})",
-                    a,
-                    b,
-                    i + 2 * 2,
-                    i + 3 * 2
-                ),
-                "1001_1010_aaaa_abbb" => println!("sbi {:#x}, {}", a, b),
-                "1001_1011_aaaa_abbb" => println!(
-                    "sbis {:#x}, {} ; {:#x} (or {:#x})",
-                    a,
-                    b,
-                    i + 2 * 2,
-                    i + 3 * 2
-                ),
-                "1001_11rd_dddd_rrrr" => println!("mul r{}, r{}", d, r),
-                "1011_0aad_dddd_aaaa" => println!("in r{}, {:#x}", d, a),
-                "1011_1aar_rrrr_aaaa" => println!("out {:#x}, r{}", a, r),
-                "10q0_qq1r_rrrr_0qqq" => println!("std Z+{}, r{}", q, r),
-                "10q0_qq1r_rrrr_1qqq" => println!("std Y+{}, r{}", q, r),
-                "10q0_qq0d_dddd_0qqq" => println!("ldd r{}, Z+{}", d, q),
-                "10q0_qq0d_dddd_1qqq" => println!("ldd r{}, Y+{}", d, q),
-                "1100_ekkk_kkkk_kkkk" => println!(
-                    "rjmp .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0111_1111_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0111_1111_1111) * 2 + 2
-                ),
-                "1101_ekkk_kkkk_kkkk" => println!(
-                    "rcall .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0111_1111_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0111_1111_1111) * 2 + 2
-                ),
-                "1110_kkkk_dddd_kkkk" => match (k == u16::MAX) && cli.overloads {
-                    true => println!("ser r{}", d),
-                    false => println!("ldi r{}, {:#x}", d + 16, k),
-                },
-                "1111_00ek_kkkk_k000" if cli.overloads => println!(
-                    "brcs .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_00ek_kkkk_k001" if cli.overloads => println!(
-                    "breq .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_00ek_kkkk_k010" if cli.overloads => println!(
-                    "brmi .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_00ek_kkkk_k011" if cli.overloads => println!(
-                    "brvs .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_00ek_kkkk_k100" if cli.overloads => println!(
-                    "brlt .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_00ek_kkkk_k101" if cli.overloads => println!(
-                    "brhs .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_00ek_kkkk_k110" if cli.overloads => println!(
-                    "brts .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_00ek_kkkk_k111" if cli.overloads => println!(
-                    "brie .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_00ek_kkkk_ksss" => println!(
-                    "brbs {}, .{:+} ; {:#x}",
-                    s,
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_01ek_kkkk_k000" if cli.overloads => println!(
-                    "brcc .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_01ek_kkkk_k001" if cli.overloads => println!(
-                    "brne .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_01ek_kkkk_k010" if cli.overloads => println!(
-                    "brpl .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
+                Content::String(text) => print!(
+                    "{} {}",
+                    palette.paint(Style::Mnemonic, ".asciz"),
+                    palette.paint(Style::Immediate, format!("\"{}\"", data::escape(&text)))
                 ),
-                "1111_01ek_kkkk_k011" if cli.overloads => println!(
-                    "brvc .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_01ek_kkkk_k100" if cli.overloads => println!(
-                    "brge .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_01ek_kkkk_k101" if cli.overloads => println!(
-                    "brhc .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_01ek_kkkk_k110" if cli.overloads => println!(
-                    "brtc .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_01ek_kkkk_k111" if cli.overloads => println!(
-                    "brid .{:+} ; {:#x}",
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_01ek_kkkk_ksss" => println!(
-                    "brid {}, .{:+} ; {:#x}",
-                    s,
-                    from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2,
-                    i as i16 + from_additional_code(e == 1, k, 0b0000_0000_0011_1111) * 2 + 2
-                ),
-                "1111_100d_dddd_0bbb" => println!("bld r{}, {}", d, b),
-                "1111_101d_dddd_0bbb" => println!("bst r{}, {}", d, b),
-                "1111_110r_rrrr_0bbb" => println!(
-                    "sbrc r{}, {} ; {:#x} (or {:#x})",
-                    r,
-                    b,
-                    i + 2 * 2,
-                    i + 3 * 2
-                ),
-                "1111_111r_rrrr_0bbb" => println!(
-                    "sbrs r{}, {} ; {:#x} (or {:#x})",
-                    r,
-                    b,
-                    i + 2 * 2,
-                    i + 3 * 2
-                ),
-                _ => panic!(
-                    "error, unexpected command (0b{:0>8b}_{:0>8b})",
-                    content.0, content.1
+                Content::Bytes(bytes) => {
+                    let bytes: Vec<String> =
+                        bytes.iter().map(|byte| format!("{:#04x}", byte)).collect();
+                    print!(
+                        "{} {}",
+                        palette.paint(Style::Mnemonic, ".byte"),
+                        palette.paint(Style::Immediate, bytes.join(", "))
+                    );
+                }
+                Content::Word(word) => print!(
+                    "{} {}",
+                    palette.paint(Style::Mnemonic, ".word"),
+                    palette.paint(Style::Immediate, format!("{:#06x}", word))
                 ),
+            }
+            match note {
+                Some(note) => println!("{}", palette.paint(Style::Comment, format!(" ; {}", note))),
+                None => println!(),
+            }
+        }
+    }
+    if cli.cycles {
+        for (entry, function) in graph.functions.range(window.clone()) {
+            let name = match symbols.code.get(entry) {
+                Some(name) => format!("{:#x} <{}>", entry, name),
+                None => format!("{:#x}", entry),
             };
+            println!("function {}", palette.paint(Style::Label, name));
+            let blocks = flow::blocks(&image, function);
+            for block in blocks.values() {
+                let (min, max) = flow::block_cycles(&image, block, core);
+                println!(
+                    "    block {:#x}-{:#x}: {}",
+                    block.start,
+                    block.instructions.last().map_or(block.start, |last| last.0),
+                    range(min, max)
+                );
+            }
+            let loops = flow::loops(&blocks, *entry);
+            for body in &loops {
+                let (min, max) = flow::loop_cycles(&image, &blocks, body, &loops, core);
+                println!(
+                    "    loop {:#x}-{:#x}: {} per iteration",
+                    body.header,
+                    blocks[&body.latch]
+                        .instructions
+                        .last()
+                        .map_or(body.latch, |last| last.0),
+                    range(min, max)
+                );
+            }
         }
     }
 }
+
+fn range(min: u32, max: u32) -> String {
+    match min == max {
+        true => format!("{} cycles", min),
+        false => format!("{}..{} cycles", min, max),
+    }
+}
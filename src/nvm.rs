@@ -0,0 +1,492 @@
+use crate::device;
+
+/// Clock frequency the programming times of the datasheets are converted
+/// to cycles at.
+const CLOCK: u64 = 16_000_000;
+/// Cycles of an EEPROM erase and write, 3.4 ms, and of an erase or a write
+/// alone, 1.8 ms.
+const EEPROM_ATOMIC: u64 = CLOCK * 34 / 10_000;
+const EEPROM_SPLIT: u64 = CLOCK * 18 / 10_000;
+/// Cycles of a flash page erase or write, 4.5 ms.
+const PAGE: u64 = CLOCK * 45 / 10_000;
+/// Cycles after setting `EEMPE` or `SPMEN` within which `EEPE` or `spm`
+/// must follow.
+const WINDOW: u64 = 4;
+
+/// Bits of `EECR`.
+const EERE: u8 = 0;
+const EEPE: u8 = 1;
+const EEMPE: u8 = 2;
+const EERIE: u8 = 3;
+/// `EEPM1:0`, erase and write, erase only or write only.
+const EEPM: u8 = 0x30;
+
+/// Bits of `SPMCSR`.
+const SPMEN: u8 = 0;
+const PGERS: u8 = 1;
+const PGWRT: u8 = 2;
+const RWWSRE: u8 = 4;
+const RWWSB: u8 = 6;
+const SPMIE: u8 = 7;
+
+/// The EEPROM and self-programming controllers of a device. Both work on
+/// the memories of the simulator, which passes them in.
+pub struct Controller {
+    layout: device::Nvm,
+    /// The `EERIE` and `EEPM` bits of `EECR`.
+    eecr: u8,
+    eedr: u8,
+    eear: u16,
+    /// Cycle `EEMPE` clears at.
+    master: u64,
+    /// Cycle the EEPROM write in progress completes at.
+    eeprom_busy: u64,
+    /// `SPMCSR` as last written, selecting what `spm` does.
+    spmcsr: u8,
+    /// Cycle the operation selected in `SPMCSR` expires at.
+    armed: u64,
+    /// Cycle the page erase or write in progress completes at.
+    flash_busy: u64,
+    /// The read-while-write section stays unreadable after a page erase or
+    /// write until it is enabled again.
+    rww_busy: bool,
+    /// Temporary page buffer filled by `spm`.
+    buffer: Vec<u8>,
+}
+
+impl Controller {
+    pub fn new(layout: device::Nvm) -> Self {
+        Controller {
+            layout,
+            eecr: 0,
+            eedr: 0,
+            eear: 0,
+            master: 0,
+            eeprom_busy: 0,
+            spmcsr: 0,
+            armed: 0,
+            flash_busy: 0,
+            rww_busy: false,
+            buffer: vec![0xff; layout.page as usize],
+        }
+    }
+
+    /// Whether the register at the data address `address` belongs to the
+    /// controllers.
+    pub fn owns(&self, address: u16) -> bool {
+        (self.layout.eecr..self.layout.eecr + 4).contains(&address) || address == self.layout.spmcsr
+    }
+
+    pub fn peek(&self, address: u16, cycles: u64) -> u8 {
+        let layout = self.layout;
+        match address {
+            address if address == layout.spmcsr => {
+                let armed = cycles < self.armed;
+                let operation = match armed {
+                    true => self.spmcsr & 0x3f,
+                    false => 0,
+                };
+                operation
+                    | ((armed || cycles < self.flash_busy) as u8) << SPMEN
+                    | (self.rww_busy as u8) << RWWSB
+                    | self.spmcsr & 1 << SPMIE
+            }
+            address => match address - layout.eecr {
+                0 => {
+                    self.eecr
+                        | ((cycles < self.master) as u8) << EEMPE
+                        | ((cycles < self.eeprom_busy) as u8) << EEPE
+                }
+                1 => self.eedr,
+                2 => self.eear as u8,
+                _ => (self.eear >> 8) as u8,
+            },
+        }
+    }
+
+    /// Writes a register at cycle `cycles`, returns the cycles the CPU is
+    /// halted for.
+    pub fn write(&mut self, address: u16, value: u8, cycles: u64, eeprom: &mut [u8]) -> u64 {
+        let layout = self.layout;
+        if address == layout.spmcsr {
+            if cycles >= self.flash_busy {
+                self.spmcsr = value;
+                if value >> SPMEN & 1 == 1 {
+                    self.armed = cycles + WINDOW;
+                }
+            }
+            return 0;
+        }
+        match address - layout.eecr {
+            0 => self.control(value, cycles, eeprom),
+            1 => {
+                self.eedr = value;
+                0
+            }
+            2 => {
+                self.eear = self.eear & 0xff00 | value as u16;
+                0
+            }
+            _ => {
+                self.eear = self.eear & 0xff | (value as u16) << 8;
+                0
+            }
+        }
+    }
+
+    /// A write to `EECR`.
+    fn control(&mut self, value: u8, cycles: u64, eeprom: &mut [u8]) -> u64 {
+        let idle = cycles >= self.eeprom_busy;
+        self.eecr = match idle {
+            true => value & (EEPM | 1 << EERIE),
+            false => self.eecr & EEPM | value & 1 << EERIE,
+        };
+        if eeprom.is_empty() || !idle {
+            return 0;
+        }
+        let address = self.eear as usize % eeprom.len();
+        if value >> EEPE & 1 == 1 && cycles < self.master && cycles >= self.flash_busy {
+            let (byte, time) = match self.eecr & EEPM {
+                0x00 => (self.eedr, EEPROM_ATOMIC),
+                0x10 => (0xff, EEPROM_SPLIT),
+                // Writing alone can only clear bits.
+                _ => (eeprom[address] & self.eedr, EEPROM_SPLIT),
+            };
+            eeprom[address] = byte;
+            self.eeprom_busy = cycles + time;
+            self.master = 0;
+            return 2;
+        }
+        if value >> EEMPE & 1 == 1 {
+            self.master = cycles + WINDOW;
+        }
+        if value >> EERE & 1 == 1 {
+            self.eedr = eeprom[address];
+            return 4;
+        }
+        0
+    }
+
+    /// Executes `spm` at cycle `cycles` with the byte address `z` and the
+    /// word `data` from r1:r0. It does nothing unless `SPMCSR` selected an
+    /// operation just before.
+    pub fn spm(&mut self, z: u32, data: u16, cycles: u64, flash: &mut [u8]) {
+        if cycles >= self.armed {
+            return;
+        }
+        self.armed = 0;
+        let size = self.layout.page as usize;
+        let offset = z as usize % size;
+        let page = (z as usize - offset).min(flash.len());
+        let end = (page + size).min(flash.len());
+        match self.spmcsr & 0x1e {
+            0 => {
+                let [low, high] = data.to_le_bytes();
+                self.buffer[offset & !1] = low;
+                self.buffer[offset | 1] = high;
+            }
+            operation if operation == 1 << PGERS => {
+                flash[page..end].fill(0xff);
+                self.flash_busy = cycles + PAGE;
+                self.rww_busy = true;
+            }
+            operation if operation == 1 << PGWRT => {
+                for (cell, byte) in flash[page..end].iter_mut().zip(&self.buffer) {
+                    *cell &= byte;
+                }
+                self.buffer.fill(0xff);
+                self.flash_busy = cycles + PAGE;
+                self.rww_busy = true;
+            }
+            operation if operation == 1 << RWWSRE => {
+                self.rww_busy = false;
+                self.buffer.fill(0xff);
+            }
+            // Lock bits and the signature row are not modelled.
+            _ => {}
+        }
+    }
+
+    /// Vector of the EEPROM or store program memory ready interrupt, when
+    /// enabled and the controller is idle.
+    pub fn pending(&self, cycles: u64) -> Option<u8> {
+        let eeprom = self.eecr >> EERIE & 1 == 1 && cycles >= self.eeprom_busy;
+        let flash = self.spmcsr >> SPMIE & 1 == 1 && cycles >= self.flash_busy.max(self.armed);
+        [
+            (eeprom, Some(self.layout.ee_ready)),
+            (flash, self.layout.spm_ready),
+        ]
+        .into_iter()
+        .filter_map(|(pending, vector)| vector.filter(|_| pending))
+        .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        device::{tests::vector, Device},
+        image::Image,
+        simulator::{Event, Simulator},
+    };
+    use clap::ValueEnum;
+
+    /// Writes 0xa5 to EEPROM address 0x12 in the `EEPM` mode held in r17
+    /// with `EECR` at I/O 0x1f, then enables the ready interrupt.
+    const READY_1F: &[u16] = &[
+        0xe102, // ldi r16, 0x12
+        0xbd01, // out EEARL, r16
+        0xea05, // ldi r16, 0xa5
+        0xbd00, // out EEDR, r16
+        0xbb1f, // out EECR, r17
+        0x9afa, // sbi EECR, EEMPE
+        0x9af9, // sbi EECR, EEPE
+        0x9afb, // sbi EECR, EERIE
+        0x9478, // sei
+        // loop:
+        0xcfff, // rjmp loop
+    ];
+
+    /// The same with `EECR` at I/O 0x1c.
+    const READY_1C: &[u16] = &[
+        0xe102, // ldi r16, 0x12
+        0xbb0e, // out EEARL, r16
+        0xea05, // ldi r16, 0xa5
+        0xbb0d, // out EEDR, r16
+        0xbb1c, // out EECR, r17
+        0x9ae2, // sbi EECR, EEMPE
+        0x9ae1, // sbi EECR, EEPE
+        0x9ae3, // sbi EECR, EERIE
+        0x9478, // sei
+        // loop:
+        0xcfff, // rjmp loop
+    ];
+
+    /// Cycle at which `EEPE` is set by the programs above.
+    const WRITE: u64 = 7;
+
+    fn simulator(device: Device, segments: &[(u32, &[u16])]) -> Simulator {
+        Simulator::new(device.profile(), &Image::from_words(segments))
+    }
+
+    /// Executes `steps` instructions.
+    fn execute(simulator: &mut Simulator, steps: usize) {
+        for _ in 0..steps {
+            assert_eq!(simulator.step(), Ok(Event::Executed));
+        }
+    }
+
+    /// The EEPROM write program of `device`.
+    fn eeprom_write(device: Device, mode: u8) -> Simulator {
+        let program = match device.profile().nvm.unwrap().eecr {
+            0x3f => READY_1F,
+            _ => READY_1C,
+        };
+        let mut simulator = simulator(device, &[(0, program)]);
+        simulator.registers[17] = mode;
+        simulator
+    }
+
+    #[test]
+    fn eeprom_ready_follows_a_write() {
+        for &device in Device::value_variants() {
+            let eecr = device.profile().nvm.unwrap().eecr;
+            let mut simulator = eeprom_write(device, 0);
+            execute(&mut simulator, 7);
+            // EEPE stays set until the write completes.
+            assert_eq!(simulator.peek(eecr) & 2, 2, "{:?}", device);
+            let ready = vector(device, &["EE_READY", "EE_RDY"]);
+            assert_eq!(simulator.run_until_interrupt(), ready, "{:?}", device);
+            let completed = WRITE + EEPROM_ATOMIC;
+            assert!((completed..completed + 10).contains(&simulator.cycles));
+            assert_eq!(simulator.peek(eecr) & 2, 0);
+            assert_eq!(simulator.eeprom[0x12], 0xa5, "{:?}", device);
+            let written = simulator.eeprom.iter().filter(|&&byte| byte != 0xff);
+            assert_eq!(written.count(), 1);
+        }
+    }
+
+    #[test]
+    fn eeprom_write_needs_the_master_enable() {
+        let mut alone = simulator(
+            Device::Atmega328p,
+            &[(0, &[0x9af9])], // sbi EECR, EEPE
+        );
+        execute(&mut alone, 1);
+        assert_eq!(alone.peek(0x3f), 0);
+        assert_eq!(alone.eeprom[0], 0xff);
+        // EEMPE is cleared by hardware four cycles after it is set.
+        let mut late = simulator(
+            Device::Atmega328p,
+            &[(
+                0,
+                &[
+                    0xe505, // ldi r16, 0x55
+                    0xbd00, // out EEDR, r16
+                    0x9afa, // sbi EECR, EEMPE
+                    0x0000, // nop
+                    0x0000, // nop
+                    0x9af9, // sbi EECR, EEPE
+                ],
+            )],
+        );
+        execute(&mut late, 6);
+        assert_eq!(late.eeprom[0], 0xff);
+    }
+
+    #[test]
+    fn eeprom_erases_and_writes_separately() {
+        // Erase only.
+        let mut erase = eeprom_write(Device::Atmega328p, 0x10);
+        erase.eeprom[0x12] = 0x00;
+        execute(&mut erase, 7);
+        assert_eq!(erase.eeprom[0x12], 0xff);
+        // Write only, which can only clear bits.
+        let mut write = eeprom_write(Device::Atmega328p, 0x20);
+        write.eeprom[0x12] = 0x5f;
+        execute(&mut write, 7);
+        assert_eq!(write.eeprom[0x12], 0x05);
+        // Both take half the time of an atomic write.
+        write.cycles = WRITE + EEPROM_SPLIT - 1;
+        assert_eq!(write.peek(0x3f) & 2, 2);
+        write.cycles += 1;
+        assert_eq!(write.peek(0x3f) & 2, 0);
+    }
+
+    #[test]
+    fn eeprom_read_halts_the_cpu() {
+        let mut simulator = simulator(
+            Device::Atmega328p,
+            &[(
+                0,
+                &[
+                    0xe304, // ldi r16, 0x34
+                    0xbd01, // out EEARL, r16
+                    0x9af8, // sbi EECR, EERE
+                    0xb510, // in r17, EEDR
+                ],
+            )],
+        );
+        simulator.eeprom[0x34] = 0x5a;
+        execute(&mut simulator, 4);
+        assert_eq!(simulator.registers[17], 0x5a);
+        assert_eq!(simulator.cycles, 1 + 1 + 2 + 4 + 1);
+    }
+
+    #[test]
+    fn spm_ready_follows_a_page_erase() {
+        for &device in Device::value_variants() {
+            let nvm = device.profile().nvm.unwrap();
+            if nvm.spm_ready.is_none() {
+                continue;
+            }
+            let page = nvm.page as u32;
+            // SPMIE, PGERS and SPMEN on the page at 0x200, the pages around
+            // it programmed with zeros.
+            let program: &[u16] = &[
+                0xe0e0, // ldi r30, 0
+                0xe0f2, // ldi r31, 2
+                0xe803, // ldi r16, 0x83
+                0xbf07, // out SPMCSR, r16
+                0x95e8, // spm
+                0x9478, // sei
+                // loop:
+                0xcfff, // rjmp loop
+            ];
+            let mut simulator = simulator(
+                device,
+                &[
+                    (0, program),
+                    (0x1fe, &[0]),
+                    (0x200, &[0, 0]),
+                    (0x200 + page, &[0]),
+                ],
+            );
+            let ready = vector(device, &["SPM_READY", "SPM_RDY"]);
+            assert_eq!(simulator.run_until_interrupt(), ready, "{:?}", device);
+            assert!((4 + PAGE..4 + PAGE + 10).contains(&simulator.cycles));
+            let flash = &simulator.flash;
+            assert!(flash[0x200..(0x200 + page) as usize]
+                .iter()
+                .all(|&byte| byte == 0xff));
+            assert_eq!(flash[0x1fe..0x200], [0, 0], "{:?}", device);
+            assert_eq!(flash[(0x200 + page) as usize], 0, "{:?}", device);
+        }
+    }
+
+    #[test]
+    fn spm_fills_erases_and_writes_a_page() {
+        let program: &[u16] = &[
+            0xe0e0, // ldi r30, 0
+            0xe0f1, // ldi r31, 1
+            0xe001, // ldi r16, 1
+            0xe384, // ldi r24, 0x34
+            0xe192, // ldi r25, 0x12
+            0x010c, // movw r0, r24
+            0xbf07, // out SPMCSR, r16
+            0x95e8, // spm
+            0x9632, // adiw r30, 2
+            0xe788, // ldi r24, 0x78
+            0xe596, // ldi r25, 0x56
+            0x010c, // movw r0, r24
+            0xbf07, // out SPMCSR, r16
+            0x95e8, // spm
+            0x9732, // sbiw r30, 2
+            0xe003, // ldi r16, 3
+            0xbf07, // out SPMCSR, r16
+            0x95e8, // spm
+            // erase:
+            0xb717, // in r17, SPMCSR
+            0xfd10, // sbrc r17, SPMEN
+            0xcffd, // rjmp erase
+            0xe005, // ldi r16, 5
+            0xbf07, // out SPMCSR, r16
+            0x95e8, // spm
+            // write:
+            0xb717, // in r17, SPMCSR
+            0xfd10, // sbrc r17, SPMEN
+            0xcffd, // rjmp write
+            // rww:
+            0xe101, // ldi r16, 0x11
+            0xbf07, // out SPMCSR, r16
+            0x95e8, // spm
+            // done:
+            0xcfff, // rjmp done
+        ];
+        let mut simulator = simulator(Device::Atmega328p, &[(0, program), (0x100, &[0, 0, 0])]);
+        while simulator.pc != 0x36 {
+            simulator.step().unwrap();
+        }
+        // RWWSB is set from the erase until the section is enabled again.
+        assert_eq!(simulator.peek(0x57), 0x40);
+        assert!(simulator.cycles > 2 * PAGE);
+        assert_eq!(
+            simulator.flash[0x100..0x106],
+            [0x34, 0x12, 0x78, 0x56, 0xff, 0xff]
+        );
+        while simulator.pc != 0x3c {
+            simulator.step().unwrap();
+        }
+        assert_eq!(simulator.peek(0x57), 0);
+    }
+
+    #[test]
+    fn spm_needs_spmcsr_just_before() {
+        let program: &[u16] = &[
+            0xe0e0, // ldi r30, 0
+            0xe0f1, // ldi r31, 1
+            0xe003, // ldi r16, 3
+            0xbf07, // out SPMCSR, r16
+            0x0000, // nop
+            0x0000, // nop
+            0x0000, // nop
+            0x0000, // nop
+            0x95e8, // spm
+        ];
+        let mut simulator = simulator(Device::Atmega328p, &[(0, program), (0x100, &[0])]);
+        execute(&mut simulator, 9);
+        assert_eq!(simulator.flash[0x100..0x102], [0, 0]);
+        assert_eq!(simulator.peek(0x57), 0);
+    }
+}
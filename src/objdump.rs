@@ -0,0 +1,155 @@
+use crate::{
+    image::Image,
+    instruction::{Instruction, Operand},
+    symbols::Symbols,
+};
+use std::{fmt::Write, ops::Range};
+
+/// Bytes shown in the raw column of each line.
+const OCTETS_PER_LINE: u32 = 4;
+/// A run of zero bytes this long is printed as `...`.
+const SKIP_ZEROES: u32 = 8;
+/// Zero bytes at the end of a section shorter than this are printed as `...`.
+const SKIP_ZEROES_AT_END: u32 = 3;
+
+/// Code address in a comment, followed by the closest symbol before it.
+fn code(address: u32, symbols: &Symbols) -> String {
+    match symbols.nearest(address) {
+        Some((name, 0)) => format!("0x{:x} <{}>", address, name),
+        Some((name, offset)) => format!("0x{:x} <{}+0x{:x}>", address, name, offset),
+        None => format!("0x{:x}", address),
+    }
+}
+
+/// Operand text and the comment avr-objdump prints for it.
+fn operand(
+    instruction: &Instruction,
+    address: u32,
+    operand: &Operand,
+    symbols: &Symbols,
+) -> (String, Option<String>) {
+    match *operand {
+        Operand::Register(d) | Operand::Pair(d) => (format!("r{}", d), None),
+        Operand::Immediate(k) => (
+            format!("0x{:02X}", k),
+            match instruction {
+                Instruction::Des { .. } => None,
+                _ => Some(k.to_string()),
+            },
+        ),
+        Operand::Bit(b) => (b.to_string(), None),
+        Operand::Io(a) => (format!("0x{:02x}", a), Some(a.to_string())),
+        Operand::Data(k) => (
+            format!("0x{:04X}", k),
+            Some(match symbols.data.get(&(k as u32)) {
+                Some(name) => format!("0x{:x} <{}>", 0x800000 | k as u32, name),
+                None => format!("0x{:x}", 0x800000 | k as u32),
+            }),
+        ),
+        // Printed with `%#x`, which leaves zero without a prefix.
+        Operand::Program(0) => ("0".to_string(), Some(code(0, symbols))),
+        Operand::Program(k) => (format!("{:#x}", k), Some(code(k, symbols))),
+        Operand::Relative(k) => (
+            format!(".{:<+8}", k),
+            instruction
+                .target(address)
+                .map(|target| code(target, symbols)),
+        ),
+        Operand::Indirect(..) => (operand.to_string(), None),
+        Operand::Displacement(pointer, q) => {
+            (format!("{}+{}", pointer, q), Some(format!("0x{:02x}", q)))
+        }
+    }
+}
+
+/// Instruction text after the raw bytes, mnemonics of avr-objdump use the
+/// flag and branch aliases only.
+fn text(instruction: &Instruction, address: u32, symbols: &Symbols) -> String {
+    let overloads = matches!(
+        instruction,
+        Instruction::Bset { .. }
+            | Instruction::Bclr { .. }
+            | Instruction::Brbs { .. }
+            | Instruction::Brbc { .. }
+    );
+    let mut text = instruction.mnemonic(overloads).to_string();
+    let mut comments = vec![];
+    for (i, operand) in instruction.operands(overloads).iter().enumerate() {
+        let (operand, comment) = self::operand(instruction, address, operand, symbols);
+        text.push_str(if i == 0 { "\t" } else { ", " });
+        text.push_str(&operand);
+        comments.extend(comment);
+    }
+    for (i, comment) in comments.iter().enumerate() {
+        text.push_str(if i == 0 { "\t; " } else { " " });
+        text.push_str(comment);
+    }
+    text
+}
+
+/// Lines of the loaded `range` laid out like `avr-objdump -d`, without the
+/// file and section headers. `range` may be part of a segment, as with
+/// `--start-address` and `--stop-address`. Each code symbol starts a block
+/// headed by its name.
+pub fn render(image: &Image, range: Range<u32>, symbols: &Symbols) -> String {
+    // Addresses are as wide as the end of the section needs, in steps of
+    // four digits.
+    let section = image
+        .segments()
+        .into_iter()
+        .find(|segment| segment.contains(&range.start))
+        .map_or(range.end, |segment| segment.end);
+    let zeros = format!("{:08x}", section)
+        .chars()
+        .take_while(|&digit| digit == '0')
+        .count();
+    let width = 8 - zeros.saturating_sub(1) / 4 * 4;
+    let mut output = String::new();
+    let mut address = range.start;
+    while address < range.end {
+        if let Some(name) = symbols.code.get(&address) {
+            let _ = writeln!(output, "\n{:08x} <{}>:", address, name);
+        }
+        // Zero runs stop at the next symbol, which heads its own block.
+        let limit = symbols
+            .code
+            .range(address + 1..range.end)
+            .next()
+            .map_or(range.end, |(&start, _)| start);
+        let mut zeros = 0;
+        while address + zeros < limit && image.byte(address + zeros) == Some(0) {
+            zeros += 1;
+        }
+        let end = address + zeros == limit;
+        if zeros >= SKIP_ZEROES || (end && zeros < SKIP_ZEROES_AT_END && zeros > 0) {
+            output.push_str("\t...\n");
+            address += match end {
+                true => zeros,
+                false => zeros & !3,
+            };
+            continue;
+        }
+        let (size, text) = match image.decode(address) {
+            Some(Ok(instruction)) if address + instruction.length() * 2 <= range.end => (
+                instruction.length() * 2,
+                text(&instruction, address, symbols),
+            ),
+            _ => {
+                let word = image.word(address).unwrap_or_default();
+                (2, format!(".word\t0x{:04x}\t; ????", word))
+            }
+        };
+        let _ = write!(output, "{:>width$x}:\t", address, width = width);
+        for i in 0..OCTETS_PER_LINE {
+            match (i < size).then(|| image.byte(address + i)).flatten() {
+                Some(byte) => {
+                    let _ = write!(output, "{:02x} ", byte);
+                }
+                None => output.push_str("   "),
+            }
+        }
+        let _ = writeln!(output, "\t{}", text);
+        address += size;
+    }
+    output
+}
@@ -0,0 +1,858 @@
+//! The AVR instruction set as a single table. Decoding, encoding, the
+//! printed operands, the `--bits` breakdown, the assembler, cycle counts and
+//! the `instructions` reference are all derived from [`OPCODES`].
+
+use crate::{
+    cycles,
+    device::Core,
+    instruction::{DecodeError, Instruction, Mode, Operand, Pointer},
+};
+use clap::ValueEnum;
+use std::{fmt::Write, sync::OnceLock};
+
+/// How the bits of a field become the value of an operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Any register, r0 to r31.
+    Register,
+    /// `d + 16`, one of the upper registers.
+    Upper,
+    /// `d * 2`, the low register of any pair.
+    Even,
+    /// `d * 2 + 24`, the low register of r25:24 to r31:30.
+    Word,
+    Immediate,
+    /// Bit number, in a register, an I/O register or `SREG`.
+    Bit,
+    Io,
+    /// Address in data memory.
+    Data,
+    /// Word address in program memory.
+    Program,
+    /// Signed word offset from the next instruction.
+    Relative,
+    /// Displacement from `Y` or `Z`.
+    Displacement,
+}
+
+impl Kind {
+    /// Value of the `width` bits `raw` of the field.
+    fn decode(self, raw: u32, width: u32) -> i64 {
+        let raw = raw as i64;
+        match self {
+            Kind::Upper => raw + 16,
+            Kind::Even => raw * 2,
+            Kind::Word => raw * 2 + 24,
+            Kind::Relative if raw >> (width - 1) & 1 == 1 => raw - (1 << width),
+            _ => raw,
+        }
+    }
+
+    /// Bits of `value` before they are cut to the width of the field.
+    fn raw(self, value: i64) -> u32 {
+        let raw = match self {
+            Kind::Upper => value - 16,
+            Kind::Even => value / 2,
+            Kind::Word => (value - 24) / 2,
+            _ => value,
+        };
+        raw as u32
+    }
+
+    /// Bits of `value` in a field of `width` bits, `None` when the field
+    /// cannot hold it.
+    fn encode(self, value: i64, width: u32) -> Option<u32> {
+        let raw = self.raw(value) & ((1 << width) - 1);
+        (self.decode(raw, width) == value).then_some(raw)
+    }
+
+    fn operand(self, value: i64) -> Operand {
+        match self {
+            Kind::Register | Kind::Upper => Operand::Register(value as u8),
+            Kind::Even | Kind::Word => Operand::Pair(value as u8),
+            Kind::Immediate => Operand::Immediate(value as u8),
+            Kind::Bit => Operand::Bit(value as u8),
+            Kind::Io => Operand::Io(value as u8),
+            Kind::Data => Operand::Data(value as u16),
+            Kind::Program => Operand::Program(value as u32 * 2),
+            Kind::Relative => Operand::Relative(value as i32 * 2),
+            Kind::Displacement => Operand::Immediate(value as u8),
+        }
+    }
+}
+
+/// Clock cycles of an opcode on the AVRe, AVRxm, AVRxt and AVRrc cores, in
+/// the columns of the instruction set manual.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clocks {
+    Fixed([u32; 4]),
+    /// One cycle more with a 22-bit program counter.
+    Wide([u32; 4]),
+    /// One cycle, two when the branch is taken.
+    Branch,
+    /// When the skip is performed, as many cycles more as the skipped
+    /// instruction has words.
+    Skip([u32; 4]),
+    /// As [`Clocks::Skip`], except that AVRrc cores take one cycle more
+    /// whatever they skip.
+    SkipIo([u32; 4]),
+}
+
+/// Cores an opcode is available on, after the multilib options of avr-gcc.
+#[derive(Debug, Clone, Copy)]
+pub struct Cores {
+    pub name: &'static str,
+    pub cores: &'static [Core],
+}
+
+const ALL: Cores = Cores {
+    name: "all",
+    cores: &[
+        Core::Avr1,
+        Core::Avr2,
+        Core::Avr25,
+        Core::Avr3,
+        Core::Avr31,
+        Core::Avr35,
+        Core::Avr4,
+        Core::Avr5,
+        Core::Avr51,
+        Core::Avr6,
+        Core::Xmega2,
+        Core::Xmega3,
+        Core::Xmega4,
+        Core::Xmega5,
+        Core::Xmega6,
+        Core::Xmega7,
+        Core::Tiny,
+    ],
+};
+const NOT_AVR1: Cores = Cores {
+    name: "all but avr1",
+    cores: ALL.cores.split_at(1).1,
+};
+const NOT_TINY: Cores = Cores {
+    name: "all but avrtiny",
+    cores: ALL.cores.split_at(16).0,
+};
+/// Cores with the full set of pointer and word instructions.
+const CLASSIC: Cores = Cores {
+    name: "avr2 and up",
+    cores: ALL.cores.split_at(16).0.split_at(1).1,
+};
+/// `movw`, `lpm Rd, Z` and `spm`.
+const ENHANCED: Cores = Cores {
+    name: "avr25, avr35, avr4 and up",
+    cores: &[
+        Core::Avr25,
+        Core::Avr35,
+        Core::Avr4,
+        Core::Avr5,
+        Core::Avr51,
+        Core::Avr6,
+        Core::Xmega2,
+        Core::Xmega3,
+        Core::Xmega4,
+        Core::Xmega5,
+        Core::Xmega6,
+        Core::Xmega7,
+    ],
+};
+const MULTIPLY: Cores = Cores {
+    name: "avr4 and up",
+    cores: ENHANCED.cores.split_at(2).1,
+};
+/// `jmp` and `call`, on devices with more than 8 KiB of flash.
+const LONG: Cores = Cores {
+    name: "avr3, avr31, avr35, avr5 and up",
+    cores: &[
+        Core::Avr3,
+        Core::Avr31,
+        Core::Avr35,
+        Core::Avr5,
+        Core::Avr51,
+        Core::Avr6,
+        Core::Xmega2,
+        Core::Xmega3,
+        Core::Xmega4,
+        Core::Xmega5,
+        Core::Xmega6,
+        Core::Xmega7,
+    ],
+};
+/// `elpm`, on devices with more than 64 KiB of flash.
+const EXTENDED: Cores = Cores {
+    name: "avr31, avr51, avr6, avrxmega4 and up",
+    cores: &[
+        Core::Avr31,
+        Core::Avr51,
+        Core::Avr6,
+        Core::Xmega4,
+        Core::Xmega5,
+        Core::Xmega6,
+        Core::Xmega7,
+    ],
+};
+/// `eijmp` and `eicall`, on devices with more than 128 KiB of flash.
+const EIND: Cores = Cores {
+    name: "avr6, avrxmega6, avrxmega7",
+    cores: &[Core::Avr6, Core::Xmega6, Core::Xmega7],
+};
+const XMEGA: Cores = Cores {
+    name: "avrxmega",
+    cores: ENHANCED.cores.split_at(6).1,
+};
+/// Cores with the AVRxm timing, which have `des` and the read-modify-write
+/// instructions.
+const AVRXM: Cores = Cores {
+    name: "avrxmega but avrxmega3",
+    cores: &[
+        Core::Xmega2,
+        Core::Xmega4,
+        Core::Xmega5,
+        Core::Xmega6,
+        Core::Xmega7,
+    ],
+};
+
+/// Values of the fields of an opcode, by their letter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Values(pub Vec<(char, i64)>);
+
+impl Values {
+    pub fn get(&self, letter: char) -> i64 {
+        self.0
+            .iter()
+            .find(|(field, _)| *field == letter)
+            .map_or(0, |(_, value)| *value)
+    }
+}
+
+/// An operand in the syntax of an opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    /// `{d}`, the value of a field.
+    Field(char),
+    /// A pointer register the opcode implies, such as `X+`.
+    Indirect(Pointer, Mode),
+    /// `Y+{q}`, a pointer register with the displacement of a field.
+    Displacement(Pointer, char),
+}
+
+/// One encoding of an instruction.
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    /// Bits of the first word and, for 32-bit opcodes, of the second one,
+    /// with the letters of the fields as in the instruction set manual.
+    pub pattern: &'static str,
+    /// Operands as printed, `{d}` standing for the value of field `d`.
+    pub syntax: &'static str,
+    pub fields: &'static [(char, Kind)],
+    pub cores: Cores,
+    pub clocks: Clocks,
+    /// `SREG` flags the instruction changes.
+    pub flags: &'static str,
+    pub summary: &'static str,
+    build: fn(&Values) -> Instruction,
+    split: fn(&Instruction) -> Option<Values>,
+}
+
+/// Defines [`OPCODES`] from one line per opcode: the [`Instruction`] it
+/// decodes to, with the kind and letter of each field and the value of the
+/// fields the opcode implies, then the mnemonic, bit pattern, syntax,
+/// cores, clocks, flags and a summary.
+macro_rules! opcodes {
+    ($(
+        $variant:ident { $($field:ident: $kind:ident $letter:literal),* }
+        $([$($fixed:ident: $($value:tt)::+),*])?
+        $mnemonic:literal $pattern:literal $syntax:literal
+        $cores:ident $clocks:expr, $flags:literal $summary:literal;
+    )*) => {
+        /// Every opcode, in the order decoding tries them.
+        pub static OPCODES: &[Opcode] = &[$(
+            Opcode {
+                mnemonic: $mnemonic,
+                pattern: $pattern,
+                syntax: $syntax,
+                fields: &[$(($letter, Kind::$kind)),*],
+                cores: $cores,
+                clocks: $clocks,
+                flags: $flags,
+                summary: $summary,
+                build: |_values| Instruction::$variant {
+                    $($field: _values.get($letter) as _,)*
+                    $($($fixed: $($value)::+,)*)?
+                },
+                split: |instruction| match *instruction {
+                    Instruction::$variant { $($field,)* $($($fixed: $($value)::+,)*)? } => {
+                        Some(Values(vec![$(($letter, $field as i64)),*]))
+                    }
+                    _ => None,
+                },
+            },
+        )*];
+    };
+}
+
+use Clocks::{Branch, Fixed, Skip, SkipIo, Wide};
+
+opcodes! {
+    Nop {} "nop" "0000_0000_0000_0000" "" ALL Fixed([1, 1, 1, 1]), "" "No operation";
+    Movw { d: Even 'd', r: Even 'r' } "movw" "0000_0001_dddd_rrrr" "{d}, {r}"
+        ENHANCED Fixed([1, 1, 1, 1]), "" "Copy register word";
+    Muls { d: Upper 'd', r: Upper 'r' } "muls" "0000_0010_dddd_rrrr" "{d}, {r}"
+        MULTIPLY Fixed([2, 2, 2, 2]), "ZC" "Multiply signed";
+    Mulsu { d: Upper 'd', r: Upper 'r' } "mulsu" "0000_0011_0ddd_0rrr" "{d}, {r}"
+        MULTIPLY Fixed([2, 2, 2, 2]), "ZC" "Multiply signed with unsigned";
+    Fmul { d: Upper 'd', r: Upper 'r' } "fmul" "0000_0011_0ddd_1rrr" "{d}, {r}"
+        MULTIPLY Fixed([2, 2, 2, 2]), "ZC" "Fractional multiply unsigned";
+    Fmuls { d: Upper 'd', r: Upper 'r' } "fmuls" "0000_0011_1ddd_0rrr" "{d}, {r}"
+        MULTIPLY Fixed([2, 2, 2, 2]), "ZC" "Fractional multiply signed";
+    Fmulsu { d: Upper 'd', r: Upper 'r' } "fmulsu" "0000_0011_1ddd_1rrr" "{d}, {r}"
+        MULTIPLY Fixed([2, 2, 2, 2]), "ZC" "Fractional multiply signed with unsigned";
+    Cpc { d: Register 'd', r: Register 'r' } "cpc" "0000_01rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Compare with carry";
+    Sbc { d: Register 'd', r: Register 'r' } "sbc" "0000_10rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Subtract with carry";
+    Add { d: Register 'd', r: Register 'r' } "add" "0000_11rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Add without carry";
+    Cpse { d: Register 'd', r: Register 'r' } "cpse" "0001_00rd_dddd_rrrr" "{d}, {r}"
+        ALL Skip([1, 1, 1, 1]), "" "Compare, skip if equal";
+    Cp { d: Register 'd', r: Register 'r' } "cp" "0001_01rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Compare";
+    Sub { d: Register 'd', r: Register 'r' } "sub" "0001_10rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Subtract without carry";
+    Adc { d: Register 'd', r: Register 'r' } "adc" "0001_11rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Add with carry";
+    And { d: Register 'd', r: Register 'r' } "and" "0010_00rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZ" "Logical AND";
+    Eor { d: Register 'd', r: Register 'r' } "eor" "0010_01rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZ" "Exclusive OR";
+    Or { d: Register 'd', r: Register 'r' } "or" "0010_10rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZ" "Logical OR";
+    Mov { d: Register 'd', r: Register 'r' } "mov" "0010_11rd_dddd_rrrr" "{d}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "" "Copy register";
+    Cpi { d: Upper 'd', k: Immediate 'K' } "cpi" "0011_KKKK_dddd_KKKK" "{d}, {K}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Compare with immediate";
+    Sbci { d: Upper 'd', k: Immediate 'K' } "sbci" "0100_KKKK_dddd_KKKK" "{d}, {K}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Subtract immediate with carry";
+    Subi { d: Upper 'd', k: Immediate 'K' } "subi" "0101_KKKK_dddd_KKKK" "{d}, {K}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Subtract immediate";
+    Ori { d: Upper 'd', k: Immediate 'K' } "ori" "0110_KKKK_dddd_KKKK" "{d}, {K}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZ" "Logical OR with immediate";
+    Andi { d: Upper 'd', k: Immediate 'K' } "andi" "0111_KKKK_dddd_KKKK" "{d}, {K}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZ" "Logical AND with immediate";
+    Ld { d: Register 'd' } [pointer: Pointer::Z, mode: Mode::Plain]
+        "ld" "1000_000d_dddd_0000" "{d}, Z" ALL Fixed([2, 1, 2, 1]), "" "Load indirect";
+    Ld { d: Register 'd' } [pointer: Pointer::Y, mode: Mode::Plain]
+        "ld" "1000_000d_dddd_1000" "{d}, Y" NOT_AVR1 Fixed([2, 1, 2, 1]), "" "Load indirect";
+    St { r: Register 'r' } [pointer: Pointer::Z, mode: Mode::Plain]
+        "st" "1000_001r_rrrr_0000" "Z, {r}" ALL Fixed([2, 1, 1, 1]), "" "Store indirect";
+    St { r: Register 'r' } [pointer: Pointer::Y, mode: Mode::Plain]
+        "st" "1000_001r_rrrr_1000" "Y, {r}" NOT_AVR1 Fixed([2, 1, 1, 1]), "" "Store indirect";
+    Lds { d: Register 'd', k: Data 'k' }
+        "lds" "1001_000d_dddd_0000_kkkk_kkkk_kkkk_kkkk" "{d}, {k}"
+        CLASSIC Fixed([2, 2, 3, 1]), "" "Load direct from data space";
+    Ld { d: Register 'd' } [pointer: Pointer::Z, mode: Mode::PostIncrement]
+        "ld" "1001_000d_dddd_0001" "{d}, Z+" NOT_AVR1 Fixed([2, 1, 2, 2]), "" "Load indirect";
+    Ld { d: Register 'd' } [pointer: Pointer::Z, mode: Mode::PreDecrement]
+        "ld" "1001_000d_dddd_0010" "{d}, -Z" NOT_AVR1 Fixed([2, 2, 2, 2]), "" "Load indirect";
+    Lpm { d: Register 'd' } [increment: false]
+        "lpm" "1001_000d_dddd_0100" "{d}, Z" ENHANCED Fixed([3, 3, 3, 3]), ""
+        "Load program memory";
+    Lpm { d: Register 'd' } [increment: true]
+        "lpm" "1001_000d_dddd_0101" "{d}, Z+" ENHANCED Fixed([3, 3, 3, 3]), ""
+        "Load program memory";
+    Elpm { d: Register 'd' } [increment: false]
+        "elpm" "1001_000d_dddd_0110" "{d}, Z" EXTENDED Fixed([3, 3, 3, 3]), ""
+        "Extended load program memory";
+    Elpm { d: Register 'd' } [increment: true]
+        "elpm" "1001_000d_dddd_0111" "{d}, Z+" EXTENDED Fixed([3, 3, 3, 3]), ""
+        "Extended load program memory";
+    Ld { d: Register 'd' } [pointer: Pointer::Y, mode: Mode::PostIncrement]
+        "ld" "1001_000d_dddd_1001" "{d}, Y+" NOT_AVR1 Fixed([2, 1, 2, 2]), "" "Load indirect";
+    Ld { d: Register 'd' } [pointer: Pointer::Y, mode: Mode::PreDecrement]
+        "ld" "1001_000d_dddd_1010" "{d}, -Y" NOT_AVR1 Fixed([2, 2, 2, 2]), "" "Load indirect";
+    Ld { d: Register 'd' } [pointer: Pointer::X, mode: Mode::Plain]
+        "ld" "1001_000d_dddd_1100" "{d}, X" NOT_AVR1 Fixed([2, 1, 2, 1]), "" "Load indirect";
+    Ld { d: Register 'd' } [pointer: Pointer::X, mode: Mode::PostIncrement]
+        "ld" "1001_000d_dddd_1101" "{d}, X+" NOT_AVR1 Fixed([2, 1, 2, 2]), "" "Load indirect";
+    Ld { d: Register 'd' } [pointer: Pointer::X, mode: Mode::PreDecrement]
+        "ld" "1001_000d_dddd_1110" "{d}, -X" NOT_AVR1 Fixed([2, 2, 2, 2]), "" "Load indirect";
+    Pop { d: Register 'd' } "pop" "1001_000d_dddd_1111" "{d}"
+        NOT_AVR1 Fixed([2, 2, 2, 3]), "" "Pop register from stack";
+    Sts { k: Data 'k', r: Register 'r' }
+        "sts" "1001_001r_rrrr_0000_kkkk_kkkk_kkkk_kkkk" "{k}, {r}"
+        CLASSIC Fixed([2, 2, 2, 1]), "" "Store direct to data space";
+    St { r: Register 'r' } [pointer: Pointer::Z, mode: Mode::PostIncrement]
+        "st" "1001_001r_rrrr_0001" "Z+, {r}" NOT_AVR1 Fixed([2, 1, 1, 1]), "" "Store indirect";
+    St { r: Register 'r' } [pointer: Pointer::Z, mode: Mode::PreDecrement]
+        "st" "1001_001r_rrrr_0010" "-Z, {r}" NOT_AVR1 Fixed([2, 2, 1, 1]), "" "Store indirect";
+    Xch { r: Register 'r' } "xch" "1001_001r_rrrr_0100" "Z, {r}"
+        AVRXM Fixed([2, 2, 2, 2]), "" "Exchange";
+    Las { r: Register 'r' } "las" "1001_001r_rrrr_0101" "Z, {r}"
+        AVRXM Fixed([2, 2, 2, 2]), "" "Load and set";
+    Lac { r: Register 'r' } "lac" "1001_001r_rrrr_0110" "Z, {r}"
+        AVRXM Fixed([2, 2, 2, 2]), "" "Load and clear";
+    Lat { r: Register 'r' } "lat" "1001_001r_rrrr_0111" "Z, {r}"
+        AVRXM Fixed([2, 2, 2, 2]), "" "Load and toggle";
+    St { r: Register 'r' } [pointer: Pointer::Y, mode: Mode::PostIncrement]
+        "st" "1001_001r_rrrr_1001" "Y+, {r}" NOT_AVR1 Fixed([2, 1, 1, 1]), "" "Store indirect";
+    St { r: Register 'r' } [pointer: Pointer::Y, mode: Mode::PreDecrement]
+        "st" "1001_001r_rrrr_1010" "-Y, {r}" NOT_AVR1 Fixed([2, 2, 1, 1]), "" "Store indirect";
+    St { r: Register 'r' } [pointer: Pointer::X, mode: Mode::Plain]
+        "st" "1001_001r_rrrr_1100" "X, {r}" NOT_AVR1 Fixed([2, 1, 1, 1]), "" "Store indirect";
+    St { r: Register 'r' } [pointer: Pointer::X, mode: Mode::PostIncrement]
+        "st" "1001_001r_rrrr_1101" "X+, {r}" NOT_AVR1 Fixed([2, 1, 1, 1]), "" "Store indirect";
+    St { r: Register 'r' } [pointer: Pointer::X, mode: Mode::PreDecrement]
+        "st" "1001_001r_rrrr_1110" "-X, {r}" NOT_AVR1 Fixed([2, 2, 1, 1]), "" "Store indirect";
+    Push { r: Register 'r' } "push" "1001_001r_rrrr_1111" "{r}"
+        NOT_AVR1 Fixed([2, 1, 1, 1]), "" "Push register on stack";
+    Ijmp {} "ijmp" "1001_0100_0000_1001" "" NOT_AVR1 Fixed([2, 2, 2, 2]), "" "Indirect jump to Z";
+    Eijmp {} "eijmp" "1001_0100_0001_1001" "" EIND Fixed([2, 2, 2, 2]), ""
+        "Extended indirect jump to EIND:Z";
+    Bset { s: Bit 's' } "bset" "1001_0100_0sss_1000" "{s}"
+        ALL Fixed([1, 1, 1, 1]), "ITHSVNZC" "Set flag";
+    Bclr { s: Bit 's' } "bclr" "1001_0100_1sss_1000" "{s}"
+        ALL Fixed([1, 1, 1, 1]), "ITHSVNZC" "Clear flag";
+    Des { k: Immediate 'K' } "des" "1001_0100_KKKK_1011" "{K}"
+        AVRXM Fixed([1, 1, 1, 1]), "" "Data encryption round";
+    Ret {} "ret" "1001_0101_0000_1000" "" ALL Wide([4, 4, 4, 6]), "" "Return from subroutine";
+    Icall {} "icall" "1001_0101_0000_1001" "" NOT_AVR1 Wide([3, 2, 2, 3]), "" "Indirect call to Z";
+    Reti {} "reti" "1001_0101_0001_1000" "" ALL Wide([4, 4, 4, 6]), "I" "Return from interrupt";
+    Eicall {} "eicall" "1001_0101_0001_1001" "" EIND Fixed([4, 3, 3, 3]), ""
+        "Extended indirect call to EIND:Z";
+    Sleep {} "sleep" "1001_0101_1000_1000" "" ALL Fixed([1, 1, 1, 1]), "" "Sleep";
+    Break {} "break" "1001_0101_1001_1000" "" NOT_AVR1 Fixed([1, 1, 1, 1]), "" "Break";
+    Wdr {} "wdr" "1001_0101_1010_1000" "" ALL Fixed([1, 1, 1, 1]), "" "Watchdog reset";
+    LpmR0 {} "lpm" "1001_0101_1100_1000" "" NOT_TINY Fixed([3, 3, 3, 3]), ""
+        "Load program memory into r0";
+    ElpmR0 {} "elpm" "1001_0101_1101_1000" "" EXTENDED Fixed([3, 3, 3, 3]), ""
+        "Extended load program memory into r0";
+    Spm {} "spm" "1001_0101_1110_1000" "" ENHANCED Fixed([1, 1, 1, 1]), ""
+        "Store program memory";
+    SpmZ {} "spm" "1001_0101_1111_1000" "Z+" XMEGA Fixed([1, 1, 1, 1]), ""
+        "Store program memory and post-increment Z";
+    Com { d: Register 'd' } "com" "1001_010d_dddd_0000" "{d}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZC" "One's complement";
+    Neg { d: Register 'd' } "neg" "1001_010d_dddd_0001" "{d}"
+        ALL Fixed([1, 1, 1, 1]), "HSVNZC" "Two's complement";
+    Swap { d: Register 'd' } "swap" "1001_010d_dddd_0010" "{d}"
+        ALL Fixed([1, 1, 1, 1]), "" "Swap nibbles";
+    Inc { d: Register 'd' } "inc" "1001_010d_dddd_0011" "{d}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZ" "Increment";
+    Asr { d: Register 'd' } "asr" "1001_010d_dddd_0101" "{d}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZC" "Arithmetic shift right";
+    Lsr { d: Register 'd' } "lsr" "1001_010d_dddd_0110" "{d}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZC" "Logical shift right";
+    Ror { d: Register 'd' } "ror" "1001_010d_dddd_0111" "{d}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZC" "Rotate right through carry";
+    Dec { d: Register 'd' } "dec" "1001_010d_dddd_1010" "{d}"
+        ALL Fixed([1, 1, 1, 1]), "SVNZ" "Decrement";
+    Jmp { k: Program 'k' } "jmp" "1001_010k_kkkk_110k_kkkk_kkkk_kkkk_kkkk" "{k}"
+        LONG Fixed([3, 3, 3, 3]), "" "Jump";
+    Call { k: Program 'k' } "call" "1001_010k_kkkk_111k_kkkk_kkkk_kkkk_kkkk" "{k}"
+        LONG Wide([4, 3, 3, 3]), "" "Call subroutine";
+    Adiw { d: Word 'd', k: Immediate 'K' } "adiw" "1001_0110_KKdd_KKKK" "{d}, {K}"
+        CLASSIC Fixed([2, 2, 2, 2]), "SVNZC" "Add immediate to word";
+    Sbiw { d: Word 'd', k: Immediate 'K' } "sbiw" "1001_0111_KKdd_KKKK" "{d}, {K}"
+        CLASSIC Fixed([2, 2, 2, 2]), "SVNZC" "Subtract immediate from word";
+    Cbi { a: Io 'A', b: Bit 'b' } "cbi" "1001_1000_AAAA_Abbb" "{A}, {b}"
+        ALL Fixed([2, 1, 1, 1]), "" "Clear bit in I/O register";
+    Sbic { a: Io 'A', b: Bit 'b' } "sbic" "1001_1001_AAAA_Abbb" "{A}, {b}"
+        ALL SkipIo([1, 2, 1, 1]), "" "Skip if bit in I/O register is cleared";
+    Sbi { a: Io 'A', b: Bit 'b' } "sbi" "1001_1010_AAAA_Abbb" "{A}, {b}"
+        ALL Fixed([2, 1, 1, 1]), "" "Set bit in I/O register";
+    Sbis { a: Io 'A', b: Bit 'b' } "sbis" "1001_1011_AAAA_Abbb" "{A}, {b}"
+        ALL SkipIo([1, 2, 1, 1]), "" "Skip if bit in I/O register is set";
+    Mul { d: Register 'd', r: Register 'r' } "mul" "1001_11rd_dddd_rrrr" "{d}, {r}"
+        MULTIPLY Fixed([2, 2, 2, 2]), "ZC" "Multiply unsigned";
+    In { d: Register 'd', a: Io 'A' } "in" "1011_0AAd_dddd_AAAA" "{d}, {A}"
+        ALL Fixed([1, 1, 1, 1]), "" "In from I/O location";
+    Out { a: Io 'A', r: Register 'r' } "out" "1011_1AAr_rrrr_AAAA" "{A}, {r}"
+        ALL Fixed([1, 1, 1, 1]), "" "Out to I/O location";
+    Std { q: Displacement 'q', r: Register 'r' } [pointer: Pointer::Z]
+        "std" "10q0_qq1r_rrrr_0qqq" "Z+{q}, {r}" CLASSIC Fixed([2, 2, 1, 1]), ""
+        "Store indirect with displacement";
+    Std { q: Displacement 'q', r: Register 'r' } [pointer: Pointer::Y]
+        "std" "10q0_qq1r_rrrr_1qqq" "Y+{q}, {r}" CLASSIC Fixed([2, 2, 1, 1]), ""
+        "Store indirect with displacement";
+    Ldd { d: Register 'd', q: Displacement 'q' } [pointer: Pointer::Z]
+        "ldd" "10q0_qq0d_dddd_0qqq" "{d}, Z+{q}" CLASSIC Fixed([2, 2, 2, 2]), ""
+        "Load indirect with displacement";
+    Ldd { d: Register 'd', q: Displacement 'q' } [pointer: Pointer::Y]
+        "ldd" "10q0_qq0d_dddd_1qqq" "{d}, Y+{q}" CLASSIC Fixed([2, 2, 2, 2]), ""
+        "Load indirect with displacement";
+    Rjmp { k: Relative 'k' } "rjmp" "1100_kkkk_kkkk_kkkk" "{k}"
+        ALL Fixed([2, 2, 2, 2]), "" "Relative jump";
+    Rcall { k: Relative 'k' } "rcall" "1101_kkkk_kkkk_kkkk" "{k}"
+        ALL Wide([3, 2, 2, 3]), "" "Relative call to subroutine";
+    Ldi { d: Upper 'd', k: Immediate 'K' } "ldi" "1110_KKKK_dddd_KKKK" "{d}, {K}"
+        ALL Fixed([1, 1, 1, 1]), "" "Load immediate";
+    Brbs { s: Bit 's', k: Relative 'k' } "brbs" "1111_00kk_kkkk_ksss" "{s}, {k}"
+        ALL Branch, "" "Branch if flag set";
+    Brbc { s: Bit 's', k: Relative 'k' } "brbc" "1111_01kk_kkkk_ksss" "{s}, {k}"
+        ALL Branch, "" "Branch if flag cleared";
+    Bld { d: Register 'd', b: Bit 'b' } "bld" "1111_100d_dddd_0bbb" "{d}, {b}"
+        ALL Fixed([1, 1, 1, 1]), "" "Bit load from T to register";
+    Bst { d: Register 'd', b: Bit 'b' } "bst" "1111_101d_dddd_0bbb" "{d}, {b}"
+        ALL Fixed([1, 1, 1, 1]), "T" "Bit store from register to T";
+    Sbrc { r: Register 'r', b: Bit 'b' } "sbrc" "1111_110r_rrrr_0bbb" "{r}, {b}"
+        ALL Skip([1, 1, 1, 1]), "" "Skip if bit in register is cleared";
+    Sbrs { r: Register 'r', b: Bit 'b' } "sbrs" "1111_111r_rrrr_0bbb" "{r}, {b}"
+        ALL Skip([1, 1, 1, 1]), "" "Skip if bit in register is set";
+}
+
+/// How an alias rewrites the operands of the instruction it stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rewrite {
+    /// Its register is both operands, `lsl r24` is `add r24, r24`.
+    Twice,
+    /// The first operand is implied, `breq .+2` is `brbs 1, .+2`.
+    First(i64),
+    /// The last operand is implied, `ser r16` is `ldi r16, 0xff`.
+    Last(i64),
+}
+
+/// A mnemonic standing for another one with some operands implied.
+pub struct Alias {
+    pub mnemonic: &'static str,
+    pub of: &'static str,
+    pub rewrite: Rewrite,
+}
+
+const fn alias(mnemonic: &'static str, of: &'static str, rewrite: Rewrite) -> Alias {
+    Alias {
+        mnemonic,
+        of,
+        rewrite,
+    }
+}
+
+/// Aliases printed in place of the instructions they stand for, the first
+/// that applies winning, and accepted by the assembler.
+pub const ALIASES: &[Alias] = &[
+    alias("lsl", "add", Rewrite::Twice),
+    alias("rol", "adc", Rewrite::Twice),
+    alias("tst", "and", Rewrite::Twice),
+    alias("clr", "eor", Rewrite::Twice),
+    alias("ser", "ldi", Rewrite::Last(0xff)),
+    alias("sec", "bset", Rewrite::First(0)),
+    alias("sez", "bset", Rewrite::First(1)),
+    alias("sen", "bset", Rewrite::First(2)),
+    alias("sev", "bset", Rewrite::First(3)),
+    alias("ses", "bset", Rewrite::First(4)),
+    alias("seh", "bset", Rewrite::First(5)),
+    alias("set", "bset", Rewrite::First(6)),
+    alias("sei", "bset", Rewrite::First(7)),
+    alias("clc", "bclr", Rewrite::First(0)),
+    alias("clz", "bclr", Rewrite::First(1)),
+    alias("cln", "bclr", Rewrite::First(2)),
+    alias("clv", "bclr", Rewrite::First(3)),
+    alias("cls", "bclr", Rewrite::First(4)),
+    alias("clh", "bclr", Rewrite::First(5)),
+    alias("clt", "bclr", Rewrite::First(6)),
+    alias("cli", "bclr", Rewrite::First(7)),
+    alias("brcs", "brbs", Rewrite::First(0)),
+    alias("breq", "brbs", Rewrite::First(1)),
+    alias("brmi", "brbs", Rewrite::First(2)),
+    alias("brvs", "brbs", Rewrite::First(3)),
+    alias("brlt", "brbs", Rewrite::First(4)),
+    alias("brhs", "brbs", Rewrite::First(5)),
+    alias("brts", "brbs", Rewrite::First(6)),
+    alias("brie", "brbs", Rewrite::First(7)),
+    alias("brlo", "brbs", Rewrite::First(0)),
+    alias("brcc", "brbc", Rewrite::First(0)),
+    alias("brne", "brbc", Rewrite::First(1)),
+    alias("brpl", "brbc", Rewrite::First(2)),
+    alias("brvc", "brbc", Rewrite::First(3)),
+    alias("brge", "brbc", Rewrite::First(4)),
+    alias("brhc", "brbc", Rewrite::First(5)),
+    alias("brtc", "brbc", Rewrite::First(6)),
+    alias("brid", "brbc", Rewrite::First(7)),
+    alias("brsh", "brbc", Rewrite::First(0)),
+];
+
+/// Numeric value of an immediate or bit operand.
+fn constant(operand: &Operand) -> Option<i64> {
+    match *operand {
+        Operand::Immediate(k) | Operand::Bit(k) => Some(k as i64),
+        _ => None,
+    }
+}
+
+impl Alias {
+    /// The operands of the alias, if it stands for the instruction with
+    /// `operands`.
+    pub fn applies(&self, operands: &[Operand]) -> Option<Vec<Operand>> {
+        match (self.rewrite, operands) {
+            (Rewrite::Twice, [d, r]) if d == r => Some(vec![*d]),
+            (Rewrite::First(value), [first, rest @ ..]) if constant(first) == Some(value) => {
+                Some(rest.to_vec())
+            }
+            (Rewrite::Last(value), [rest @ .., last]) if constant(last) == Some(value) => {
+                Some(rest.to_vec())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Positions of the bits of a field in a pattern, most significant first,
+/// counted from the least significant bit of the whole pattern.
+struct Compiled {
+    /// Fixed bits of the first word.
+    mask: u16,
+    value: u16,
+    words: u32,
+    fields: Vec<(char, Kind, Vec<u32>)>,
+}
+
+struct Decoder {
+    compiled: Vec<Compiled>,
+    /// Opcode each first word decodes with.
+    first: Vec<Option<u8>>,
+}
+
+fn compile(opcode: &Opcode) -> Compiled {
+    let bits: Vec<char> = opcode.pattern.chars().filter(|&c| c != '_').collect();
+    let size = bits.len() as u32;
+    let (mut mask, mut value) = (0u16, 0u16);
+    let mut fields: Vec<(char, Kind, Vec<u32>)> = opcode
+        .fields
+        .iter()
+        .map(|&(letter, kind)| (letter, kind, vec![]))
+        .collect();
+    for (i, &bit) in bits.iter().enumerate() {
+        let position = size - 1 - i as u32;
+        match bit {
+            '0' | '1' => {
+                if position >= size - 16 {
+                    mask |= 1 << (position - (size - 16));
+                    value |= ((bit == '1') as u16) << (position - (size - 16));
+                }
+            }
+            letter => {
+                if let Some(field) = fields.iter_mut().find(|field| field.0 == letter) {
+                    field.2.push(position);
+                }
+            }
+        }
+    }
+    Compiled {
+        mask,
+        value,
+        words: size / 16,
+        fields,
+    }
+}
+
+fn decoder() -> &'static Decoder {
+    static DECODER: OnceLock<Decoder> = OnceLock::new();
+    DECODER.get_or_init(|| {
+        let compiled: Vec<Compiled> = OPCODES.iter().map(compile).collect();
+        let first = (0..=u16::MAX)
+            .map(|word| {
+                compiled
+                    .iter()
+                    .position(|opcode| word & opcode.mask == opcode.value)
+                    .map(|index| index as u8)
+            })
+            .collect();
+        Decoder { compiled, first }
+    })
+}
+
+/// Decodes the instruction starting with `word`, `next` is the following
+/// program word which is consumed by 32-bit opcodes only.
+pub fn decode(word: u16, next: Option<u16>) -> Result<Instruction, DecodeError> {
+    let decoder = decoder();
+    let index = decoder.first[word as usize].ok_or(DecodeError::UnexpectedCommand(word))? as usize;
+    let compiled = &decoder.compiled[index];
+    let bits = match compiled.words {
+        2 => (word as u32) << 16 | next.ok_or(DecodeError::MissingExtension(word))? as u32,
+        _ => word as u32,
+    };
+    let values = compiled
+        .fields
+        .iter()
+        .map(|(letter, kind, positions)| {
+            let raw = positions
+                .iter()
+                .fold(0, |raw, position| raw << 1 | bits >> position & 1);
+            (*letter, kind.decode(raw, positions.len() as u32))
+        })
+        .collect();
+    Ok((OPCODES[index].build)(&Values(values)))
+}
+
+/// The opcode `instruction` is encoded with and the values of its fields.
+pub fn find(instruction: &Instruction) -> (&'static Opcode, Values) {
+    OPCODES
+        .iter()
+        .find_map(|opcode| Some((opcode, (opcode.split)(instruction)?)))
+        .expect("every instruction has an opcode")
+}
+
+/// Opcodes written with `mnemonic`, in table order.
+pub fn named(mnemonic: &str) -> impl Iterator<Item = &'static Opcode> + '_ {
+    OPCODES
+        .iter()
+        .filter(move |opcode| opcode.mnemonic == mnemonic)
+}
+
+impl Opcode {
+    /// Size in 16-bit words.
+    pub fn words(&self) -> u32 {
+        self.pattern.chars().filter(|&c| c != '_').count() as u32 / 16
+    }
+
+    pub fn available(&self, core: Core) -> bool {
+        self.cores.cores.contains(&core)
+    }
+
+    /// Operands of the syntax in order.
+    pub fn tokens(&self) -> Vec<Token> {
+        let field = |text: &str| {
+            let mut chars = text.strip_prefix('{')?.strip_suffix('}')?.chars();
+            chars.next().filter(|_| chars.next().is_none())
+        };
+        let pointer = |name: &str| match name {
+            "X" => Some(Pointer::X),
+            "Y" => Some(Pointer::Y),
+            "Z" => Some(Pointer::Z),
+            _ => None,
+        };
+        self.syntax
+            .split(", ")
+            .filter(|text| !text.is_empty())
+            .map(|text| {
+                if let Some(letter) = field(text) {
+                    return Token::Field(letter);
+                }
+                if let Some(pointer) = pointer(text) {
+                    return Token::Indirect(pointer, Mode::Plain);
+                }
+                if let Some(pointer) = text.strip_prefix('-').and_then(pointer) {
+                    return Token::Indirect(pointer, Mode::PreDecrement);
+                }
+                match text.split_once('+') {
+                    Some((name, "")) => {
+                        Token::Indirect(pointer(name).unwrap(), Mode::PostIncrement)
+                    }
+                    Some((name, letter)) => {
+                        Token::Displacement(pointer(name).unwrap(), field(letter).unwrap())
+                    }
+                    None => panic!("{}: not an operand of {}", text, self.mnemonic),
+                }
+            })
+            .collect()
+    }
+
+    pub fn kind(&self, letter: char) -> Kind {
+        self.fields
+            .iter()
+            .find(|field| field.0 == letter)
+            .map_or(Kind::Immediate, |field| field.1)
+    }
+
+    /// Typed operands of the opcode with `values`, as printed.
+    pub fn operands(&self, values: &Values) -> Vec<Operand> {
+        self.tokens()
+            .into_iter()
+            .map(|token| match token {
+                Token::Field(letter) => self.kind(letter).operand(values.get(letter)),
+                Token::Indirect(pointer, mode) => Operand::Indirect(pointer, mode),
+                Token::Displacement(pointer, letter) => {
+                    Operand::Displacement(pointer, values.get(letter) as u8)
+                }
+            })
+            .collect()
+    }
+
+    fn compiled(&self) -> &'static Compiled {
+        let index = OPCODES
+            .iter()
+            .position(|opcode| std::ptr::eq(opcode, self))
+            .expect("opcodes come from the table");
+        &decoder().compiled[index]
+    }
+
+    /// Program words of the opcode with `values`, `None` when a value does
+    /// not fit its field.
+    pub fn encode(&self, values: &Values) -> Option<Vec<u16>> {
+        let compiled = self.compiled();
+        let fits = compiled.fields.iter().all(|(letter, kind, positions)| {
+            kind.encode(values.get(*letter), positions.len() as u32)
+                .is_some()
+        });
+        fits.then(|| self.place(values))
+    }
+
+    /// Program words of the opcode with `values` cut to the width of their
+    /// fields.
+    pub fn place(&self, values: &Values) -> Vec<u16> {
+        let compiled = self.compiled();
+        let mut bits = (compiled.value as u32) << ((compiled.words - 1) * 16);
+        for (letter, kind, positions) in &compiled.fields {
+            let raw = kind.raw(values.get(*letter));
+            for (i, position) in positions.iter().rev().enumerate() {
+                bits |= (raw >> i & 1) << position;
+            }
+        }
+        match compiled.words {
+            2 => vec![(bits >> 16) as u16, bits as u16],
+            _ => vec![bits as u16],
+        }
+    }
+}
+
+/// Reference of the instruction set as a Markdown table, with the cycles
+/// on `core`.
+pub fn reference(core: Core) -> String {
+    let name = core
+        .to_possible_value()
+        .map_or(String::new(), |value| value.get_name().to_string());
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "| Mnemonic | Operands | Opcode | Cycles on {} | Flags | Cores | Description |",
+        name
+    );
+    let _ = writeln!(text, "|---|---|---|---|---|---|---|");
+    for opcode in OPCODES {
+        let pattern: Vec<&str> = opcode.pattern.split('_').collect();
+        let _ = writeln!(
+            text,
+            "| `{}` | {} | `{}` | {} | {} | {} | {} |",
+            opcode.mnemonic,
+            match opcode.syntax {
+                "" => String::new(),
+                syntax => format!("`{}`", syntax.replace(['{', '}'], "")),
+            },
+            pattern.join(" "),
+            match opcode.available(core) {
+                true => cycles::clocks(opcode.clocks, core, 1).to_string(),
+                false => "-".to_string(),
+            },
+            opcode.flags,
+            opcode.cores.name,
+            opcode.summary
+        );
+    }
+    let _ = writeln!(text, "\n| Alias | Stands for |\n|---|---|");
+    for alias in ALIASES {
+        let _ = writeln!(
+            text,
+            "| `{}` | `{}` {} |",
+            alias.mnemonic,
+            alias.of,
+            match alias.rewrite {
+                Rewrite::Twice => "with the register twice".to_string(),
+                Rewrite::First(value) => format!("with {} first", value),
+                Rewrite::Last(value) => format!("with {:#x} last", value),
+            }
+        );
+    }
+    text
+}
@@ -0,0 +1,763 @@
+use crate::device::{self, Profile};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+/// A device on the data bus of the simulator, owning the I/O registers at
+/// its addresses.
+pub trait Peripheral {
+    /// Data addresses of the registers of the peripheral.
+    fn addresses(&self) -> Vec<u16>;
+    /// Value of a register, without the side effects of a read.
+    fn peek(&self, address: u16) -> u8;
+    /// Value of a register read by the program.
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+    fn write(&mut self, address: u16, value: u8);
+    /// Advances the peripheral by `cycles` clock cycles.
+    fn tick(&mut self, _cycles: u64) {}
+    /// Lowest vector whose interrupt is both flagged and enabled.
+    fn pending(&self) -> Option<u8> {
+        None
+    }
+    /// The core entered the handler of `vector`, flags cleared by hardware
+    /// on entry are cleared.
+    fn acknowledge(&mut self, _vector: u8) {}
+    /// State of the peripheral printed after a run.
+    fn summary(&self) -> Option<String> {
+        None
+    }
+}
+
+/// A GPIO port, `PINx` reads back the output and pull-up levels as no pins
+/// are driven from outside.
+pub struct Port {
+    name: char,
+    pin: u16,
+    ddr: u8,
+    port: u8,
+}
+
+impl Peripheral for Port {
+    fn addresses(&self) -> Vec<u16> {
+        vec![self.pin, self.pin + 1, self.pin + 2]
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address - self.pin {
+            1 => self.ddr,
+            _ => self.port,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address - self.pin {
+            // Writing ones to PINx toggles those bits of PORTx.
+            0 => self.port ^= value,
+            1 => self.ddr = value,
+            _ => self.port = value,
+        }
+    }
+
+    fn summary(&self) -> Option<String> {
+        Some(format!(
+            "port{}  ddr {:#04x}  port {:#04x}",
+            self.name, self.ddr, self.port
+        ))
+    }
+}
+
+/// Clock dividers selected by the `CS` bits of `TCCRxB`, zero when stopped
+/// or clocked from a pin.
+const PRESCALERS: [u64; 8] = [0, 1, 8, 64, 256, 1024, 0, 0];
+
+/// Bits of `TIFRx` and `TIMSKx`.
+const TOV: u8 = 0;
+const OCFA: u8 = 1;
+const OCFB: u8 = 2;
+
+/// Timer/counter with overflow and compare match interrupts, in the normal,
+/// CTC, fast PWM and phase correct modes. Output compare pins and input
+/// capture are not modelled.
+pub struct TimerCounter {
+    name: &'static str,
+    layout: device::Timer,
+    tccra: u8,
+    tccrb: u8,
+    tcnt: u16,
+    icr: u16,
+    ocra: u16,
+    ocrb: u16,
+    timsk: u8,
+    tifr: u8,
+    /// High byte shared by the 16-bit registers, latched when the low byte
+    /// is read and written with it.
+    temp: u8,
+    /// Cycles counted towards the next timer clock.
+    prescaled: u64,
+    /// Counting down in a phase correct mode.
+    down: bool,
+}
+
+impl TimerCounter {
+    pub fn new(name: &'static str, layout: device::Timer) -> Self {
+        TimerCounter {
+            name,
+            layout,
+            tccra: 0,
+            tccrb: 0,
+            tcnt: 0,
+            icr: 0,
+            ocra: 0,
+            ocrb: 0,
+            timsk: 0,
+            tifr: 0,
+            temp: 0,
+            prescaled: 0,
+            down: false,
+        }
+    }
+
+    fn max(&self) -> u16 {
+        match self.layout.wide {
+            true => 0xffff,
+            false => 0xff,
+        }
+    }
+
+    fn wgm(&self) -> u8 {
+        let high = match self.layout.wide {
+            true => self.tccrb >> 3 & 3,
+            false => self.tccrb >> 3 & 1,
+        };
+        high << 2 | self.tccra & 3
+    }
+
+    /// TOP of the counter, whether it counts up and down, and whether the
+    /// mode clears it on compare match.
+    fn mode(&self) -> (u16, bool, bool) {
+        match (self.layout.wide, self.wgm()) {
+            (false, 1) => (0xff, true, false),
+            (false, 2) => (self.ocra, false, true),
+            (false, 3) => (0xff, false, false),
+            (false, 5) => (self.ocra, true, false),
+            (false, 7) => (self.ocra, false, false),
+            (true, 1) => (0xff, true, false),
+            (true, 2) => (0x1ff, true, false),
+            (true, 3) => (0x3ff, true, false),
+            (true, 4) => (self.ocra, false, true),
+            (true, 5) => (0xff, false, false),
+            (true, 6) => (0x1ff, false, false),
+            (true, 7) => (0x3ff, false, false),
+            (true, 8) | (true, 10) => (self.icr, true, false),
+            (true, 9) | (true, 11) => (self.ocra, true, false),
+            (true, 12) => (self.icr, false, true),
+            (true, 14) => (self.icr, false, false),
+            (true, 15) => (self.ocra, false, false),
+            _ => (self.max(), false, false),
+        }
+    }
+
+    /// One count of the timer clock.
+    fn clock(&mut self) {
+        let (top, dual, ctc) = self.mode();
+        if dual {
+            if self.down {
+                self.tcnt = self.tcnt.saturating_sub(1);
+                if self.tcnt == 0 {
+                    self.down = false;
+                    self.tifr |= 1 << TOV;
+                }
+            } else if self.tcnt >= top {
+                self.down = true;
+                self.tcnt = self.tcnt.saturating_sub(1);
+            } else {
+                self.tcnt += 1;
+            }
+        } else if self.tcnt == top {
+            self.tcnt = 0;
+            if !ctc || top == self.max() {
+                self.tifr |= 1 << TOV;
+            }
+        } else if self.tcnt == self.max() {
+            // A TOP written below the count is missed until the wrap.
+            self.tcnt = 0;
+            self.tifr |= 1 << TOV;
+        } else {
+            self.tcnt += 1;
+        }
+        if self.tcnt == self.ocra {
+            self.tifr |= 1 << OCFA;
+        }
+        if self.tcnt == self.ocrb {
+            self.tifr |= 1 << OCFB;
+        }
+    }
+
+    /// The 16-bit register whose low byte is at `low`.
+    fn wide(&mut self, low: u16) -> Option<&mut u16> {
+        let layout = self.layout;
+        match low {
+            low if low == layout.tcnt => Some(&mut self.tcnt),
+            low if Some(low) == layout.icr => Some(&mut self.icr),
+            low if low == layout.ocra => Some(&mut self.ocra),
+            low if low == layout.ocrb => Some(&mut self.ocrb),
+            _ => None,
+        }
+    }
+
+    /// Flag bit and vector of each interrupt.
+    fn interrupts(&self) -> [(u8, u8); 3] {
+        let [compa, compb, overflow] = self.layout.vectors;
+        [(OCFA, compa), (OCFB, compb), (TOV, overflow)]
+    }
+}
+
+impl Peripheral for TimerCounter {
+    fn addresses(&self) -> Vec<u16> {
+        let layout = self.layout;
+        let mut addresses = vec![layout.tccra, layout.tccrb, layout.timsk, layout.tifr];
+        let registers = [
+            Some(layout.tcnt),
+            layout.icr,
+            Some(layout.ocra),
+            Some(layout.ocrb),
+        ];
+        for low in registers.into_iter().flatten() {
+            addresses.push(low);
+            if layout.wide {
+                addresses.push(low + 1);
+            }
+        }
+        addresses
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let layout = self.layout;
+        let value = |register: u16, high: bool| match high {
+            true => (register >> 8) as u8,
+            false => register as u8,
+        };
+        match address {
+            address if address == layout.tccra => self.tccra,
+            address if address == layout.tccrb => self.tccrb,
+            address if address == layout.timsk => self.timsk,
+            address if address == layout.tifr => self.tifr,
+            address => {
+                let high = layout.wide
+                    && [layout.tcnt, layout.ocra, layout.ocrb]
+                        .iter()
+                        .chain(&layout.icr)
+                        .any(|&low| address == low + 1);
+                let low = address - high as u16;
+                match low {
+                    low if low == layout.tcnt => value(self.tcnt, high),
+                    low if Some(low) == layout.icr => value(self.icr, high),
+                    low if low == layout.ocra => value(self.ocra, high),
+                    _ => value(self.ocrb, high),
+                }
+            }
+        }
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        if !self.layout.wide {
+            return self.peek(address);
+        }
+        let value = self.peek(address);
+        match self.wide(address).map(|register| (*register >> 8) as u8) {
+            Some(high) => {
+                self.temp = high;
+                value
+            }
+            None if self.wide(address - 1).is_some() => self.temp,
+            None => value,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let layout = self.layout;
+        match address {
+            address if address == layout.tccra => self.tccra = value,
+            address if address == layout.tccrb => self.tccrb = value,
+            address if address == layout.timsk => self.timsk = value,
+            // Flags are cleared by writing ones to them.
+            address if address == layout.tifr => self.tifr &= !value,
+            address if !layout.wide => {
+                if let Some(register) = self.wide(address) {
+                    *register = value as u16;
+                }
+            }
+            address => {
+                let temp = self.temp;
+                match self.wide(address) {
+                    Some(register) => *register = (temp as u16) << 8 | value as u16,
+                    None => self.temp = value,
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        let divider = PRESCALERS[(self.tccrb & 7) as usize];
+        if divider == 0 {
+            return;
+        }
+        self.prescaled += cycles;
+        while self.prescaled >= divider {
+            self.prescaled -= divider;
+            self.clock();
+        }
+    }
+
+    fn pending(&self) -> Option<u8> {
+        self.interrupts()
+            .into_iter()
+            .filter(|(bit, _)| (self.tifr & self.timsk) >> bit & 1 == 1)
+            .map(|(_, vector)| vector)
+            .min()
+    }
+
+    fn acknowledge(&mut self, vector: u8) {
+        for (bit, flagged) in self.interrupts() {
+            if flagged == vector {
+                self.tifr &= !(1 << bit);
+            }
+        }
+    }
+
+    fn summary(&self) -> Option<String> {
+        let width = match self.layout.wide {
+            true => 6,
+            false => 4,
+        };
+        Some(format!(
+            "{}  tcnt {:#0width$x}  tifr {:#04x}  timsk {:#04x}",
+            self.name, self.tcnt, self.tifr, self.timsk
+        ))
+    }
+}
+
+/// Where a USART gets the bytes it receives.
+pub enum Input {
+    /// Everything the program will receive, such as the contents of a file.
+    Bytes(VecDeque<u8>),
+    /// Standard input, read on a thread once the receiver is enabled.
+    Stdin(Option<Receiver<u8>>),
+}
+
+impl Input {
+    fn next(&mut self) -> Option<u8> {
+        match self {
+            Input::Bytes(bytes) => bytes.pop_front(),
+            Input::Stdin(receiver) => {
+                let receiver = receiver.get_or_insert_with(|| {
+                    let (sender, receiver) = mpsc::channel();
+                    thread::spawn(move || {
+                        for byte in io::stdin().lock().bytes() {
+                            match byte {
+                                Ok(byte) if sender.send(byte).is_ok() => {}
+                                _ => break,
+                            }
+                        }
+                    });
+                    receiver
+                });
+                receiver.try_recv().ok()
+            }
+        }
+    }
+}
+
+/// Bits of `UCSRxA`.
+const RXC: u8 = 7;
+const TXC: u8 = 6;
+const UDRE: u8 = 5;
+const U2X: u8 = 1;
+/// Bits of `UCSRxB`.
+const RXCIE: u8 = 7;
+const TXCIE: u8 = 6;
+const UDRIE: u8 = 5;
+const RXEN: u8 = 4;
+const TXEN: u8 = 3;
+
+/// Asynchronous USART. Transmitted bytes are written out at once, received
+/// bytes arrive at most one frame time of the baud rate apart.
+pub struct Serial {
+    layout: device::Usart,
+    /// The writable bits of `UCSRxA`.
+    ucsra: u8,
+    ucsrb: u8,
+    ucsrc: u8,
+    ubrr: u16,
+    received: Option<u8>,
+    transmitted: bool,
+    /// Cycles until the next byte can arrive.
+    wait: u64,
+    input: Input,
+    output: Box<dyn Write>,
+}
+
+impl Serial {
+    pub fn new(layout: device::Usart, input: Input, output: Box<dyn Write>) -> Self {
+        Serial {
+            layout,
+            ucsra: 0,
+            ucsrb: 0,
+            // Eight data bits, as after reset.
+            ucsrc: 0x06,
+            ubrr: 0,
+            received: None,
+            transmitted: false,
+            wait: 0,
+            input,
+            output,
+        }
+    }
+
+    fn enabled(&self, bit: u8) -> bool {
+        self.ucsrb >> bit & 1 == 1
+    }
+
+    /// Clock cycles of a frame with a start bit, eight data bits and a
+    /// stop bit.
+    fn frame(&self) -> u64 {
+        let divider = match self.ucsra >> U2X & 1 == 1 {
+            true => 8,
+            false => 16,
+        };
+        10 * divider * (self.ubrr as u64 + 1)
+    }
+
+    fn status(&self) -> u8 {
+        (self.received.is_some() as u8) << RXC
+            | (self.transmitted as u8) << TXC
+            | 1 << UDRE
+            | self.ucsra
+    }
+}
+
+impl Peripheral for Serial {
+    fn addresses(&self) -> Vec<u16> {
+        let layout = self.layout;
+        vec![
+            layout.ucsra,
+            layout.ucsrb,
+            layout.ucsrc,
+            layout.ubrrl,
+            layout.ubrrh,
+            layout.udr,
+        ]
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let layout = self.layout;
+        match address {
+            address if address == layout.ucsra => self.status(),
+            address if address == layout.ucsrb => self.ucsrb,
+            address if address == layout.ucsrc => self.ucsrc,
+            address if address == layout.ubrrl => self.ubrr as u8,
+            address if address == layout.ubrrh => (self.ubrr >> 8) as u8,
+            _ => self.received.unwrap_or(0),
+        }
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        match address == self.layout.udr {
+            true => self.received.take().unwrap_or(0),
+            false => self.peek(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let layout = self.layout;
+        match address {
+            address if address == layout.ucsra => {
+                if value >> TXC & 1 == 1 {
+                    self.transmitted = false;
+                }
+                self.ucsra = value & 0x03;
+            }
+            address if address == layout.ucsrb => self.ucsrb = value,
+            address if address == layout.ucsrc => self.ucsrc = value,
+            address if address == layout.ubrrl => self.ubrr = self.ubrr & 0x0f00 | value as u16,
+            address if address == layout.ubrrh => {
+                self.ubrr = self.ubrr & 0xff | (value as u16 & 0x0f) << 8;
+            }
+            _ => {
+                if self.enabled(TXEN) {
+                    let _ = self.output.write_all(&[value]);
+                    let _ = self.output.flush();
+                    self.transmitted = true;
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if !self.enabled(RXEN) || self.received.is_some() {
+            return;
+        }
+        self.wait = self.wait.saturating_sub(cycles);
+        if self.wait == 0 {
+            if let Some(byte) = self.input.next() {
+                self.received = Some(byte);
+                self.wait = self.frame();
+            }
+        }
+    }
+
+    fn pending(&self) -> Option<u8> {
+        let [rx, udre, tx] = self.layout.vectors;
+        [
+            (self.enabled(RXCIE) && self.received.is_some(), rx),
+            (self.enabled(UDRIE), udre),
+            (self.enabled(TXCIE) && self.transmitted, tx),
+        ]
+        .into_iter()
+        .filter(|(pending, _)| *pending)
+        .map(|(_, vector)| vector)
+        .min()
+    }
+
+    fn acknowledge(&mut self, vector: u8) {
+        if vector == self.layout.vectors[2] {
+            self.transmitted = false;
+        }
+    }
+}
+
+/// The GPIO ports, timers and USART of a device profile, the USART
+/// receiving from `input` and transmitting to `output`.
+pub fn standard(
+    profile: &Profile,
+    input: Input,
+    output: Box<dyn Write>,
+) -> Vec<Box<dyn Peripheral>> {
+    let mut peripherals: Vec<Box<dyn Peripheral>> = vec![];
+    for &(name, pin) in profile.ports {
+        peripherals.push(Box::new(Port {
+            name,
+            pin,
+            ddr: 0,
+            port: 0,
+        }));
+    }
+    if let Some(timer) = profile.timer0 {
+        peripherals.push(Box::new(TimerCounter::new("timer0", timer)));
+    }
+    if let Some(timer) = profile.timer1 {
+        peripherals.push(Box::new(TimerCounter::new("timer1", timer)));
+    }
+    if let Some(usart) = profile.usart {
+        peripherals.push(Box::new(Serial::new(usart, input, output)));
+    }
+    peripherals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        device::{tests::vector, Device},
+        image::Image,
+        simulator::Simulator,
+    };
+    use clap::ValueEnum;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Bytes transmitted by the USART, shared with the test.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// `program` at address zero of `device` with its standard peripherals,
+    /// the USART receiving `input`.
+    fn simulator(device: Device, program: &[u16], input: &[u8]) -> (Simulator, Output) {
+        let profile = device.profile();
+        let mut simulator = Simulator::new(profile, &Image::from_words(&[(0, program)]));
+        let output = Output::default();
+        let input = Input::Bytes(VecDeque::from(input.to_vec()));
+        for peripheral in standard(&profile, input, Box::new(output.clone())) {
+            simulator.attach(peripheral);
+        }
+        (simulator, output)
+    }
+
+    /// Devices with their timers and USART modelled.
+    fn modelled() -> impl Iterator<Item = Device> {
+        Device::value_variants()
+            .iter()
+            .copied()
+            .filter(|device| device.profile().usart.is_some())
+    }
+
+    #[test]
+    fn prescaler_divides_the_clock() {
+        let (mut simulator, _) = simulator(
+            Device::Atmega328p,
+            &[
+                0xe003, // ldi r16, 3
+                0xbd05, // out TCCR0B, r16
+                // loop:
+                0xcfff, // rjmp loop
+            ],
+            &[],
+        );
+        while simulator.peek(0x35) & 1 == 0 {
+            simulator.step().unwrap();
+            if simulator.cycles == 2 + 100 * 64 {
+                assert_eq!(simulator.peek(0x46), 100);
+            }
+        }
+        // CS02:0 = 3 divides the clock by 64 from the cycle of the out, 256
+        // counts to the overflow.
+        assert_eq!(simulator.cycles, 2 + 256 * 64);
+        assert_eq!(simulator.peek(0x46), 0);
+        // Writing a one clears TOV0.
+        simulator.write(0x35, 1);
+        assert_eq!(simulator.peek(0x35) & 1, 0);
+    }
+
+    #[test]
+    fn timer0_overflow_enters_its_vector() {
+        for device in modelled() {
+            let (mut simulator, _) = simulator(
+                device,
+                &[
+                    0xe001, // ldi r16, 1
+                    0x9300, 0x006e, // sts TIMSK0, r16
+                    0xbd05, // out TCCR0B, r16
+                    0x9478, // sei
+                    // loop:
+                    0xcfff, // rjmp loop
+                ],
+                &[],
+            );
+            let overflow = vector(device, &["TIMER0_OVF"]);
+            assert_eq!(simulator.run_until_interrupt(), overflow, "{:?}", device);
+            assert_eq!(
+                simulator.pc,
+                overflow as u32 * simulator.profile.vector_size()
+            );
+            // TOV0 is cleared by entering the handler.
+            assert_eq!(simulator.peek(0x35) & 1, 0, "{:?}", device);
+            assert!(simulator.cycles > 256);
+        }
+    }
+
+    #[test]
+    fn timer1_compare_match_clears_in_ctc_mode() {
+        for device in modelled() {
+            // OCR1A = 299 written high byte first, WGM12 with no prescaling
+            // and OCIE1A.
+            let (mut simulator, _) = simulator(
+                device,
+                &[
+                    0xe001, // ldi r16, 1
+                    0x9300, 0x0089, // sts OCR1AH, r16
+                    0xe20b, // ldi r16, 43
+                    0x9300, 0x0088, // sts OCR1AL, r16
+                    0xe009, // ldi r16, 9
+                    0x9300, 0x0081, // sts TCCR1B, r16
+                    0xe002, // ldi r16, 2
+                    0x9300, 0x006f, // sts TIMSK1, r16
+                    0x9478, // sei
+                    // loop:
+                    0xcfff, // rjmp loop
+                ],
+                &[],
+            );
+            let compare = vector(device, &["TIMER1_COMPA"]);
+            assert_eq!(simulator.run_until_interrupt(), compare, "{:?}", device);
+            assert_eq!((simulator.peek(0x89), simulator.peek(0x88)), (1, 43));
+            assert_eq!(simulator.peek(0x36) & 2, 0);
+            // The count restarted from zero at the match, without TOV1.
+            let count = (simulator.peek(0x85) as u16) << 8 | simulator.peek(0x84) as u16;
+            assert!(count < 16, "{:?}: {}", device, count);
+            assert_eq!(simulator.peek(0x36) & 1, 0);
+        }
+    }
+
+    #[test]
+    fn usart_echoes_received_bytes() {
+        // UBRR0 = 0, RXEN0 and TXEN0, each byte sent back incremented.
+        let (mut simulator, output) = simulator(
+            Device::Atmega328p,
+            &[
+                0xe108, // ldi r16, 0x18
+                0x9300, 0x00c1, // sts UCSR0B, r16
+                // wait:
+                0x9110, 0x00c0, // lds r17, UCSR0A
+                0xff17, // sbrs r17, RXC0
+                0xcffc, // rjmp wait
+                0x9120, 0x00c6, // lds r18, UDR0
+                0x9523, // inc r18
+                0x9320, 0x00c6, // sts UDR0, r18
+                0xcff6, // rjmp wait
+            ],
+            b"abc",
+        );
+        while simulator.cycles < 1000 {
+            simulator.step().unwrap();
+        }
+        assert_eq!(*output.0.borrow(), b"bcd");
+        // TXC0 and UDRE0 are set, RXC0 cleared by reading UDR0.
+        assert_eq!(simulator.peek(0xc0), 0x60);
+    }
+
+    #[test]
+    fn usart_does_not_transmit_when_disabled() {
+        let (mut simulator, output) = simulator(
+            Device::Atmega328p,
+            &[
+                0xe505, // ldi r16, 0x55
+                0x9300, 0x00c6, // sts UDR0, r16
+            ],
+            &[],
+        );
+        simulator.step().unwrap();
+        simulator.step().unwrap();
+        assert!(output.0.borrow().is_empty());
+        assert_eq!(simulator.peek(0xc0) & 0x40, 0);
+    }
+
+    #[test]
+    fn usart_receive_enters_its_vector() {
+        for device in modelled() {
+            let usart = device.profile().usart.unwrap();
+            let (mut simulator, _) = simulator(
+                device,
+                &[
+                    0xe900, // ldi r16, 0x90
+                    0x9300,
+                    usart.ucsrb, // sts UCSRnB, r16
+                    0x9478,      // sei
+                    // loop:
+                    0xcfff, // rjmp loop
+                ],
+                &[0x55],
+            );
+            let receive = vector(device, &["USART_RX", "USART0_RX", "USART1_RX"]);
+            assert_eq!(simulator.run_until_interrupt(), receive, "{:?}", device);
+            // RXC stays set until UDR is read.
+            assert_eq!(simulator.peek(usart.ucsra) & 0x80, 0x80);
+            assert_eq!(simulator.read(usart.udr), 0x55);
+            assert_eq!(simulator.peek(usart.ucsra) & 0x80, 0);
+        }
+    }
+}
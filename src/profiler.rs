@@ -0,0 +1,178 @@
+use crate::{
+    color::{Palette, Style},
+    instruction::Instruction,
+    simulator::{Event, Simulator, Snapshot},
+    symbols::Symbols,
+};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// A function running on the call stack of the simulated program.
+struct Frame {
+    entry: u32,
+    /// Stack pointer before the return address was pushed, reached again
+    /// when the function returns.
+    sp: u16,
+    /// Cycles at the call or the interrupt.
+    start: u64,
+}
+
+/// Cycles and calls of a function.
+#[derive(Default)]
+struct Totals {
+    calls: u64,
+    /// Cycles of its own instructions.
+    exclusive: u64,
+    /// Cycles of its own instructions and of the functions it called.
+    inclusive: u64,
+}
+
+/// Calls from one function to another and the cycles they took.
+#[derive(Default)]
+struct Arc {
+    calls: u64,
+    cycles: u64,
+}
+
+/// Cycles spent in each function of a simulated run, following calls,
+/// returns and interrupts on a shadow call stack.
+pub struct Profiler {
+    stack: Vec<Frame>,
+    functions: BTreeMap<u32, Totals>,
+    /// Calls keyed by caller and callee.
+    arcs: BTreeMap<(u32, u32), Arc>,
+    /// Cycles of executed instructions, without the cycles asleep.
+    cycles: u64,
+}
+
+impl Profiler {
+    /// A profile of a run starting at `simulator`'s program counter, which is
+    /// charged to the function there.
+    pub fn new(simulator: &Simulator) -> Self {
+        let mut functions = BTreeMap::new();
+        functions.insert(simulator.pc, Totals::default());
+        Profiler {
+            stack: vec![Frame {
+                entry: simulator.pc,
+                sp: simulator.sp,
+                start: simulator.cycles,
+            }],
+            functions,
+            arcs: BTreeMap::new(),
+            cycles: 0,
+        }
+    }
+
+    fn enter(&mut self, entry: u32, sp: u16, start: u64) {
+        if let Some(caller) = self.stack.last() {
+            self.arcs.entry((caller.entry, entry)).or_default().calls += 1;
+        }
+        self.functions.entry(entry).or_default().calls += 1;
+        self.stack.push(Frame { entry, sp, start });
+    }
+
+    /// Accounts for an instruction or an interrupt entry. Calls are charged
+    /// to the function they call, returns to the one that returns.
+    pub fn record(&mut self, before: &Snapshot, event: Event, after: &Simulator) {
+        let instruction = after.fetch(before.pc).and_then(Result::ok);
+        match (event, instruction) {
+            (Event::Interrupt(_), _) => self.enter(after.pc, before.sp, before.cycles),
+            (
+                Event::Executed,
+                Some(
+                    Instruction::Call { .. }
+                    | Instruction::Rcall { .. }
+                    | Instruction::Icall
+                    | Instruction::Eicall,
+                ),
+            ) if after.sp < before.sp => self.enter(after.pc, before.sp, before.cycles),
+            _ => {}
+        }
+        let cycles = after.cycles - before.cycles;
+        self.cycles += cycles;
+        if let Some(top) = self.stack.last() {
+            self.functions.entry(top.entry).or_default().exclusive += cycles;
+        }
+        let mut charged: Vec<u32> = vec![];
+        for frame in &self.stack {
+            if !charged.contains(&frame.entry) {
+                charged.push(frame.entry);
+                self.functions.entry(frame.entry).or_default().inclusive += cycles;
+            }
+        }
+        if let Some(Instruction::Ret | Instruction::Reti) = instruction {
+            // Returns unwind every frame whose return address was popped.
+            while self.stack.len() > 1 && self.stack.last().is_some_and(|top| top.sp <= after.sp) {
+                let frame = self.stack.pop().unwrap();
+                let caller = self.stack.last().map_or(0, |caller| caller.entry);
+                let arc = self.arcs.entry((caller, frame.entry)).or_default();
+                arc.cycles += after.cycles - frame.start;
+            }
+        }
+    }
+
+    /// Flat profile of the functions by the cycles of their own
+    /// instructions, followed by the call graph with the callers (`<-`) and
+    /// callees (`->`) of each function. Cycles of an arc are those of the
+    /// calls that returned.
+    pub fn report(&self, symbols: &Symbols, palette: Palette) -> String {
+        let name = |entry: u32| match symbols.code.get(&entry) {
+            Some(name) => format!("{:#x} <{}>", entry, name),
+            None => format!("{:#x}", entry),
+        };
+        let percent = |cycles: u64| match self.cycles {
+            0 => 0.0,
+            total => cycles as f64 * 100.0 / total as f64,
+        };
+        let mut text = String::new();
+        let _ = writeln!(text, "flat profile, {} cycles executed", self.cycles);
+        let _ = writeln!(
+            text,
+            "{:>12} {:>7} {:>12} {:>7} {:>8}  function",
+            "self", "%", "total", "%", "calls"
+        );
+        let mut flat: Vec<(&u32, &Totals)> = self.functions.iter().collect();
+        flat.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+        for (&entry, totals) in flat {
+            let _ = writeln!(
+                text,
+                "{:>12} {:>6.2}% {:>12} {:>6.2}% {:>8}  {}",
+                totals.exclusive,
+                percent(totals.exclusive),
+                totals.inclusive,
+                percent(totals.inclusive),
+                totals.calls,
+                palette.paint(Style::Label, name(entry))
+            );
+        }
+        let _ = writeln!(text, "\ncall graph");
+        for (&entry, totals) in &self.functions {
+            let _ = writeln!(
+                text,
+                "{}: {} calls, {} cycles, {} in itself",
+                palette.paint(Style::Label, name(entry)),
+                totals.calls,
+                totals.inclusive,
+                totals.exclusive
+            );
+            for (&(caller, _), arc) in self.arcs.iter().filter(|(arc, _)| arc.1 == entry) {
+                let _ = writeln!(
+                    text,
+                    "    <- {}: {} calls, {} cycles",
+                    name(caller),
+                    arc.calls,
+                    arc.cycles
+                );
+            }
+            for (&(_, callee), arc) in self.arcs.range((entry, 0)..=(entry, u32::MAX)) {
+                let _ = writeln!(
+                    text,
+                    "    -> {}: {} calls, {} cycles",
+                    name(callee),
+                    arc.calls,
+                    arc.cycles
+                );
+            }
+        }
+        text
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{
+    color::{Palette, Style},
+    data::{self, Data},
+    device::Core,
+    image::Image,
+    instruction::{Instruction, Operand},
+    listing::{self, Content, Line},
+    symbols::Symbols,
+};
+use clap::ValueEnum;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    ops::Range,
+};
+
+/// Name of the label at `address`, the symbol defined there if any.
+pub fn label(address: u32, symbols: &Symbols) -> String {
+    match symbols.code.get(&address) {
+        Some(name) => name.clone(),
+        None => format!("L_{:x}", address),
+    }
+}
+
+/// Operand in avr-as syntax, targets and pointers to data that start a
+/// line get a label.
+fn operand(
+    instruction: &Instruction,
+    address: u32,
+    operand: &Operand,
+    labels: &BTreeSet<u32>,
+    data: &Data,
+    symbols: &Symbols,
+) -> String {
+    let label = |address| label(address, symbols);
+    let labelled = |target: Option<u32>| target.filter(|target| labels.contains(target));
+    match (*operand, instruction) {
+        (Operand::Pair(d), _) => format!("r{}", d),
+        (Operand::Relative(_) | Operand::Program(_), _) => {
+            match labelled(instruction.target(address)) {
+                Some(target) => label(target),
+                None => operand.to_string(),
+            }
+        }
+        (Operand::Data(k), _) => match symbols.data.get(&(k as u32)) {
+            Some(name) => name.clone(),
+            None => operand.to_string(),
+        },
+        (Operand::Immediate(k), Instruction::Ldi { d, .. }) => {
+            match labelled(data.loads.get(&address).copied()) {
+                Some(target) if d % 2 == 0 && k == target as u8 => {
+                    format!("lo8({})", label(target))
+                }
+                Some(target) if d % 2 == 1 && k == (target >> 8) as u8 => {
+                    format!("hi8({})", label(target))
+                }
+                _ => operand.to_string(),
+            }
+        }
+        _ => operand.to_string(),
+    }
+}
+
+fn bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04x}", byte)).collect();
+    bytes.join(", ")
+}
+
+/// How [`render`] writes the source.
+#[derive(Debug, Clone, Copy)]
+pub struct Options<'a> {
+    /// Core named in the `-mmcu` comment.
+    pub core: Core,
+    pub overloads: bool,
+    pub palette: Palette,
+    /// Addresses to render, loaded segments are cut to it.
+    pub window: &'a Range<u32>,
+}
+
+/// The part of the image inside the window as a `.S` file avr-as builds
+/// back into the same bytes, each loaded segment placed with `.org`.
+/// Symbols name the labels and the data addresses, which are defined with
+/// `.set`, and annotated comments follow their lines.
+pub fn render(
+    image: &Image,
+    data: &Data,
+    resolved: &BTreeMap<u32, Vec<u32>>,
+    symbols: &Symbols,
+    options: Options,
+) -> String {
+    let Options {
+        core,
+        overloads,
+        palette,
+        window,
+    } = options;
+    let label = |address| label(address, symbols);
+    let segments: Vec<(u32, Vec<Line>)> = image
+        .segments_in(window)
+        .into_iter()
+        .map(|segment| (segment.start, listing::sweep(image, data, segment)))
+        .collect();
+    let starts: BTreeSet<u32> = segments
+        .iter()
+        .flat_map(|(_, lines)| lines.iter().map(|line| line.address))
+        .collect();
+    let mut labels = BTreeSet::new();
+    for (_, lines) in &segments {
+        for line in lines {
+            if let Content::Instruction { instruction, .. } = &line.content {
+                labels.extend(instruction.target(line.address));
+                labels.extend(resolved.get(&line.address).into_iter().flatten());
+            }
+        }
+    }
+    labels.extend(data.loads.values());
+    labels.extend(symbols.code.keys());
+    labels.retain(|address| starts.contains(address));
+    let mut output = String::new();
+    let core = core
+        .to_possible_value()
+        .map_or(String::new(), |value| value.get_name().to_string());
+    let _ = writeln!(
+        output,
+        "{}",
+        palette.paint(Style::Comment, format!("; avr-as -mmcu={}", core))
+    );
+    for (address, name) in &symbols.data {
+        let _ = writeln!(output, "\t.set {}, {:#x}", name, address);
+    }
+    output.push_str("\t.text\n");
+    for (start, lines) in &segments {
+        let _ = writeln!(output, "\t.org {:#x}", start);
+        for line in lines {
+            let address = line.address;
+            if labels.contains(&address) {
+                let _ = writeln!(output, "{}:", palette.paint(Style::Label, label(address)));
+            }
+            match &line.content {
+                Content::Instruction { instruction, .. } => {
+                    let _ = write!(output, "\t{}", instruction.mnemonic(overloads));
+                    for (i, operand) in instruction.operands(overloads).iter().enumerate() {
+                        output.push_str(if i == 0 { " " } else { ", " });
+                        output.push_str(&self::operand(
+                            instruction,
+                            address,
+                            operand,
+                            &labels,
+                            data,
+                            symbols,
+                        ));
+                    }
+                    if let Some(targets) = resolved.get(&address) {
+                        let targets: Vec<String> = targets
+                            .iter()
+                            .map(|&target| match labels.contains(&target) {
+                                true => label(target),
+                                false => format!("{:#x}", target),
+                            })
+                            .collect();
+                        let _ = write!(
+                            output,
+                            " {}",
+                            palette.paint(Style::Comment, format!("; {}", targets.join(", ")))
+                        );
+                    }
+                }
+                Content::String(text) => {
+                    let _ = write!(output, "\t.asciz \"{}\"", data::escape(text));
+                }
+                Content::Bytes(values) => {
+                    let _ = write!(output, "\t.byte {}", bytes(values));
+                }
+                Content::Word(word) => {
+                    let _ = write!(output, "\t.word {:#06x}", word);
+                }
+            }
+            if let Some(note) = symbols.comments.get(&address) {
+                let _ = write!(
+                    output,
+                    " {}",
+                    palette.paint(Style::Comment, format!("; {}", note))
+                );
+            }
+            output.push('\n');
+        }
+    }
+    output
+}
@@ -0,0 +1,150 @@
+use crate::hexdump;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum Index {
+    Data = 0,
+    End = 1,
+    AddressSegment = 2,
+    StartAddress80x86 = 3,
+    ExtendedAddress = 4,
+    LinearAdrres = 5,
+}
+
+pub struct Record {
+    pub address: u16,
+    pub index: Index,
+    pub data: Vec<(u8, u8)>,
+    /// Number of data bytes, less than twice the pairs when the last one
+    /// is padded.
+    pub size: usize,
+}
+
+#[derive(Debug)]
+pub enum RecordParseError {
+    BeginningOfRecord,
+    CalculatingTheSize,
+    CalculatingTheAddress,
+    CalculatingIndex,
+    CalculatingData,
+    CalculatingChecksum,
+}
+
+impl Record {
+    /// Parses a record such as `:02000000FFCF30`, checking its length and
+    /// checksum. The last pair of an odd number of data bytes is padded
+    /// with 0xff, which [`Record::bytes`] leaves out.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(hex: &str) -> Result<Self, RecordParseError> {
+        let digits = hex
+            .strip_prefix(':')
+            .ok_or(RecordParseError::BeginningOfRecord)?;
+        // `from_str_radix` alone would accept a sign.
+        let byte = |index: usize, error: RecordParseError| {
+            digits
+                .get(index * 2..index * 2 + 2)
+                .filter(|text| text.bytes().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|text| u8::from_str_radix(text, 16).ok())
+                .ok_or(error)
+        };
+        let size = byte(0, RecordParseError::CalculatingTheSize)?;
+        let high = byte(1, RecordParseError::CalculatingTheAddress)?;
+        let low = byte(2, RecordParseError::CalculatingTheAddress)?;
+        let kind = byte(3, RecordParseError::CalculatingIndex)?;
+        let index = match kind {
+            0 => Index::Data,
+            1 => Index::End,
+            2 => Index::AddressSegment,
+            3 => Index::StartAddress80x86,
+            4 => Index::ExtendedAddress,
+            5 => Index::LinearAdrres,
+            _ => return Err(RecordParseError::CalculatingIndex),
+        };
+        let bytes = (4..4 + size as usize)
+            .map(|i| byte(i, RecordParseError::CalculatingData))
+            .collect::<Result<Vec<u8>, _>>()?;
+        let checksum = byte(4 + size as usize, RecordParseError::CalculatingChecksum)?;
+        let sum = [size, high, low, kind]
+            .iter()
+            .chain(&bytes)
+            .fold(checksum, |sum, byte| sum.wrapping_add(*byte));
+        if sum != 0 || digits.len() != (5 + size as usize) * 2 {
+            return Err(RecordParseError::CalculatingChecksum);
+        }
+        Ok(Record {
+            address: u16::from_be_bytes([high, low]),
+            index,
+            data: bytes
+                .chunks(2)
+                .map(|pair| (pair.get(1).copied().unwrap_or(0xff), pair[0]))
+                .collect(),
+            size: bytes.len(),
+        })
+    }
+}
+
+impl Record {
+    /// Data bytes in the order they are written in the record, without the
+    /// pad of an odd count.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.data
+            .iter()
+            .flat_map(|content| [content.1, content.0])
+            .take(self.size)
+            .collect()
+    }
+}
+
+/// Intel HEX file of `segments`, each a start address and its bytes, in
+/// records of at most 16 bytes with extended linear address records above
+/// 64 KiB.
+pub fn intel_hex(segments: &[(u32, &[u8])]) -> String {
+    fn record(text: &mut String, kind: u8, address: u16, data: &[u8]) {
+        let mut bytes = vec![data.len() as u8];
+        bytes.extend(address.to_be_bytes());
+        bytes.push(kind);
+        bytes.extend(data);
+        let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        bytes.push(sum.wrapping_neg());
+        text.push(':');
+        for byte in bytes {
+            text.push_str(&format!("{:02X}", byte));
+        }
+        text.push('\n');
+    }
+    let mut text = String::new();
+    let mut upper = 0;
+    for &(start, bytes) in segments {
+        let mut address = start;
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            if address >> 16 != upper {
+                upper = address >> 16;
+                record(&mut text, 4, 0, &(upper as u16).to_be_bytes());
+            }
+            // Records neither cross a 16-byte row nor a 64 KiB boundary.
+            let length = rest.len().min(16 - (address as usize & 15));
+            let (data, tail) = rest.split_at(length);
+            record(&mut text, 0, address as u16, data);
+            // The last record may end at the top of the address space.
+            address = address.wrapping_add(length as u32);
+            rest = tail;
+        }
+    }
+    record(&mut text, 1, 0, &[]);
+    text
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "size: {}, address: {:#x}, index: {:?},",
+            self.size, self.address, self.index
+        )?;
+        if !self.data.is_empty() {
+            write!(f, "{}", hexdump::rows(self.address as u32, &self.bytes()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1075 @@
+use crate::{
+    cycles,
+    device::Profile,
+    image::Image,
+    instruction::{DecodeError, Instruction, Mode, Pointer},
+    nvm::Controller,
+    peripherals::Peripheral,
+};
+use std::{collections::HashMap, fmt, mem};
+
+/// Bits of the status register.
+pub const C: u8 = 0;
+pub const Z: u8 = 1;
+pub const N: u8 = 2;
+pub const V: u8 = 3;
+pub const S: u8 = 4;
+pub const H: u8 = 5;
+pub const T: u8 = 6;
+pub const I: u8 = 7;
+
+/// I/O addresses of the registers implemented by the core itself.
+const RAMPZ: u8 = 0x3b;
+const EIND: u8 = 0x3c;
+const SPL: u8 = 0x3d;
+const SPH: u8 = 0x3e;
+const SREG: u8 = 0x3f;
+
+/// Why the simulation stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The cycle budget ran out.
+    Cycles,
+    /// A `break` instruction, the program counter stays on it.
+    Break,
+    /// A `sleep` instruction with interrupts disabled or no peripherals to
+    /// raise one, nothing can wake the core.
+    Sleep,
+    /// The program counter left the flash.
+    Outside,
+    Undecoded(DecodeError),
+    /// An instruction the simulator does not implement.
+    Unsupported(Instruction),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Cycles => write!(f, "cycle limit reached"),
+            Stop::Break => write!(f, "break"),
+            Stop::Sleep => write!(f, "sleep"),
+            Stop::Outside => write!(f, "program counter outside of flash"),
+            Stop::Undecoded(DecodeError::UnexpectedCommand(word)) => {
+                write!(f, "unknown instruction {:#06x}", word)
+            }
+            Stop::Undecoded(DecodeError::MissingExtension(word)) => {
+                write!(f, "instruction {:#06x} without its second word", word)
+            }
+            Stop::Unsupported(instruction) => {
+                write!(f, "{} is not simulated", instruction.mnemonic(false))
+            }
+        }
+    }
+}
+
+/// What a step of the simulator did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The instruction at the program counter was executed.
+    Executed,
+    /// The core entered the handler of an interrupt vector.
+    Interrupt(u8),
+    /// The core slept for a cycle, waiting for an interrupt.
+    Idle,
+}
+
+/// An AVR core with the memories of a device, executing the typed
+/// instructions of the decoder.
+pub struct Simulator {
+    pub profile: Profile,
+    pub registers: [u8; 32],
+    pub sreg: u8,
+    pub sp: u16,
+    /// Byte address of the next instruction.
+    pub pc: u32,
+    pub rampz: u8,
+    pub eind: u8,
+    pub cycles: u64,
+    pub flash: Vec<u8>,
+    /// Data memory up to the end of SRAM. The registers above are kept in
+    /// their fields, their bytes here are unused.
+    pub data: Vec<u8>,
+    pub eeprom: Vec<u8>,
+    /// Data memory written by the last instruction, other than the
+    /// registers of the core, in the order of the writes.
+    pub writes: Vec<(u16, u8)>,
+    pub peripherals: Vec<Box<dyn Peripheral>>,
+    /// EEPROM and self-programming controllers of the device.
+    pub nvm: Option<Controller>,
+    /// Index in `peripherals` of the owner of each I/O address.
+    owners: HashMap<u16, usize>,
+    /// Waiting for an interrupt after a `sleep`.
+    pub sleeping: bool,
+    /// Interrupts wait for one more instruction, after `sei` and `reti`.
+    hold: bool,
+}
+
+/// State of the core before an instruction, to tell what it changed.
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot {
+    pub pc: u32,
+    pub cycles: u64,
+    pub registers: [u8; 32],
+    pub sreg: u8,
+    pub sp: u16,
+}
+
+/// Letters of the set flags of a status register value, `-` for clear ones.
+pub fn flags(sreg: u8) -> String {
+    "ITHSVNZC"
+        .chars()
+        .enumerate()
+        .map(|(i, letter)| match sreg >> (7 - i) & 1 == 1 {
+            true => letter,
+            false => '-',
+        })
+        .collect()
+}
+
+impl Simulator {
+    /// A device in its reset state with `image` programmed into erased flash.
+    pub fn new(profile: Profile, image: &Image) -> Self {
+        let mut flash = vec![0xff; profile.flash as usize];
+        for segment in image.segments() {
+            for address in segment {
+                if let (Some(byte), Some(cell)) =
+                    (image.byte(address), flash.get_mut(address as usize))
+                {
+                    *cell = byte;
+                }
+            }
+        }
+        Simulator {
+            profile,
+            registers: [0; 32],
+            sreg: 0,
+            sp: profile.ramend(),
+            pc: 0,
+            rampz: 0,
+            eind: 0,
+            cycles: 0,
+            flash,
+            data: vec![0; profile.ramend() as usize + 1],
+            eeprom: vec![0xff; profile.eeprom as usize],
+            writes: vec![],
+            peripherals: vec![],
+            nvm: profile.nvm.map(Controller::new),
+            owners: HashMap::new(),
+            sleeping: false,
+            hold: false,
+        }
+    }
+
+    /// Maps the registers of `peripheral` into data memory, in place of
+    /// SRAM or of the registers of an earlier peripheral.
+    pub fn attach(&mut self, peripheral: Box<dyn Peripheral>) {
+        for address in peripheral.addresses() {
+            self.owners.insert(address, self.peripherals.len());
+        }
+        self.peripherals.push(peripheral);
+    }
+
+    pub fn flag(&self, bit: u8) -> bool {
+        self.sreg >> bit & 1 == 1
+    }
+
+    pub fn set_flag(&mut self, bit: u8, value: bool) {
+        self.sreg = self.sreg & !(1 << bit) | (value as u8) << bit;
+    }
+
+    fn word(&self, address: u32) -> Option<u16> {
+        let low = *self.flash.get(address as usize)?;
+        let high = *self.flash.get(address as usize + 1)?;
+        Some(u16::from_le_bytes([low, high]))
+    }
+
+    /// Instruction at the byte address `address` of the flash.
+    pub fn fetch(&self, address: u32) -> Option<Result<Instruction, DecodeError>> {
+        let word = self.word(address)?;
+        Some(Instruction::decode(word, self.word(address + 2)))
+    }
+
+    pub fn pointer(&self, pointer: Pointer) -> u16 {
+        let low = match pointer {
+            Pointer::X => 26,
+            Pointer::Y => 28,
+            Pointer::Z => 30,
+        };
+        self.pair(low)
+    }
+
+    fn set_pointer(&mut self, pointer: Pointer, value: u16) {
+        let low = match pointer {
+            Pointer::X => 26,
+            Pointer::Y => 28,
+            Pointer::Z => 30,
+        };
+        self.set_pair(low, value);
+    }
+
+    /// The register pair starting at the even register `low`.
+    pub fn pair(&self, low: u8) -> u16 {
+        u16::from_le_bytes([
+            self.registers[low as usize],
+            self.registers[low as usize + 1],
+        ])
+    }
+
+    pub fn set_pair(&mut self, low: u8, value: u16) {
+        let [l, h] = value.to_le_bytes();
+        self.registers[low as usize] = l;
+        self.registers[low as usize + 1] = h;
+    }
+
+    /// Byte of data memory as the program reads it.
+    pub fn read(&mut self, address: u16) -> u8 {
+        match self.owners.get(&address) {
+            Some(&owner) => self.peripherals[owner].read(address),
+            None => self.peek(address),
+        }
+    }
+
+    /// Byte of data memory, with the registers and the I/O registers of the
+    /// core mapped in, without the side effects of a read by the program.
+    /// Addresses past SRAM read as zero.
+    pub fn peek(&self, address: u16) -> u8 {
+        let io = self.profile.io;
+        if io > 0 && address < 32 {
+            return self.registers[address as usize];
+        }
+        match address.checked_sub(io).and_then(|a| u8::try_from(a).ok()) {
+            Some(SREG) => self.sreg,
+            Some(SPL) => self.sp as u8,
+            Some(SPH) => (self.sp >> 8) as u8,
+            Some(RAMPZ) => self.rampz,
+            Some(EIND) => self.eind,
+            _ => match (&self.nvm, self.owners.get(&address)) {
+                (Some(nvm), _) if nvm.owns(address) => nvm.peek(address, self.cycles),
+                (_, Some(&owner)) => self.peripherals[owner].peek(address),
+                _ => self.data.get(address as usize).copied().unwrap_or(0),
+            },
+        }
+    }
+
+    /// Writes a byte of data memory, writes past SRAM are lost.
+    pub fn write(&mut self, address: u16, value: u8) {
+        let io = self.profile.io;
+        if io > 0 && address < 32 {
+            self.registers[address as usize] = value;
+            return;
+        }
+        match address.checked_sub(io).and_then(|a| u8::try_from(a).ok()) {
+            Some(SREG) => self.sreg = value,
+            Some(SPL) => self.sp = self.sp & 0xff00 | value as u16,
+            Some(SPH) => self.sp = self.sp & 0x00ff | (value as u16) << 8,
+            Some(RAMPZ) => self.rampz = value,
+            Some(EIND) => self.eind = value,
+            _ => {
+                self.writes.push((address, value));
+                if let Some(nvm) = self.nvm.as_mut().filter(|nvm| nvm.owns(address)) {
+                    self.cycles += nvm.write(address, value, self.cycles, &mut self.eeprom);
+                } else if let Some(&owner) = self.owners.get(&address) {
+                    self.peripherals[owner].write(address, value);
+                } else if let Some(cell) = self.data.get_mut(address as usize) {
+                    *cell = value;
+                }
+            }
+        }
+    }
+
+    fn read_io(&mut self, a: u8) -> u8 {
+        self.read(self.profile.io + a as u16)
+    }
+
+    fn write_io(&mut self, a: u8, value: u8) {
+        self.write(self.profile.io + a as u16, value);
+    }
+
+    fn push(&mut self, value: u8) {
+        self.write(self.sp, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    fn pop(&mut self) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        self.read(self.sp)
+    }
+
+    /// Pushes the word address of `address` as the return address, low
+    /// byte first.
+    pub fn push_pc(&mut self, address: u32) {
+        let word = address / 2;
+        for i in 0..self.profile.core.pc_bytes() {
+            self.push((word >> (8 * i)) as u8);
+        }
+    }
+
+    fn pop_pc(&mut self) -> u32 {
+        let mut word = 0;
+        for _ in 0..self.profile.core.pc_bytes() {
+            word = word << 8 | self.pop() as u32;
+        }
+        word * 2
+    }
+
+    /// Sets N, Z and S from `result`, V must already be set.
+    fn nzs(&mut self, result: u8) {
+        self.set_flag(N, result & 0x80 != 0);
+        self.set_flag(Z, result == 0);
+        self.set_flag(S, self.flag(N) ^ self.flag(V));
+    }
+
+    fn logic(&mut self, result: u8) -> u8 {
+        self.set_flag(V, false);
+        self.nzs(result);
+        result
+    }
+
+    fn add(&mut self, a: u8, b: u8, carry: bool) -> u8 {
+        let r = a.wrapping_add(b).wrapping_add(carry as u8);
+        let carries = a & b | b & !r | !r & a;
+        self.set_flag(H, carries & 0x08 != 0);
+        self.set_flag(V, (a & b & !r | !a & !b & r) & 0x80 != 0);
+        self.set_flag(C, carries & 0x80 != 0);
+        self.nzs(r);
+        r
+    }
+
+    /// `a - b - carry`, `chain` keeps Z cleared when it already was, as the
+    /// subtractions with carry do.
+    fn sub(&mut self, a: u8, b: u8, carry: bool, chain: bool) -> u8 {
+        let r = a.wrapping_sub(b).wrapping_sub(carry as u8);
+        let borrows = !a & b | b & r | r & !a;
+        let zero = r == 0 && (!chain || self.flag(Z));
+        self.set_flag(H, borrows & 0x08 != 0);
+        self.set_flag(V, (a & !b & !r | !a & b & r) & 0x80 != 0);
+        self.set_flag(C, borrows & 0x80 != 0);
+        self.nzs(r);
+        self.set_flag(Z, zero);
+        r
+    }
+
+    /// Flags of the right shifts: C is the bit shifted out.
+    fn shift(&mut self, value: u8, result: u8) -> u8 {
+        self.set_flag(C, value & 1 != 0);
+        self.set_flag(N, result & 0x80 != 0);
+        self.set_flag(V, self.flag(N) ^ self.flag(C));
+        self.set_flag(S, self.flag(N) ^ self.flag(V));
+        self.set_flag(Z, result == 0);
+        result
+    }
+
+    /// Stores the product of a multiplication in r1:r0, `fractional`
+    /// products are shifted left by one.
+    fn product(&mut self, product: u16, fractional: bool) {
+        self.set_flag(C, product & 0x8000 != 0);
+        let product = match fractional {
+            true => product << 1,
+            false => product,
+        };
+        self.set_flag(Z, product == 0);
+        self.set_pair(0, product);
+    }
+
+    /// Data address of an indirect access, updating the pointer register.
+    fn indirect(&mut self, pointer: Pointer, mode: Mode) -> u16 {
+        let value = self.pointer(pointer);
+        match mode {
+            Mode::Plain => value,
+            Mode::PostIncrement => {
+                self.set_pointer(pointer, value.wrapping_add(1));
+                value
+            }
+            Mode::PreDecrement => {
+                let value = value.wrapping_sub(1);
+                self.set_pointer(pointer, value);
+                value
+            }
+        }
+    }
+
+    /// Byte of program memory at `Z`, extended by RAMPZ for `elpm`, and
+    /// the incremented pointer written back.
+    fn load_program(&mut self, extended: bool, increment: bool) -> u8 {
+        let z = self.pointer(Pointer::Z) as u32;
+        let address = match extended {
+            true => (self.rampz as u32) << 16 | z,
+            false => z,
+        };
+        if increment {
+            let next = address.wrapping_add(1);
+            self.set_pointer(Pointer::Z, next as u16);
+            if extended {
+                self.rampz = (next >> 16) as u8;
+            }
+        }
+        self.flash.get(address as usize).copied().unwrap_or(0xff)
+    }
+
+    /// Advances the peripherals by the cycles since `start`.
+    fn tick(&mut self, start: u64) {
+        let cycles = self.cycles - start;
+        for peripheral in &mut self.peripherals {
+            peripheral.tick(cycles);
+        }
+    }
+
+    /// Enters the handler of the pending interrupt with the lowest vector,
+    /// when interrupts are enabled.
+    fn interrupt(&mut self) -> Option<u8> {
+        if !self.flag(I) {
+            return None;
+        }
+        let cycles = self.cycles;
+        let vector = self
+            .peripherals
+            .iter()
+            .filter_map(|p| p.pending())
+            .chain(self.nvm.as_ref().and_then(|nvm| nvm.pending(cycles)))
+            .min()?;
+        for peripheral in &mut self.peripherals {
+            peripheral.acknowledge(vector);
+        }
+        let start = self.cycles;
+        self.push_pc(self.pc);
+        self.set_flag(I, false);
+        self.pc = vector as u32 * self.profile.vector_size();
+        self.cycles += 4 + (self.profile.core.pc_bytes() == 3) as u64;
+        if mem::take(&mut self.sleeping) {
+            self.cycles += 4;
+        }
+        self.tick(start);
+        Some(vector)
+    }
+
+    /// Enters a pending interrupt, waits a cycle for one while sleeping, or
+    /// executes the instruction at the program counter.
+    pub fn step(&mut self) -> Result<Event, Stop> {
+        self.writes.clear();
+        if !mem::take(&mut self.hold) {
+            if let Some(vector) = self.interrupt() {
+                return Ok(Event::Interrupt(vector));
+            }
+        }
+        if self.sleeping {
+            if !self.flag(I) {
+                return Err(Stop::Sleep);
+            }
+            self.cycles += 1;
+            self.tick(self.cycles - 1);
+            return Ok(Event::Idle);
+        }
+        let start = self.cycles;
+        let address = self.pc;
+        let instruction = match self.fetch(address) {
+            Some(Ok(instruction)) => instruction,
+            Some(Err(error)) => return Err(Stop::Undecoded(error)),
+            None => return Err(Stop::Outside),
+        };
+        let next = address + instruction.length() * 2;
+        let skipped = self
+            .fetch(next)
+            .and_then(Result::ok)
+            .map_or(1, |instruction| instruction.length());
+        let timing = cycles::cycles(&instruction, self.profile.core, skipped);
+        let r = |register: u8| register as usize;
+        let mut pc = next;
+        let mut taken = false;
+        match instruction {
+            Instruction::Nop | Instruction::Wdr => {}
+            Instruction::Movw { d, r } => {
+                let value = self.pair(r);
+                self.set_pair(d, value);
+            }
+            Instruction::Mul { d, r: s } => {
+                let product = self.registers[r(d)] as u16 * self.registers[r(s)] as u16;
+                self.product(product, false);
+            }
+            Instruction::Muls { d, r: s } => {
+                let product = self.registers[r(d)] as i8 as i16 * self.registers[r(s)] as i8 as i16;
+                self.product(product as u16, false);
+            }
+            Instruction::Mulsu { d, r: s } => {
+                let product = self.registers[r(d)] as i8 as i16 * self.registers[r(s)] as i16;
+                self.product(product as u16, false);
+            }
+            Instruction::Fmul { d, r: s } => {
+                let product = self.registers[r(d)] as u16 * self.registers[r(s)] as u16;
+                self.product(product, true);
+            }
+            Instruction::Fmuls { d, r: s } => {
+                let product = self.registers[r(d)] as i8 as i16 * self.registers[r(s)] as i8 as i16;
+                self.product(product as u16, true);
+            }
+            Instruction::Fmulsu { d, r: s } => {
+                let product = self.registers[r(d)] as i8 as i16 * self.registers[r(s)] as i16;
+                self.product(product as u16, true);
+            }
+            Instruction::Cpc { d, r: s } => {
+                let carry = self.flag(C);
+                self.sub(self.registers[r(d)], self.registers[r(s)], carry, true);
+            }
+            Instruction::Sbc { d, r: s } => {
+                let carry = self.flag(C);
+                self.registers[r(d)] =
+                    self.sub(self.registers[r(d)], self.registers[r(s)], carry, true);
+            }
+            Instruction::Add { d, r: s } => {
+                self.registers[r(d)] = self.add(self.registers[r(d)], self.registers[r(s)], false);
+            }
+            Instruction::Adc { d, r: s } => {
+                let carry = self.flag(C);
+                self.registers[r(d)] = self.add(self.registers[r(d)], self.registers[r(s)], carry);
+            }
+            Instruction::Cp { d, r: s } => {
+                self.sub(self.registers[r(d)], self.registers[r(s)], false, false);
+            }
+            Instruction::Sub { d, r: s } => {
+                self.registers[r(d)] =
+                    self.sub(self.registers[r(d)], self.registers[r(s)], false, false);
+            }
+            Instruction::Cpse { d, r: s } => {
+                taken = self.registers[r(d)] == self.registers[r(s)];
+            }
+            Instruction::And { d, r: s } => {
+                self.registers[r(d)] = self.logic(self.registers[r(d)] & self.registers[r(s)]);
+            }
+            Instruction::Eor { d, r: s } => {
+                self.registers[r(d)] = self.logic(self.registers[r(d)] ^ self.registers[r(s)]);
+            }
+            Instruction::Or { d, r: s } => {
+                self.registers[r(d)] = self.logic(self.registers[r(d)] | self.registers[r(s)]);
+            }
+            Instruction::Mov { d, r: s } => self.registers[r(d)] = self.registers[r(s)],
+            Instruction::Cpi { d, k } => {
+                self.sub(self.registers[r(d)], k, false, false);
+            }
+            Instruction::Sbci { d, k } => {
+                let carry = self.flag(C);
+                self.registers[r(d)] = self.sub(self.registers[r(d)], k, carry, true);
+            }
+            Instruction::Subi { d, k } => {
+                self.registers[r(d)] = self.sub(self.registers[r(d)], k, false, false);
+            }
+            Instruction::Ori { d, k } => {
+                self.registers[r(d)] = self.logic(self.registers[r(d)] | k);
+            }
+            Instruction::Andi { d, k } => {
+                self.registers[r(d)] = self.logic(self.registers[r(d)] & k);
+            }
+            Instruction::Ldi { d, k } => self.registers[r(d)] = k,
+            Instruction::Ld { d, pointer, mode } => {
+                let address = self.indirect(pointer, mode);
+                self.registers[r(d)] = self.read(address);
+            }
+            Instruction::Ldd { d, pointer, q } => {
+                let address = self.pointer(pointer).wrapping_add(q as u16);
+                self.registers[r(d)] = self.read(address);
+            }
+            Instruction::St {
+                pointer,
+                mode,
+                r: s,
+            } => {
+                // The register is read before a pointer it is part of changes.
+                let value = self.registers[r(s)];
+                let address = self.indirect(pointer, mode);
+                self.write(address, value);
+            }
+            Instruction::Std { pointer, q, r: s } => {
+                let address = self.pointer(pointer).wrapping_add(q as u16);
+                self.write(address, self.registers[r(s)]);
+            }
+            Instruction::Lds { d, k } => self.registers[r(d)] = self.read(k),
+            Instruction::Sts { k, r: s } => self.write(k, self.registers[r(s)]),
+            Instruction::LpmR0 => self.registers[0] = self.load_program(false, false),
+            Instruction::Lpm { d, increment } => {
+                self.registers[r(d)] = self.load_program(false, increment);
+            }
+            Instruction::ElpmR0 => self.registers[0] = self.load_program(true, false),
+            Instruction::Elpm { d, increment } => {
+                self.registers[r(d)] = self.load_program(true, increment);
+            }
+            Instruction::Xch { r: s }
+            | Instruction::Las { r: s }
+            | Instruction::Lac { r: s }
+            | Instruction::Lat { r: s } => {
+                let z = self.pointer(Pointer::Z);
+                let memory = self.read(z);
+                let register = self.registers[r(s)];
+                let value = match instruction {
+                    Instruction::Xch { .. } => register,
+                    Instruction::Las { .. } => memory | register,
+                    Instruction::Lac { .. } => memory & !register,
+                    _ => memory ^ register,
+                };
+                self.write(z, value);
+                self.registers[r(s)] = memory;
+            }
+            Instruction::Pop { d } => self.registers[r(d)] = self.pop(),
+            Instruction::Push { r: s } => self.push(self.registers[r(s)]),
+            Instruction::Com { d } => {
+                let result = !self.registers[r(d)];
+                self.set_flag(C, true);
+                self.registers[r(d)] = self.logic(result);
+            }
+            Instruction::Neg { d } => {
+                let value = self.registers[r(d)];
+                let result = 0u8.wrapping_sub(value);
+                self.set_flag(H, (result | value) & 0x08 != 0);
+                self.set_flag(V, result == 0x80);
+                self.set_flag(C, result != 0);
+                self.nzs(result);
+                self.registers[r(d)] = result;
+            }
+            Instruction::Swap { d } => {
+                self.registers[r(d)] = self.registers[r(d)].rotate_left(4);
+            }
+            Instruction::Inc { d } => {
+                let result = self.registers[r(d)].wrapping_add(1);
+                self.set_flag(V, result == 0x80);
+                self.nzs(result);
+                self.registers[r(d)] = result;
+            }
+            Instruction::Dec { d } => {
+                let result = self.registers[r(d)].wrapping_sub(1);
+                self.set_flag(V, result == 0x7f);
+                self.nzs(result);
+                self.registers[r(d)] = result;
+            }
+            Instruction::Asr { d } => {
+                let value = self.registers[r(d)];
+                self.registers[r(d)] = self.shift(value, value >> 1 | value & 0x80);
+            }
+            Instruction::Lsr { d } => {
+                let value = self.registers[r(d)];
+                self.registers[r(d)] = self.shift(value, value >> 1);
+            }
+            Instruction::Ror { d } => {
+                let value = self.registers[r(d)];
+                let carry = self.flag(C) as u8;
+                self.registers[r(d)] = self.shift(value, value >> 1 | carry << 7);
+            }
+            Instruction::Bset { s } => {
+                self.set_flag(s, true);
+                self.hold = s == I;
+            }
+            Instruction::Bclr { s } => self.set_flag(s, false),
+            Instruction::Ijmp => pc = self.pointer(Pointer::Z) as u32 * 2,
+            Instruction::Eijmp => {
+                pc = ((self.eind as u32) << 16 | self.pointer(Pointer::Z) as u32) * 2;
+            }
+            Instruction::Icall => {
+                self.push_pc(next);
+                pc = self.pointer(Pointer::Z) as u32 * 2;
+            }
+            Instruction::Eicall => {
+                self.push_pc(next);
+                pc = ((self.eind as u32) << 16 | self.pointer(Pointer::Z) as u32) * 2;
+            }
+            Instruction::Ret => pc = self.pop_pc(),
+            Instruction::Reti => {
+                pc = self.pop_pc();
+                self.set_flag(I, true);
+                self.hold = true;
+            }
+            Instruction::Sleep => {
+                self.pc = next;
+                self.cycles += timing.cycles as u64;
+                self.tick(start);
+                if !self.flag(I) || self.peripherals.is_empty() && self.nvm.is_none() {
+                    return Err(Stop::Sleep);
+                }
+                self.sleeping = true;
+                return Ok(Event::Executed);
+            }
+            Instruction::Break => return Err(Stop::Break),
+            Instruction::Jmp { .. } | Instruction::Rjmp { .. } => {
+                pc = instruction.target(address).unwrap_or(next);
+            }
+            Instruction::Call { .. } | Instruction::Rcall { .. } => {
+                self.push_pc(next);
+                pc = instruction.target(address).unwrap_or(next);
+            }
+            Instruction::Adiw { d, k } => {
+                let value = self.pair(d);
+                let result = value.wrapping_add(k as u16);
+                let (high, sign) = (value & 0x8000 != 0, result & 0x8000 != 0);
+                self.set_flag(V, !high && sign);
+                self.set_flag(C, high && !sign);
+                self.set_flag(N, sign);
+                self.set_flag(Z, result == 0);
+                self.set_flag(S, self.flag(N) ^ self.flag(V));
+                self.set_pair(d, result);
+            }
+            Instruction::Sbiw { d, k } => {
+                let value = self.pair(d);
+                let result = value.wrapping_sub(k as u16);
+                let (high, sign) = (value & 0x8000 != 0, result & 0x8000 != 0);
+                self.set_flag(V, high && !sign);
+                self.set_flag(C, sign && !high);
+                self.set_flag(N, sign);
+                self.set_flag(Z, result == 0);
+                self.set_flag(S, self.flag(N) ^ self.flag(V));
+                self.set_pair(d, result);
+            }
+            Instruction::Cbi { a, b } => {
+                let value = self.read_io(a) & !(1 << b);
+                self.write_io(a, value);
+            }
+            Instruction::Sbi { a, b } => {
+                let value = self.read_io(a) | 1 << b;
+                self.write_io(a, value);
+            }
+            Instruction::Sbic { a, b } => taken = self.read_io(a) >> b & 1 == 0,
+            Instruction::Sbis { a, b } => taken = self.read_io(a) >> b & 1 == 1,
+            Instruction::In { d, a } => self.registers[r(d)] = self.read_io(a),
+            Instruction::Out { a, r: s } => self.write_io(a, self.registers[r(s)]),
+            Instruction::Brbs { s, .. } | Instruction::Brbc { s, .. } => {
+                taken = self.flag(s) == matches!(instruction, Instruction::Brbs { .. });
+                if taken {
+                    pc = instruction.target(address).unwrap_or(next);
+                }
+            }
+            Instruction::Bld { d, b } => {
+                let t = self.flag(T) as u8;
+                self.registers[r(d)] = self.registers[r(d)] & !(1 << b) | t << b;
+            }
+            Instruction::Bst { d, b } => self.set_flag(T, self.registers[r(d)] >> b & 1 == 1),
+            Instruction::Sbrc { r: s, b } => taken = self.registers[r(s)] >> b & 1 == 0,
+            Instruction::Sbrs { r: s, b } => taken = self.registers[r(s)] >> b & 1 == 1,
+            Instruction::Spm if self.nvm.is_some() => {
+                let z = (self.rampz as u32) << 16 | self.pointer(Pointer::Z) as u32;
+                let data = self.pair(0);
+                if let Some(nvm) = &mut self.nvm {
+                    nvm.spm(z, data, self.cycles, &mut self.flash);
+                }
+            }
+            Instruction::Spm | Instruction::SpmZ | Instruction::Des { .. } => {
+                return Err(Stop::Unsupported(instruction));
+            }
+        }
+        if taken && instruction.is_skip() {
+            pc = next + skipped * 2;
+        }
+        self.pc = pc;
+        self.cycles += match taken {
+            true => timing.taken.unwrap_or(timing.cycles),
+            false => timing.cycles,
+        } as u64;
+        self.tick(start);
+        Ok(Event::Executed)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            pc: self.pc,
+            cycles: self.cycles,
+            registers: self.registers,
+            sreg: self.sreg,
+            sp: self.sp,
+        }
+    }
+
+    /// Executes instructions until one stops the core or `max_cycles` have
+    /// passed since reset, calling `observe` after each executed instruction
+    /// and interrupt entry with the state before it.
+    pub fn run(
+        &mut self,
+        max_cycles: u64,
+        mut observe: impl FnMut(&Snapshot, Event, &Simulator),
+    ) -> Stop {
+        while self.cycles < max_cycles {
+            let before = self.snapshot();
+            match self.step() {
+                Ok(Event::Idle) => {}
+                Ok(event) => observe(&before, event, self),
+                Err(stop) => {
+                    // A sleep that cannot wake up still ran.
+                    if self.cycles != before.cycles {
+                        observe(&before, Event::Executed, self);
+                    }
+                    return stop;
+                }
+            }
+        }
+        Stop::Cycles
+    }
+}
+
+impl fmt::Display for Simulator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "pc {:#06x}  sp {:#06x}  sreg {} ({:#04x})  cycles {}",
+            self.pc,
+            self.sp,
+            flags(self.sreg),
+            self.sreg,
+            self.cycles
+        )?;
+        for row in self.registers.chunks(8).enumerate() {
+            let (i, registers) = row;
+            let bytes: Vec<String> = registers.iter().map(|r| format!("{:02x}", r)).collect();
+            writeln!(f, "{:<4}{}", format!("r{}", i * 8), bytes.join(" "))?;
+        }
+        writeln!(
+            f,
+            "X {:#06x}  Y {:#06x}  Z {:#06x}",
+            self.pointer(Pointer::X),
+            self.pointer(Pointer::Y),
+            self.pointer(Pointer::Z)
+        )?;
+        for summary in self.peripherals.iter().filter_map(|p| p.summary()) {
+            writeln!(f, "{}", summary)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Simulator {
+    /// Steps until an interrupt is entered, returns its vector.
+    pub fn run_until_interrupt(&mut self) -> u8 {
+        for _ in 0..100_000 {
+            if let Event::Interrupt(vector) = self.step().unwrap() {
+                return vector;
+            }
+        }
+        panic!("no interrupt at {:#x}", self.pc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembler::assemble,
+        device::{Core, Device},
+    };
+
+    /// A simulator with `source` assembled into the flash of `profile`.
+    fn simulator(source: &str, profile: Profile) -> Simulator {
+        let segments = assemble(source, profile.core).unwrap();
+        Simulator::new(profile, &Image::from_segments(&segments))
+    }
+
+    /// Executes `steps` instructions of `source` on an ATmega328P.
+    fn execute(source: &str, steps: usize) -> Simulator {
+        let mut simulator = simulator(source, Device::Atmega328p.profile());
+        for _ in 0..steps {
+            assert_eq!(simulator.step(), Ok(Event::Executed));
+        }
+        simulator
+    }
+
+    fn empty() -> Simulator {
+        Simulator::new(Core::Avr5.profile(), &Image::from_records(&[]))
+    }
+
+    #[test]
+    fn add_sets_half_carry_overflow_and_carry() {
+        let mut simulator = empty();
+        assert_eq!(simulator.add(0x0f, 0x01, false), 0x10);
+        assert_eq!(flags(simulator.sreg), "--H-----");
+        assert_eq!(simulator.add(0x7f, 0x01, false), 0x80);
+        assert_eq!(flags(simulator.sreg), "--H-VN--");
+        assert_eq!(simulator.add(0xff, 0x00, true), 0x00);
+        assert_eq!(flags(simulator.sreg), "--H---ZC");
+        assert_eq!(simulator.add(0x80, 0x80, false), 0x00);
+        assert_eq!(flags(simulator.sreg), "---SV-ZC");
+    }
+
+    #[test]
+    fn sub_chains_zero_only_when_asked() {
+        let mut simulator = empty();
+        // 0x0100 - 0x0001: the high byte is zero, the word is not.
+        assert_eq!(simulator.sub(0x00, 0x01, false, false), 0xff);
+        assert_eq!(flags(simulator.sreg), "--HS-N-C");
+        assert_eq!(simulator.sub(0x01, 0x00, true, true), 0x00);
+        assert_eq!(flags(simulator.sreg), "--------");
+        // 0x0101 - 0x0101.
+        assert_eq!(simulator.sub(0x01, 0x01, false, false), 0x00);
+        assert_eq!(simulator.sub(0x01, 0x01, false, true), 0x00);
+        assert_eq!(flags(simulator.sreg), "------Z-");
+        // Without the chain a zero result sets Z whatever it was.
+        simulator.set_flag(Z, false);
+        assert_eq!(simulator.sub(0x05, 0x04, true, false), 0x00);
+        assert!(simulator.flag(Z));
+        assert_eq!(simulator.sub(0x80, 0x01, false, false), 0x7f);
+        assert_eq!(flags(simulator.sreg), "--HSV---");
+    }
+
+    #[test]
+    fn adc_sbc_and_cpc_chain_across_bytes() {
+        // 0x00ff + 0x0001 = 0x0100, adc sets Z from its own result.
+        let simulator = execute(
+            "ldi r24, 0xff\nldi r25, 0\nldi r22, 1\nldi r23, 0\nadd r24, r22\nadc r25, r23",
+            6,
+        );
+        assert_eq!(simulator.pair(24), 0x0100);
+        assert_eq!(flags(simulator.sreg), "--------");
+        // 0x0100 - 0x0001 = 0x00ff, Z stays clear through sbc.
+        let simulator = execute(
+            "ldi r24, 0\nldi r25, 1\nldi r22, 1\nldi r23, 0\nsub r24, r22\nsbc r25, r23",
+            6,
+        );
+        assert_eq!(simulator.pair(24), 0x00ff);
+        assert_eq!(flags(simulator.sreg), "--------");
+        // cp and cpc compare words, only equal words leave Z set.
+        for (high, expected) in [(0x12, "------Z-"), (0x13, "--------"), (0x11, "--HS-N-C")] {
+            let source = format!(
+                "ldi r24, 0x34\nldi r25, {}\nldi r22, 0x34\nldi r23, 0x12\ncp r24, r22\ncpc r25, r23",
+                high
+            );
+            let simulator = execute(&source, 6);
+            assert_eq!(flags(simulator.sreg), expected, "{:#x}", high);
+        }
+        let simulator = execute(
+            "ldi r24, 0\nldi r25, 1\nldi r22, 1\nldi r23, 0\ncp r24, r22\ncpc r25, r23",
+            6,
+        );
+        assert!(!simulator.flag(Z));
+    }
+
+    #[test]
+    fn neg_and_com_edge_values() {
+        for (value, result, expected) in [
+            (0x00, 0x00, "------Z-"),
+            (0x01, 0xff, "--HS-N-C"),
+            (0x80, 0x80, "----VN-C"),
+            (0x7f, 0x81, "--HS-N-C"),
+        ] {
+            let simulator = execute(&format!("ldi r16, {:#x}\nneg r16", value), 2);
+            assert_eq!(simulator.registers[16], result, "neg {:#x}", value);
+            assert_eq!(flags(simulator.sreg), expected, "neg {:#x}", value);
+        }
+        for (value, result, expected) in [
+            (0x00, 0xff, "---S-N-C"),
+            (0xff, 0x00, "------ZC"),
+            (0x80, 0x7f, "-------C"),
+        ] {
+            let simulator = execute(&format!("ldi r16, {:#x}\ncom r16", value), 2);
+            assert_eq!(simulator.registers[16], result, "com {:#x}", value);
+            assert_eq!(flags(simulator.sreg), expected, "com {:#x}", value);
+        }
+    }
+
+    #[test]
+    fn shift_carries_the_bit_shifted_out() {
+        let mut simulator = empty();
+        assert_eq!(simulator.shift(0x01, 0x00), 0x00);
+        assert_eq!(flags(simulator.sreg), "---SV-ZC");
+        assert_eq!(simulator.shift(0x81, 0xc0), 0xc0);
+        assert_eq!(flags(simulator.sreg), "---S-N-C");
+        assert_eq!(simulator.shift(0x02, 0x81), 0x81);
+        assert_eq!(flags(simulator.sreg), "----VN--");
+        let simulator = execute("ldi r16, 0x02\nsec\nror r16", 3);
+        assert_eq!(simulator.registers[16], 0x81);
+        assert_eq!(flags(simulator.sreg), "----VN--");
+    }
+
+    #[test]
+    fn product_sets_carry_from_bit_15_and_zero() {
+        let mut simulator = empty();
+        simulator.product(0xfe01, false);
+        assert_eq!(
+            (simulator.pair(0), flags(simulator.sreg)),
+            (0xfe01, "-------C".into())
+        );
+        simulator.product(0, false);
+        assert_eq!(
+            (simulator.pair(0), flags(simulator.sreg)),
+            (0, "------Z-".into())
+        );
+        // Fractional products take C before the shift and Z after it.
+        simulator.product(0x4000, true);
+        assert_eq!(
+            (simulator.pair(0), flags(simulator.sreg)),
+            (0x8000, "--------".into())
+        );
+        simulator.product(0xfe01, true);
+        assert_eq!(
+            (simulator.pair(0), flags(simulator.sreg)),
+            (0xfc02, "-------C".into())
+        );
+        for (source, product, expected) in [
+            (
+                "ldi r16, 0xff\nldi r17, 0xff\nmul r16, r17",
+                0xfe01,
+                "-------C",
+            ),
+            (
+                "ldi r16, 0xff\nldi r17, 0x01\nmuls r16, r17",
+                0xffff,
+                "-------C",
+            ),
+            (
+                "ldi r16, 0xff\nldi r17, 0xff\nmulsu r16, r17",
+                0xff01,
+                "-------C",
+            ),
+            (
+                "ldi r16, 0x80\nldi r17, 0x80\nfmul r16, r17",
+                0x8000,
+                "--------",
+            ),
+            (
+                "ldi r16, 0x80\nldi r17, 0x80\nfmuls r16, r17",
+                0x8000,
+                "--------",
+            ),
+            (
+                "ldi r16, 0x00\nldi r17, 0x80\nfmulsu r16, r17",
+                0x0000,
+                "------Z-",
+            ),
+        ] {
+            let simulator = execute(source, 3);
+            assert_eq!(simulator.pair(0), product, "{}", source);
+            assert_eq!(flags(simulator.sreg), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn calls_push_three_bytes_with_a_22_bit_pc() {
+        let source =
+            "jmp start\n.org 0x20000\nstart: call function\nnop\n.org 0x30000\nfunction: ret";
+        let profile = Device::Atmega2560.profile();
+        let ramend = profile.ramend();
+        let mut simulator = simulator(source, profile);
+        simulator.step().unwrap();
+        simulator.step().unwrap();
+        assert_eq!(simulator.pc, 0x30000);
+        assert_eq!(simulator.sp, ramend - 3);
+        // The word address 0x10002 of the return, its low byte pushed first.
+        let stack: Vec<u8> = (ramend - 2..=ramend).map(|a| simulator.peek(a)).collect();
+        assert_eq!(stack, [0x01, 0x00, 0x02]);
+        assert_eq!(simulator.cycles, 3 + 5);
+        simulator.step().unwrap();
+        assert_eq!((simulator.pc, simulator.sp), (0x20004, ramend));
+        assert_eq!(simulator.cycles, 3 + 5 + 5);
+    }
+
+    #[test]
+    fn calls_push_two_bytes_with_a_16_bit_pc() {
+        let mut simulator = execute("rcall function\nnop\nfunction: ret", 1);
+        let ramend = simulator.profile.ramend();
+        assert_eq!((simulator.pc, simulator.sp), (4, ramend - 2));
+        assert_eq!([simulator.peek(ramend - 1), simulator.peek(ramend)], [0, 1]);
+        assert_eq!(simulator.cycles, 3);
+        simulator.step().unwrap();
+        assert_eq!(
+            (simulator.pc, simulator.sp, simulator.cycles),
+            (2, ramend, 7)
+        );
+    }
+
+    #[test]
+    fn skips_take_a_cycle_per_skipped_word() {
+        // Taken over a 32-bit instruction, taken over a 16-bit one, not taken.
+        let simulator = execute("cpse r0, r0\njmp 0\nsbrc r0, 0\nnop\nsbrs r0, 0\nnop", 3);
+        assert_eq!(simulator.pc, 0x0c);
+        assert_eq!(simulator.cycles, 3 + 2 + 1);
+    }
+}
@@ -0,0 +1,574 @@
+use crate::{
+    callgraph::{CallGraph, Function},
+    image::Image,
+    instruction::{DecodeError, Instruction},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// I/O addresses of the stack pointer.
+const SPL: u8 = 0x3d;
+const SPH: u8 = 0x3e;
+
+/// Depth above which a loop is considered to grow the stack without bound.
+const UNBOUNDED: i32 = 0x10000;
+
+pub enum Warning {
+    Recursion(Vec<u32>),
+    IndirectCall(u32),
+    IndirectJump(u32),
+    UnboundedGrowth(u32),
+    NestedInterrupts(u32),
+    Undecoded(u32, Option<DecodeError>),
+}
+
+/// Stack use of a single function in bytes, not counting the return address
+/// pushed by its caller.
+pub struct Usage {
+    /// Deepest point of the function's own frame.
+    pub frame: u32,
+    /// Deepest point including everything it calls, `None` when unbounded.
+    pub worst: Option<u32>,
+    /// Indirect calls were seen so the real worst case may be larger.
+    pub incomplete: bool,
+}
+
+pub struct Report {
+    pub pc_bytes: u32,
+    pub nested: bool,
+    pub functions: BTreeMap<u32, Usage>,
+    pub reset: Option<u32>,
+    /// Interrupt handlers with their use including the pushed return address.
+    pub interrupts: Vec<(u32, Option<u32>)>,
+    pub total: Option<u32>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Stack depth at an instruction and, when `Y` holds a copy of the stack
+/// pointer, the depth it points at.
+#[derive(Clone, Copy, PartialEq)]
+struct State {
+    depth: i32,
+    y: Option<i32>,
+    subtrahend: Option<u8>,
+}
+
+/// The deepest point of a function body and the depth at each call site.
+struct Frame {
+    max: i32,
+    calls: Vec<(i32, u32)>,
+    indirect_calls: Vec<i32>,
+    unbounded: bool,
+}
+
+fn transfer(mut state: State, instruction: &Instruction, pc_bytes: u32) -> State {
+    let y = state.y;
+    let subtrahend = state.subtrahend.take();
+    if instruction.writes().iter().any(|&r| r == 28 || r == 29) {
+        state.y = None;
+    }
+    match *instruction {
+        Instruction::Push { .. } => state.depth += 1,
+        Instruction::Pop { .. } => state.depth -= 1,
+        Instruction::Rcall { k: 0 } => state.depth += pc_bytes as i32,
+        Instruction::In { d: 28, a: SPL } | Instruction::In { d: 29, a: SPH } => {
+            state.y = Some(state.depth)
+        }
+        Instruction::Sbiw { d: 28, k } => state.y = y.map(|y| y + k as i32),
+        Instruction::Adiw { d: 28, k } => state.y = y.map(|y| y - k as i32),
+        // Frames larger than `sbiw` allows are adjusted with a subi/sbci pair.
+        Instruction::Subi { d: 28, k } => {
+            state.y = y.map(|y| y + k as i8 as i32);
+            state.subtrahend = Some(k);
+        }
+        Instruction::Sbci { d: 29, k } => {
+            state.y = match subtrahend {
+                Some(low) => {
+                    y.map(|y| y - low as i8 as i32 + u16::from_be_bytes([k, low]) as i16 as i32)
+                }
+                None => None,
+            }
+        }
+        Instruction::Out { a: SPL, r: 28 } | Instruction::Out { a: SPH, r: 29 } => {
+            if let Some(y) = y {
+                state.depth = y;
+            }
+            state.y = y;
+        }
+        _ => {}
+    }
+    state
+}
+
+fn frame(image: &Image, function: &Function, pc_bytes: u32) -> Frame {
+    let mut frame = Frame {
+        max: 0,
+        calls: vec![],
+        indirect_calls: vec![],
+        unbounded: false,
+    };
+    let mut states: BTreeMap<u32, State> = BTreeMap::new();
+    let mut pending = vec![(
+        function.entry,
+        State {
+            depth: 0,
+            y: None,
+            subtrahend: None,
+        },
+    )];
+    while let Some((address, state)) = pending.pop() {
+        let Some(instruction) = function.instructions.get(&address) else {
+            continue;
+        };
+        let state = match states.get(&address) {
+            Some(known) => {
+                let merged = State {
+                    depth: known.depth.max(state.depth),
+                    y: known.y.filter(|_| known.y == state.y),
+                    subtrahend: known
+                        .subtrahend
+                        .filter(|_| known.subtrahend == state.subtrahend),
+                };
+                if merged == *known {
+                    continue;
+                }
+                merged
+            }
+            None => state,
+        };
+        if state.depth > UNBOUNDED {
+            frame.unbounded = true;
+            continue;
+        }
+        states.insert(address, state);
+        let next = transfer(state, instruction, pc_bytes);
+        frame.max = frame.max.max(state.depth).max(next.depth);
+        for successor in function.successors(image, address, instruction) {
+            pending.push((successor, next));
+        }
+    }
+    for (address, target) in &function.calls {
+        if let Some(state) = states.get(address) {
+            frame.calls.push((state.depth, *target));
+        }
+    }
+    for address in &function.indirect_calls {
+        if let Some(state) = states.get(address) {
+            frame.indirect_calls.push(state.depth);
+        }
+    }
+    frame
+}
+
+struct Analysis {
+    frames: BTreeMap<u32, Frame>,
+    pc_bytes: u32,
+    worst: BTreeMap<u32, Option<u32>>,
+    path: Vec<u32>,
+    warnings: Vec<Warning>,
+}
+
+impl Analysis {
+    fn worst(&mut self, entry: u32) -> Option<u32> {
+        if let Some(worst) = self.worst.get(&entry) {
+            return *worst;
+        }
+        if let Some(position) = self.path.iter().position(|&function| function == entry) {
+            let mut cycle = self.path[position..].to_vec();
+            cycle.push(entry);
+            self.warnings.push(Warning::Recursion(cycle));
+            return None;
+        }
+        let Some(frame) = self.frames.get(&entry) else {
+            return Some(0);
+        };
+        let calls = frame.calls.clone();
+        let mut worst = match frame.unbounded {
+            true => None,
+            false => Some(frame.max.max(0) as u32),
+        };
+        for &depth in &frame.indirect_calls {
+            worst = worst.map(|worst| worst.max(depth.max(0) as u32 + self.pc_bytes));
+        }
+        self.path.push(entry);
+        for (depth, target) in calls {
+            let callee = self.worst(target);
+            worst = match (worst, callee) {
+                (Some(worst), Some(callee)) => {
+                    Some(worst.max(depth.max(0) as u32 + self.pc_bytes + callee))
+                }
+                _ => None,
+            };
+        }
+        self.path.pop();
+        self.worst.insert(entry, worst);
+        worst
+    }
+}
+
+/// Worst-case stack depth of every function and of the whole program,
+/// interrupts are assumed not to nest unless `nested` is set.
+pub fn analyze(image: &Image, graph: &CallGraph, pc_bytes: u32, nested: bool) -> Report {
+    let mut warnings = vec![];
+    let mut analysis = Analysis {
+        frames: BTreeMap::new(),
+        pc_bytes,
+        worst: BTreeMap::new(),
+        path: vec![],
+        warnings: vec![],
+    };
+    for (&entry, function) in &graph.functions {
+        let frame = frame(image, function, pc_bytes);
+        if frame.unbounded {
+            warnings.push(Warning::UnboundedGrowth(entry));
+        }
+        warnings.extend(
+            function
+                .indirect_calls
+                .iter()
+                .map(|&a| Warning::IndirectCall(a)),
+        );
+        warnings.extend(
+            function
+                .indirect_jumps
+                .iter()
+                .map(|&a| Warning::IndirectJump(a)),
+        );
+        warnings.extend(
+            function
+                .errors
+                .iter()
+                .map(|&(address, error)| Warning::Undecoded(address, error)),
+        );
+        analysis.frames.insert(entry, frame);
+    }
+    let mut functions = BTreeMap::new();
+    for &entry in graph.functions.keys() {
+        let worst = analysis.worst(entry);
+        functions.insert(
+            entry,
+            Usage {
+                frame: analysis.frames[&entry].max.max(0) as u32,
+                worst,
+                incomplete: reaches_indirect_call(graph, entry),
+            },
+        );
+    }
+    let reset = graph.reset();
+    let interrupts: Vec<(u32, Option<u32>)> = graph
+        .interrupts()
+        .into_iter()
+        .map(|entry| {
+            let worst = functions[&entry].worst.map(|worst| worst + pc_bytes);
+            (entry, worst)
+        })
+        .collect();
+    for &(entry, _) in &interrupts {
+        let enables = graph.functions[&entry]
+            .instructions
+            .values()
+            .any(|instruction| *instruction == Instruction::Bset { s: 7 });
+        if enables && !nested {
+            warnings.push(Warning::NestedInterrupts(entry));
+        }
+    }
+    let program = reset.and_then(|reset| functions[&reset].worst);
+    let handlers = interrupts
+        .iter()
+        .try_fold(0, |total: u32, &(_, worst)| match nested {
+            true => Some(total + worst?),
+            false => Some(total.max(worst?)),
+        });
+    let total = match reset {
+        Some(_) => program
+            .zip(handlers)
+            .map(|(program, handlers)| program + handlers),
+        None => None,
+    };
+    warnings.append(&mut analysis.warnings);
+    Report {
+        pc_bytes,
+        nested,
+        functions,
+        reset,
+        interrupts,
+        total,
+        warnings,
+    }
+}
+
+fn reaches_indirect_call(graph: &CallGraph, entry: u32) -> bool {
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![entry];
+    while let Some(entry) = pending.pop() {
+        if !seen.insert(entry) {
+            continue;
+        }
+        if let Some(function) = graph.functions.get(&entry) {
+            if !function.indirect_calls.is_empty() {
+                return true;
+            }
+            pending.extend(function.calls.iter().map(|call| call.1));
+        }
+    }
+    false
+}
+
+fn bytes(worst: Option<u32>, incomplete: bool) -> String {
+    match (worst, incomplete) {
+        (Some(worst), false) => format!("{}", worst),
+        (Some(worst), true) => format!(">={}", worst),
+        (None, _) => "unbounded".to_string(),
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::Recursion(cycle) => {
+                write!(f, "recursion ")?;
+                for (i, entry) in cycle.iter().enumerate() {
+                    match i {
+                        0 => write!(f, "{:#x}", entry)?,
+                        _ => write!(f, " -> {:#x}", entry)?,
+                    }
+                }
+                Ok(())
+            }
+            Warning::IndirectCall(address) => {
+                write!(f, "indirect call at {:#x}, callee not counted", address)
+            }
+            Warning::IndirectJump(address) => {
+                write!(f, "indirect jump at {:#x}, target not followed", address)
+            }
+            Warning::UnboundedGrowth(entry) => {
+                write!(f, "stack grows in a loop of function {:#x}", entry)
+            }
+            Warning::NestedInterrupts(entry) => write!(
+                f,
+                "interrupt handler {:#x} enables interrupts and may nest",
+                entry
+            ),
+            Warning::Undecoded(address, Some(error)) => {
+                write!(f, "flow stops at {:#x}, {:?}", address, error)
+            }
+            Warning::Undecoded(address, None) => {
+                write!(f, "flow stops at {:#x}, no code loaded", address)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "stack usage in bytes, return address takes {}",
+            self.pc_bytes
+        )?;
+        for (entry, usage) in &self.functions {
+            writeln!(
+                f,
+                "{:#x}: frame {}, worst {}",
+                entry,
+                usage.frame,
+                bytes(usage.worst, usage.incomplete)
+            )?;
+        }
+        if let Some(reset) = self.reset {
+            let usage = &self.functions[&reset];
+            writeln!(
+                f,
+                "reset {:#x}: {}",
+                reset,
+                bytes(usage.worst, usage.incomplete)
+            )?;
+        }
+        for (entry, worst) in &self.interrupts {
+            let usage = &self.functions[entry];
+            writeln!(
+                f,
+                "interrupt {:#x}: {}",
+                entry,
+                bytes(*worst, usage.incomplete)
+            )?;
+        }
+        let incomplete = self.functions.values().any(|usage| usage.incomplete);
+        writeln!(
+            f,
+            "worst case: {} (interrupts {})",
+            bytes(self.total, incomplete),
+            match self.nested {
+                true => "nested",
+                false => "not nested",
+            }
+        )?;
+        for warning in &self.warnings {
+            writeln!(f, "warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls through frames set up in `Y` the way avr-gcc does, and an
+    /// interrupt handler.
+    const PROGRAM: &[(u32, &[u16])] = &[
+        (
+            0x0,
+            &[
+                0x940c, 0x0004, // jmp main
+                0x940c, 0x0070, // jmp handler
+                // main:
+                0x940e, 0x0020, // call frame
+                0xcffd, // rjmp main
+            ],
+        ),
+        (
+            0x40,
+            &[
+                // A 10-byte frame below the saved Y, reserved with sbiw.
+                // frame:
+                0x93cf, // push r28
+                0x93df, // push r29
+                0xb7cd, // in r28, 0x3d
+                0xb7de, // in r29, 0x3e
+                0x972a, // sbiw r28, 10
+                0xbfde, // out 0x3e, r29
+                0xbfcd, // out 0x3d, r28
+                0x940e, 0x0060, // call leaf
+                0x940e, 0x0040, // call large
+                0x962a, // adiw r28, 10
+                0xbfde, // out 0x3e, r29
+                0xbfcd, // out 0x3d, r28
+                0x91df, // pop r29
+                0x91cf, // pop r28
+                0x9508, // ret
+            ],
+        ),
+        (
+            0x80,
+            &[
+                // A 300-byte frame, too large for sbiw, reserved with subi and sbci.
+                // large:
+                0x93cf, // push r28
+                0x93df, // push r29
+                0xb7cd, // in r28, 0x3d
+                0xb7de, // in r29, 0x3e
+                0x52cc, // subi r28, 0x2c
+                0x40d1, // sbci r29, 0x01
+                0xbfde, // out 0x3e, r29
+                0xbfcd, // out 0x3d, r28
+                0x940e, 0x0060, // call leaf
+                0x5dc4, // subi r28, 0xc4
+                0x4fde, // sbci r29, 0xfe
+                0xbfde, // out 0x3e, r29
+                0xbfcd, // out 0x3d, r28
+                0x91df, // pop r29
+                0x91cf, // pop r28
+                0x9508, // ret
+            ],
+        ),
+        (
+            0xc0,
+            &[
+                // leaf:
+                0x930f, // push r16
+                0x910f, // pop r16
+                0x9508, // ret
+            ],
+        ),
+        (
+            0xe0,
+            &[
+                // rcall .+0 reserves two bytes without calling anything.
+                // handler:
+                0x938f, // push r24
+                0xd000, // rcall .+0
+                0x900f, // pop r0
+                0x900f, // pop r0
+                0x918f, // pop r24
+                0x9518, // reti
+            ],
+        ),
+    ];
+
+    #[test]
+    fn follows_frames_set_up_through_y() {
+        let image = Image::from_words(PROGRAM);
+        let graph = CallGraph::new(&image);
+        let report = analyze(&image, &graph, 2, false);
+        let usage: Vec<(u32, u32, Option<u32>)> = report
+            .functions
+            .iter()
+            .map(|(&entry, usage)| (entry, usage.frame, usage.worst))
+            .collect();
+        // Each call adds the two bytes of the return address to the depth
+        // of the call site.
+        let large = 2 + 300 + 2 + 1;
+        let frame = 2 + 10 + 2 + large;
+        assert_eq!(
+            usage,
+            [
+                (0x08, 0, Some(2 + frame)),
+                (0x40, 2 + 10, Some(frame)),
+                (0x80, 2 + 300, Some(large)),
+                (0xc0, 1, Some(1)),
+                (0xe0, 1 + 2, Some(1 + 2)),
+            ]
+        );
+        assert_eq!(report.reset, Some(0x08));
+        assert_eq!(report.interrupts, [(0xe0, Some(3 + 2))]);
+        assert_eq!(report.total, Some(321 + 5));
+        assert!(report.warnings.is_empty());
+        assert!(report
+            .to_string()
+            .contains("worst case: 326 (interrupts not nested)"));
+    }
+
+    #[test]
+    fn nested_interrupts_add_up() {
+        let mut program = PROGRAM.to_vec();
+        // sei at the start of the handler.
+        let handler = [&[0x9478], PROGRAM[4].1].concat();
+        program[4] = (0xe0, &handler);
+        let image = Image::from_words(&program);
+        let graph = CallGraph::new(&image);
+        let report = analyze(&image, &graph, 2, false);
+        let warnings: Vec<String> = report.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            ["interrupt handler 0xe0 enables interrupts and may nest"]
+        );
+        let report = analyze(&image, &graph, 2, true);
+        assert_eq!(report.total, Some(326));
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn reports_recursion_as_unbounded() {
+        let image = Image::from_words(&[(
+            0x0,
+            &[
+                0x940c, 0x0002, // jmp main
+                // main:
+                0x940e, 0x0005, // call recurse
+                0xcffd, // rjmp main
+                // recurse:
+                0x930f, // push r16
+                0x940e, 0x0005, // call recurse
+                0x910f, // pop r16
+                0x9508, // ret
+            ],
+        )]);
+        let graph = CallGraph::new(&image);
+        let report = analyze(&image, &graph, 2, false);
+        assert_eq!(report.total, None);
+        assert_eq!(report.warnings[0].to_string(), "recursion 0xa -> 0xa");
+    }
+}
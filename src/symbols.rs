@@ -0,0 +1,110 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+/// Offset of data memory in the address space of avr-gcc.
+const DATA: u32 = 0x800000;
+/// Offset of the EEPROM, symbols from there on are not loaded.
+const EEPROM: u32 = 0x810000;
+
+/// Names of code and data addresses read from `avr-nm` output or a linker
+/// map file.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    /// Byte addresses in flash.
+    pub code: BTreeMap<u32, String>,
+    /// Addresses in data memory.
+    pub data: BTreeMap<u32, String>,
+    /// Notes on code addresses, printed after their line.
+    pub comments: BTreeMap<u32, String>,
+}
+
+/// Whether `name` can be used as a label by avr-as.
+pub fn valid(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_' || first == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+}
+
+impl Symbols {
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        self.parse(&fs::read_to_string(path)?);
+        Ok(())
+    }
+
+    /// Adds the symbols of each line that is either `address [size] type
+    /// name` as printed by `avr-nm`, or `0xaddress name` as in the memory
+    /// map of GNU ld. Other lines are ignored.
+    pub fn parse(&mut self, text: &str) {
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (address, kind, name) = match fields[..] {
+                [address, kind, name] | [address, _, kind, name] if kind.len() == 1 => {
+                    match u32::from_str_radix(address, 16) {
+                        Ok(address) => (address, kind.chars().next(), name),
+                        Err(_) => continue,
+                    }
+                }
+                [address, name] => match address.strip_prefix("0x") {
+                    Some(address) => match u32::from_str_radix(address, 16) {
+                        Ok(address) => (address, None, name),
+                        Err(_) => continue,
+                    },
+                    None => continue,
+                },
+                _ => continue,
+            };
+            if !valid(name) {
+                continue;
+            }
+            // Local symbols of avr-nm are lowercase and give way to globals.
+            let global = kind.is_none_or(|kind| kind.is_ascii_uppercase());
+            let kind = kind.map(|kind| kind.to_ascii_uppercase());
+            let (map, address) = match kind {
+                Some('T' | 'W') | None if address < DATA => (&mut self.code, address),
+                Some('D' | 'B' | 'C' | 'V' | 'G' | 'S') | None
+                    if (DATA..EEPROM).contains(&address) =>
+                {
+                    (&mut self.data, address - DATA)
+                }
+                _ => continue,
+            };
+            match map.get(&address) {
+                Some(_) if !global => {}
+                _ => {
+                    map.insert(address, name.to_string());
+                }
+            }
+        }
+    }
+
+    /// Names `address`, in flash below 0x800000 and in data memory from
+    /// there on, replacing the symbol defined there before.
+    pub fn define(&mut self, address: u32, name: &str) {
+        match address {
+            address if address < DATA => {
+                self.code.insert(address, name.to_string());
+            }
+            address if address < EEPROM => {
+                self.data.insert(address - DATA, name.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    /// Address of the code symbol `name`.
+    pub fn find(&self, name: &str) -> Option<u32> {
+        self.code
+            .iter()
+            .find(|(_, symbol)| *symbol == name)
+            .map(|(&address, _)| address)
+    }
+
+    /// Closest code symbol at or before `address` and the offset from it.
+    pub fn nearest(&self, address: u32) -> Option<(&str, u32)> {
+        self.code
+            .range(..=address)
+            .next_back()
+            .map(|(&start, name)| (name.as_str(), address - start))
+    }
+}
@@ -0,0 +1,104 @@
+use crate::simulator::{self, Event, Simulator, Snapshot};
+use std::fmt::Write;
+
+/// Start of a binary trace, followed by one record per instruction.
+///
+/// A record is the byte address of the instruction as a little-endian
+/// `u32`, the cycles before it as a `u64`, the number of changes as a `u8`
+/// and the changes: a register as `0, index, value`, the status register
+/// as `1, value`, the stack pointer as `2` and a `u16`, and a data memory
+/// write as `3`, the `u16` address and the value. The entry into an
+/// interrupt handler is recorded with bit 31 of the address set and the
+/// vector in its low bits.
+pub const MAGIC: &[u8; 5] = b"AVRT\x01";
+
+const REGISTER: u8 = 0;
+const SREG: u8 = 1;
+const SP: u8 = 2;
+const MEMORY: u8 = 3;
+
+/// What an instruction changed, compared with the state before it.
+pub struct Changes {
+    pub registers: Vec<(u8, u8)>,
+    pub sreg: Option<u8>,
+    pub sp: Option<u16>,
+    pub writes: Vec<(u16, u8)>,
+}
+
+impl Changes {
+    pub fn new(before: &Snapshot, after: &Simulator) -> Self {
+        Changes {
+            registers: (0..32)
+                .filter(|&i| before.registers[i] != after.registers[i])
+                .map(|i| (i as u8, after.registers[i]))
+                .collect(),
+            sreg: (before.sreg != after.sreg).then_some(after.sreg),
+            sp: (before.sp != after.sp).then_some(after.sp),
+            writes: after.writes.clone(),
+        }
+    }
+}
+
+/// Line of the text trace: cycles before the instruction, its address and
+/// listing, and what it changed.
+pub fn line(before: &Snapshot, event: Event, after: &Simulator, overloads: bool) -> String {
+    let instruction = match (event, after.fetch(before.pc)) {
+        (Event::Interrupt(vector), _) => format!("interrupt {}", vector),
+        (_, Some(Ok(instruction))) => instruction.display(before.pc, overloads, None).to_string(),
+        _ => String::new(),
+    };
+    let mut line = format!(
+        "{:>10} {:#06x}: {:<28}",
+        before.cycles, before.pc, instruction
+    );
+    let changes = Changes::new(before, after);
+    for (register, value) in changes.registers {
+        let _ = write!(line, " r{}={:02x}", register, value);
+    }
+    if let Some(sreg) = changes.sreg {
+        let _ = write!(line, " sreg={}", simulator::flags(sreg));
+    }
+    if let Some(sp) = changes.sp {
+        let _ = write!(line, " sp={:#06x}", sp);
+    }
+    for (address, value) in changes.writes {
+        let _ = write!(line, " [{:#06x}]={:02x}", address, value);
+    }
+    line.trim_end().to_string()
+}
+
+/// Record of the binary trace, see [`MAGIC`].
+pub fn record(before: &Snapshot, event: Event, after: &Simulator) -> Vec<u8> {
+    let changes = Changes::new(before, after);
+    let mut entries: Vec<u8> = vec![];
+    let mut count = 0u8;
+    for (register, value) in changes.registers {
+        entries.extend([REGISTER, register, value]);
+        count += 1;
+    }
+    if let Some(sreg) = changes.sreg {
+        entries.extend([SREG, sreg]);
+        count += 1;
+    }
+    if let Some(sp) = changes.sp {
+        entries.push(SP);
+        entries.extend(sp.to_le_bytes());
+        count += 1;
+    }
+    // An instruction writes at most the three bytes of a return address.
+    for (address, value) in changes.writes {
+        entries.push(MEMORY);
+        entries.extend(address.to_le_bytes());
+        entries.push(value);
+        count += 1;
+    }
+    let pc = match event {
+        Event::Interrupt(vector) => 0x8000_0000 | vector as u32,
+        _ => before.pc,
+    };
+    let mut record = pc.to_le_bytes().to_vec();
+    record.extend(before.cycles.to_le_bytes());
+    record.push(count);
+    record.extend(entries);
+    record
+}
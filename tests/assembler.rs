@@ -0,0 +1,176 @@
+//! The assembler against hand-encoded words and against the images of
+//! `tests/golden` disassembled by the binary.
+
+use hex::{
+    assembler::assemble,
+    device::Core,
+    image::Image,
+    record::{self, Record},
+};
+use std::{fs, path::Path, process::Command};
+
+fn words(source: &str) -> Vec<u16> {
+    let segments = assemble(source, Core::Avr5).unwrap();
+    assert_eq!(segments.len(), 1);
+    segments[0]
+        .1
+        .chunks(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+#[test]
+fn assembles_listing_and_avr_as_operands() {
+    let source = "
+        start:
+            movw r25:24, r31:30  ; listing pairs
+            movw r24, r30        ; avr-as pairs
+            adiw r25:r24, 0x3f
+            ldd r24, Y+63
+            st -X, r1
+            lpm r0, Z+
+            clr r1
+            ser r16
+            sei
+            breq .-2
+            brne start
+            call start
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x01cf, 0x01cf, 0x96cf, 0xad8f, 0x921e, 0x9005, 0x2411, 0xef0f, 0x9478, 0xf3f9, 0xf7a9,
+            0x940e, 0x0000
+        ]
+    );
+}
+
+#[test]
+fn places_directives() {
+    let source = "
+        .set buffer, 0x100
+        .org 0x10
+        text: .asciz \"a\\n\"
+        .byte 0xff
+        .word lo8(text), hi8(buffer+0x200)
+    ";
+    assert_eq!(
+        assemble(source, Core::Avr5).unwrap(),
+        [(0x10, vec![b'a', b'\n', 0, 0xff, 0x10, 0, 0x03, 0])]
+    );
+}
+
+#[test]
+fn rejects_operands_out_of_range() {
+    for (source, message) in [
+        ("ldi r15, 1", "ldi r15, 1: operand out of range"),
+        ("adiw r26, 64", "adiw r26, 64: operand out of range"),
+        ("movw r25:23, r0", "r25:23: not a register pair"),
+        ("\n\trjmp missing", "missing: undefined symbol"),
+        ("brne .+128", "brne .+128: operand out of range"),
+    ] {
+        let error = assemble(source, Core::Avr5).unwrap_err();
+        assert_eq!(error.message, message, "{}", source);
+    }
+    assert_eq!(assemble("\n\tnop r1", Core::Avr5).unwrap_err().line, 2);
+}
+
+#[test]
+fn rejects_overflows() {
+    for (source, message) in [
+        (".org 0xfffffffe\nnop\nnop", "nop: address out of range"),
+        (
+            "ldi r16, 0x7fffffffffffffff+1",
+            "0x7fffffffffffffff+1: expression overflows",
+        ),
+        (
+            "ldi r16, -(-0x7fffffffffffffff-1)",
+            "-(-0x7fffffffffffffff-1): expression overflows",
+        ),
+        (
+            "rjmp -0x7fffffffffffffff-1",
+            "-9223372036854775808: expression overflows",
+        ),
+    ] {
+        let error = assemble(source, Core::Avr5).unwrap_err();
+        assert_eq!(error.message, message, "{}", source);
+    }
+    assert_eq!(
+        assemble(".org 0xfffffffe\nnop", Core::Avr5).unwrap(),
+        [(0xfffffffe, vec![0, 0])]
+    );
+}
+
+#[test]
+fn rejects_instructions_the_core_lacks() {
+    let error = assemble("mul r0, r1", Core::Avr25).unwrap_err();
+    assert_eq!(error.message, "mul: not available on avr25");
+    assert!(assemble("ld r24, Z", Core::Avr1).is_ok());
+    assert!(assemble("ld r24, X", Core::Avr1).is_err());
+}
+
+#[test]
+fn reassembles_golden_images() {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut compared = 0;
+    for entry in fs::read_dir(&golden).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "hex") {
+            continue;
+        }
+        let text = fs::read_to_string(&path).unwrap();
+        let records: Vec<Record> = text
+            .split_whitespace()
+            .map(|record| Record::from_str(record).unwrap())
+            .collect();
+        let image = Image::from_records(&records);
+        for format in ["text", "asm"] {
+            let output = Command::new(env!("CARGO_BIN_EXE_hex"))
+                .args(["--output", format, "--color", "never"])
+                .args(text.split_whitespace())
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", path.display());
+            let source = String::from_utf8(output.stdout).unwrap();
+            let segments = assemble(&source, Core::Avr5).unwrap();
+            let size: usize = segments.iter().map(|(_, bytes)| bytes.len()).sum();
+            let expected: usize = image.segments().iter().map(|range| range.len()).sum();
+            assert_eq!(size, expected, "{}", path.display());
+            for (start, bytes) in segments {
+                for (address, byte) in (start..).zip(bytes) {
+                    assert_eq!(image.byte(address), Some(byte), "{}", path.display());
+                }
+            }
+        }
+        compared += 1;
+    }
+    assert!(compared > 0);
+}
+
+#[test]
+fn reassembles_windows_that_cut_strings() {
+    let source = "start: rjmp start\n.asciz \"hello world\"\n";
+    let segments = assemble(source, Core::Avr5).unwrap();
+    let segments: Vec<(u32, &[u8])> = segments
+        .iter()
+        .map(|(start, bytes)| (*start, &bytes[..]))
+        .collect();
+    let text = record::intel_hex(&segments);
+    for end in 3..=14 {
+        let output = Command::new(env!("CARGO_BIN_EXE_hex"))
+            .args(["--output", "asm", "--color", "never"])
+            .args(["--end", &end.to_string()])
+            .args(text.split_whitespace())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let source = String::from_utf8(output.stdout).unwrap();
+        let assembled = assemble(&source, Core::Avr5).unwrap();
+        assert_eq!(
+            assembled,
+            [(0, segments[0].1[..end].to_vec())],
+            "end {}",
+            end
+        );
+    }
+}
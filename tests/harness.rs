@@ -0,0 +1,59 @@
+//! Firmware routines called through the harness with the calling
+//! convention of avr-gcc.
+
+use hex::{device::Device, harness::Harness, record};
+
+fn harness() -> Harness {
+    let words: [u16; 10] = [
+        // 0x00 uint16_t add(uint16_t a, uint16_t b)
+        0x0f86, // add r24, r22
+        0x1f97, // adc r25, r23
+        0x9508, // ret
+        0x0000, // nop
+        // 0x08 the first argument on the stack, after nine in registers
+        0xb7ed, // in r30, SPL
+        0xb7fe, // in r31, SPH
+        0x8183, // ldd r24, Z+3
+        0x8194, // ldd r25, Z+4
+        0x9508, // ret
+        // 0x12 uint32_t identity(uint32_t a)
+        0x9508, // ret
+    ];
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let text = record::intel_hex(&[(0, &bytes[..])]);
+    Harness::from_hex(Device::Atmega328p.profile(), &text).unwrap()
+}
+
+#[test]
+fn passes_arguments_in_registers() {
+    let mut harness = harness();
+    let sum = harness
+        .call(0x00, &[1000u16.into(), 2345u16.into()])
+        .unwrap();
+    assert_eq!(sum.u16(), 3345);
+    assert_eq!(sum.cycles, 1 + 1 + 4);
+    let wide = harness.call(0x12, &[0x1234_5678u32.into()]).unwrap();
+    assert_eq!(wide.u32(), 0x1234_5678);
+}
+
+#[test]
+fn spills_arguments_to_the_stack() {
+    let mut harness = harness();
+    let sp = harness.simulator.sp;
+    let mut arguments: Vec<_> = (0..9u16).map(Into::into).collect();
+    arguments.push(0xbeefu16.into());
+    let result = harness.call(0x08, &arguments).unwrap();
+    assert_eq!(result.u16(), 0xbeef);
+    assert_eq!(result.registers[8], 8);
+    assert_eq!(harness.simulator.sp, sp);
+}
+
+#[test]
+fn records_coverage_of_calls() {
+    let mut harness = harness();
+    harness.call(0x00, &[1u16.into(), 2u16.into()]).unwrap();
+    harness.call(0x00, &[3u16.into(), 4u16.into()]).unwrap();
+    assert_eq!(harness.coverage.count(0x00), 2);
+    assert_eq!(harness.coverage.count(0x04), 2);
+    assert_eq!(harness.coverage.count(0x06), 0);
+}
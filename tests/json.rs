@@ -0,0 +1,18 @@
+//! Record headers of `--output json`.
+
+use std::process::Command;
+
+#[test]
+fn describes_records_by_their_bytes() {
+    let output = Command::new(env!("CARGO_BIN_EXE_hex"))
+        .args(["--output", "json", ":0300000011223397", ":00000001FF"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let program: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let records = program["records"].as_array().unwrap();
+    assert_eq!(records[0]["size"], 3);
+    assert_eq!(records[0]["data"], serde_json::json!([0x11, 0x22, 0x33]));
+    assert_eq!(records[1]["size"], 0);
+    assert_eq!(records[1]["index"], "End");
+}
@@ -0,0 +1,35 @@
+//! Listings of `--output objdump` compared with the layout of
+//! `avr-objdump -d`, one `.objdump` file next to each `.hex` input in
+//! `tests/golden` without the file and section headers.
+
+use std::{fs, path::Path, process::Command};
+
+#[test]
+fn matches_avr_objdump() {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut compared = 0;
+    for entry in fs::read_dir(&golden).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "hex") {
+            continue;
+        }
+        let Ok(expected) = fs::read_to_string(path.with_extension("objdump")) else {
+            continue;
+        };
+        let records = fs::read_to_string(&path).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_hex"))
+            .args(["--output", "objdump"])
+            .args(records.split_whitespace())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", path.display());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected,
+            "{}",
+            path.display()
+        );
+        compared += 1;
+    }
+    assert!(compared > 0);
+}
@@ -0,0 +1,77 @@
+//! Malformed Intel HEX records are rejected instead of panicking, the cases
+//! the `record` fuzz target in `fuzz/` started from.
+
+use hex::{
+    image::Image,
+    record::{Record, RecordParseError},
+};
+
+#[test]
+fn rejects_malformed_records() {
+    for (record, expected) in [
+        ("", RecordParseError::BeginningOfRecord),
+        ("0200", RecordParseError::BeginningOfRecord),
+        (":", RecordParseError::CalculatingTheSize),
+        (":0", RecordParseError::CalculatingTheSize),
+        (":+1", RecordParseError::CalculatingTheSize),
+        (":02é0", RecordParseError::CalculatingTheAddress),
+        (":0200", RecordParseError::CalculatingTheAddress),
+        (":0200000", RecordParseError::CalculatingIndex),
+        (":02000006", RecordParseError::CalculatingIndex),
+        (":02000000FF", RecordParseError::CalculatingData),
+        (":01000000FF", RecordParseError::CalculatingChecksum),
+        (":02000000FFCF31", RecordParseError::CalculatingChecksum),
+        (":02000000FFCF3000", RecordParseError::CalculatingChecksum),
+    ] {
+        let error = Record::from_str(record).err();
+        assert_eq!(
+            format!("{:?}", error),
+            format!("{:?}", Some(expected)),
+            "{}",
+            record
+        );
+    }
+}
+
+#[test]
+fn loads_odd_records() {
+    // Three bytes at 0x0001: the halves no record fills read as erased.
+    let records = [
+        Record::from_str(":0300010011223396").unwrap(),
+        Record::from_str(":00000001FF").unwrap(),
+    ];
+    assert_eq!(records[0].size, 3);
+    assert_eq!(records[0].bytes(), [0x11, 0x22, 0x33]);
+    let image = Image::from_records(&records);
+    assert_eq!(image.word(0), Some(0x11ff));
+    assert_eq!(image.word(2), Some(0x3322));
+    assert_eq!(image.word(4), None);
+    assert_eq!(image.segments().len(), 1);
+    assert_eq!(image.bounds(), Some((0, 2)));
+}
+
+#[test]
+fn does_not_load_the_pad_of_odd_records() {
+    // One byte at 0x0003 and three at 0x0000, in either order.
+    let (single, triple) = (":01000300AA52", ":0300000011223397");
+    assert_eq!(Record::from_str(single).unwrap().bytes(), [0xaa]);
+    for order in [[single, triple], [triple, single]] {
+        let records: Vec<Record> = order
+            .iter()
+            .map(|record| Record::from_str(record).unwrap())
+            .collect();
+        let image = Image::from_records(&records);
+        assert_eq!(image.word(0), Some(0x2211));
+        assert_eq!(image.word(2), Some(0xaa33));
+        assert_eq!(image.word(4), None);
+    }
+}
+
+#[test]
+fn ignores_words_at_the_top_of_the_address_space() {
+    let records = [
+        Record::from_str(":02000004FFFFFC").unwrap(),
+        Record::from_str(":02FFFE00FFCF33").unwrap(),
+    ];
+    assert!(Image::from_records(&records).segments().is_empty());
+}
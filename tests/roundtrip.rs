@@ -0,0 +1,181 @@
+//! Every program word decoded, encoded back and reassembled from its
+//! listing, and sample encodings of each opcode with their cycles on each
+//! core compared with `tests/golden/instructions.txt`. Set `BLESS` to
+//! rewrite that file after a deliberate change.
+
+use clap::ValueEnum;
+use hex::{
+    assembler::assemble,
+    cycles::cycles,
+    device::Core,
+    instruction::{DecodeError, Instruction},
+    opcodes::{self, OPCODES},
+};
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::Path};
+
+/// Second words tried with every 32-bit first word.
+fn extensions() -> Vec<u16> {
+    let mut words = vec![0, 0xffff, 0x5555, 0xaaaa];
+    words.extend((0..16).map(|bit| 1 << bit));
+    words
+}
+
+/// First words of `lds r0`, `sts r0`, `jmp 0` and `call 0`, tried with
+/// every second word.
+const EXHAUSTIVE: [u16; 4] = [0x9000, 0x9200, 0x940c, 0x940e];
+
+/// Every first word that decodes, with a second word for 32-bit ones.
+fn decodable() -> Vec<Vec<u16>> {
+    (0..=u16::MAX)
+        .filter_map(|word| {
+            let instruction = Instruction::decode(word, Some(0x1234)).ok()?;
+            Some(match instruction.length() {
+                2 => vec![word, 0x1234],
+                _ => vec![word],
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn reencodes_every_word() {
+    let mut decoded = 0;
+    let mut extended = 0;
+    for word in 0..=u16::MAX {
+        let Ok(instruction) = Instruction::decode(word, Some(0)) else {
+            assert_eq!(
+                Instruction::decode(word, None),
+                Err(DecodeError::UnexpectedCommand(word))
+            );
+            continue;
+        };
+        decoded += 1;
+        if instruction.length() == 1 {
+            assert_eq!(instruction.encode(), [word], "{:#06x}", word);
+            assert_eq!(Instruction::decode(word, None), Ok(instruction));
+            continue;
+        }
+        extended += 1;
+        assert_eq!(
+            Instruction::decode(word, None),
+            Err(DecodeError::MissingExtension(word))
+        );
+        let nexts = match EXHAUSTIVE.contains(&word) {
+            true => (0..=u16::MAX).collect(),
+            false => extensions(),
+        };
+        for next in nexts {
+            let instruction = Instruction::decode(word, Some(next)).unwrap();
+            assert_eq!(
+                instruction.encode(),
+                [word, next],
+                "{:#06x} {:#06x}",
+                word,
+                next
+            );
+        }
+    }
+    assert_eq!((decoded, extended), (63982, 192));
+}
+
+#[test]
+fn reassembles_every_listing() {
+    let words = decodable();
+    for overloads in [false, true] {
+        let mut source = String::new();
+        for (index, words) in words.iter().enumerate() {
+            let address = index as u32 * 4;
+            let instruction = Instruction::decode(words[0], words.get(1).copied()).unwrap();
+            let _ = writeln!(
+                source,
+                "{:#x}: {}",
+                address,
+                instruction.display(address, overloads, None)
+            );
+        }
+        let flash: BTreeMap<u32, u8> = assemble(&source, Core::Xmega7)
+            .unwrap()
+            .into_iter()
+            .flat_map(|(start, bytes)| (start..).zip(bytes))
+            .collect();
+        for (index, words) in words.iter().enumerate() {
+            let address = index as u32 * 4;
+            let assembled: Vec<u16> = (0..words.len() as u32)
+                .map(|i| address + i * 2)
+                .map(|address| u16::from_le_bytes([flash[&address], flash[&(address + 1)]]))
+                .collect();
+            assert_eq!(&assembled, words, "overloads {}", overloads);
+        }
+        assert_eq!(
+            flash.len(),
+            words.iter().map(|words| words.len() * 2).sum::<usize>()
+        );
+    }
+}
+
+/// Encodings of each opcode with its fields all clear, all set, and each
+/// single bit of a field set.
+fn samples() -> Vec<Vec<u16>> {
+    let mut samples: Vec<Vec<u16>> = vec![];
+    for opcode in OPCODES {
+        let bits: Vec<char> = opcode.pattern.chars().filter(|&c| c != '_').collect();
+        let base = bits
+            .iter()
+            .fold(0u32, |value, &bit| value << 1 | (bit == '1') as u32);
+        let fields: Vec<u32> = (0..bits.len())
+            .filter(|&i| bits[i].is_ascii_alphabetic())
+            .map(|i| 1 << (bits.len() - 1 - i))
+            .collect();
+        let all = fields.iter().fold(0, |all, bit| all | bit);
+        let values = [base, base | all]
+            .into_iter()
+            .chain(fields.iter().map(|bit| base | bit));
+        for value in values {
+            let words = match bits.len() {
+                32 => vec![(value >> 16) as u16, value as u16],
+                _ => vec![value as u16],
+            };
+            if !samples.contains(&words) {
+                samples.push(words);
+            }
+        }
+    }
+    samples
+}
+
+#[test]
+fn matches_golden_listing() {
+    let cores: Vec<Core> = Core::value_variants().to_vec();
+    let names: Vec<String> = cores
+        .iter()
+        .map(|core| core.to_possible_value().unwrap().get_name().to_string())
+        .collect();
+    let mut text = format!("{:<10}", "words");
+    for name in &names {
+        let _ = write!(text, " {:<6}", name);
+    }
+    text.push_str(" listing\n");
+    for words in samples() {
+        let instruction = Instruction::decode(words[0], words.get(1).copied()).unwrap();
+        let hex: Vec<String> = words.iter().map(|word| format!("{:04x}", word)).collect();
+        let _ = write!(text, "{:<10}", hex.join(" "));
+        for &core in &cores {
+            let (opcode, _) = opcodes::find(&instruction);
+            let column = match opcode.available(core) {
+                true => cycles(&instruction, core, 1).to_string(),
+                false => "-".to_string(),
+            };
+            let _ = write!(text, " {:<6}", column);
+        }
+        let _ = writeln!(text, " {}", instruction.display(0x1000, false, None));
+    }
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/instructions.txt");
+    if env::var_os("BLESS").is_some() {
+        fs::write(&path, &text).unwrap();
+    }
+    let expected = fs::read_to_string(&path).unwrap();
+    for (line, (expected, actual)) in expected.lines().zip(text.lines()).enumerate() {
+        assert_eq!(expected, actual, "line {}", line + 1);
+    }
+    assert_eq!(expected.lines().count(), text.lines().count());
+}